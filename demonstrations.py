@@ -0,0 +1,88 @@
+"""Loads human demonstrations recorded with `--record-demonstrations`."""
+
+import json
+from dataclasses import dataclass
+from typing import Dict, List, Tuple
+
+import numpy as np
+from entity_gym.env import (
+    CategoricalActionMask,
+    CategoricalActionSpace,
+    Entity,
+    Observation,
+    ObsSpace,
+)
+
+
+@dataclass
+class Demonstrations:
+    obs_space: ObsSpace
+    action_space: Dict[str, CategoricalActionSpace]
+    # Observation of every step of every episode with the index of the action
+    # each actor chose.
+    episodes: List[List[Tuple[Observation, Dict[str, np.ndarray]]]]
+
+
+def load(path: str) -> Demonstrations:
+    with open(path) as f:
+        header = json.loads(f.readline())
+        lines = [json.loads(line) for line in f]
+    obs_space = ObsSpace(
+        entities={
+            name: Entity(features=features)
+            for name, features in header["obs_space"].items()
+        }
+    )
+    action_space = {
+        name: CategoricalActionSpace(index_to_label=labels)
+        for name, labels in header["action_space"].items()
+    }
+
+    # Steps of each player in each episode, followed by the final scores
+    steps: Dict[Tuple[int, int], List[dict]] = {}
+    final_scores: Dict[int, List[float]] = {}
+    for line in lines:
+        if "final_scores" in line:
+            final_scores[line["episode"]] = line["final_scores"]
+        else:
+            steps.setdefault((line["episode"], line["player"]), []).append(line)
+
+    episodes = []
+    for (episode, player), player_steps in sorted(steps.items()):
+        # The last episode is incomplete if the game was quit early
+        final = final_scores.get(episode)
+        final_score = final[player] if final else player_steps[-1]["score"]
+        scores = [step["score"] for step in player_steps[1:]] + [final_score]
+        samples = []
+        for i, (step, next_score) in enumerate(zip(player_steps, scores)):
+            observation = Observation(
+                features={
+                    name: np.array(features, dtype=np.float32).reshape(
+                        len(features), len(obs_space.entities[name].features)
+                    )
+                    for name, features in step["features"].items()
+                },
+                actions={
+                    name: CategoricalActionMask(actor_types=[header["actor"]])
+                    for name in action_space
+                },
+                done=i == len(player_steps) - 1,
+                reward=next_score - step["score"],
+            )
+            actions = {
+                name: np.array(indices, dtype=np.int64)
+                for name, indices in step["actions"].items()
+            }
+            samples.append((observation, actions))
+        episodes.append(samples)
+    return Demonstrations(obs_space, action_space, episodes)
+
+
+if __name__ == "__main__":
+    import sys
+
+    demonstrations = load(sys.argv[1])
+    print(
+        f"{len(demonstrations.episodes)} episodes, "
+        f"{sum(len(e) for e in demonstrations.episodes)} steps"
+    )
@@ -30,13 +30,21 @@ fn main() {
         act_interval: args.act_interval,
         versus: false,
         ccd: true,
+        sensor_range: None,
+        render_width: None,
+        history: 0,
+        powerup_interval: 0,
+        loadout: false,
+        kill_rewards: false,
+        ids: false,
+        frames: None,
     };
     let mut env = TrainEnvBuilder::default()
-        .entity::<entity::Fighter>()
-        .entity::<entity::EnemyFighter>()
-        .entity::<entity::Asteroid>()
-        .entity::<entity::Bullet>()
-        .action::<act::FighterAction>()
+        .entity::<entity::classic::Fighter>()
+        .entity::<entity::classic::EnemyFighter>()
+        .entity::<entity::classic::Asteroid>()
+        .entity::<entity::classic::Bullet>()
+        .action::<act::classic::FighterAction>()
         .build(config, train1, args.environments, args.threads, 0)
         .env;
     let steps = args.steps;
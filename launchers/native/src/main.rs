@@ -1,7 +1,10 @@
 use std::io::Cursor;
 
 use bevy::{prelude::*, window::WindowId, winit::WinitWindows};
-use bevy_starfighter::Settings;
+use bevy_starfighter::camera::CameraMode;
+use bevy_starfighter::input::InputConfig;
+use bevy_starfighter::net::NetworkSettings;
+use bevy_starfighter::{Controller, Settings};
 use clap::Parser;
 use winit::window::Icon;
 
@@ -34,14 +37,106 @@ struct Args {
     ccd: bool,
     #[clap(long, value_parser, default_value = "450")]
     respawn_time: u32,
+    /// Frames between power-up spawns, 0 disables power-ups
+    #[clap(long, value_parser, default_value = "0")]
+    powerup_interval: u32,
     #[clap(long, value_parser, default_value = "0.3")]
     opponent_stats_multiplier: f32,
+    /// Score each player by its own kills and assists rather than by all
+    /// destroyed fighters
+    #[clap(long)]
+    kill_rewards: bool,
+    /// Hull points of player 0's fighters
+    #[clap(long, value_parser, default_value = "1.0")]
+    hull: f32,
+    /// Hull points of opponent fighters
+    #[clap(long, value_parser, default_value = "1.0")]
+    opponent_hull: f32,
+    /// Width of the arena, more than 200
+    #[clap(long, value_parser, default_value = "2000")]
+    arena_width: f32,
+    /// Height of the arena, more than 200
+    #[clap(long, value_parser, default_value = "1000")]
+    arena_height: f32,
+    /// Distance up to which fighters observe other entities
+    #[clap(long, value_parser)]
+    sensor_range: Option<f32>,
+    /// Number of past states of each fighter observed by agents, which must
+    /// match the policy's training configuration
+    #[clap(long, value_parser, default_value = "0")]
+    observation_history: usize,
+    /// Let agents observe and choose weapons and abilities, which requires
+    /// policies trained with the same option
+    #[clap(long)]
+    agent_loadout: bool,
+    /// Let agents observe the ID of every entity, which requires policies
+    /// trained with the same option
+    #[clap(long)]
+    observe_ids: bool,
+    /// Only show entities within the sensor range of player 0 on the minimap
+    #[clap(long)]
+    minimap_sensor_range: bool,
+    /// Directory to write frames rendered on the CPU to as PNGs, also works
+    /// with --headless
+    #[clap(long, value_parser)]
+    record_frames: Option<String>,
+    /// Width of recorded frames in pixels
+    #[clap(long, value_parser, default_value = "800")]
+    record_width: u32,
+    /// File to write a replay of the first game to, which can be rendered
+    /// into a video with the `replay-video` binary
+    #[clap(long, value_parser)]
+    record_replay: Option<String>,
+    /// File to append metrics of every game to, as CSV if it ends in `.csv`
+    /// and JSON lines otherwise
+    #[clap(long, value_parser)]
+    metrics_log: Option<String>,
+    /// File to write actions of local humans and their observations to, as a
+    /// dataset for behavior cloning
+    #[clap(long, value_parser)]
+    record_demonstrations: Option<String>,
+    /// Initial camera mode: `fixed`, `follow`, `frame` or `free`
+    #[clap(long, value_parser, default_value = "fixed")]
+    camera: CameraMode,
     #[clap(long)]
     human_player: bool,
     #[clap(long)]
     physics_debug_render: bool,
+    /// Show what agents observe and decide (toggle with F3)
+    #[clap(long)]
+    agent_debug_render: bool,
     #[clap(long)]
     log_diagnostics: bool,
+    /// Path to a RON file with keyboard and gamepad bindings
+    #[clap(long, value_parser)]
+    input_config: Option<String>,
+    /// Binding sets for two humans sharing the keyboard (WASD and arrow
+    /// keys)
+    #[clap(long)]
+    split_keyboard: bool,
+    /// Controller of each player, in order: `human:<binding sets>`,
+    /// `policy:<asset>`, `checkpoint:<path>`, `random`, `scripted`, `idle`,
+    /// `remote` or `bot`
+    #[clap(long = "controller", value_parser)]
+    controllers: Vec<Controller>,
+    /// Player whose humans and bots thrust and turn with continuous values
+    /// rather than discrete actions, can be repeated
+    #[clap(long = "continuous-control", value_parser)]
+    continuous_control: Vec<usize>,
+    /// Address of the relay server for networked games
+    #[clap(long, value_parser)]
+    relay: Option<String>,
+    #[clap(long, value_parser, default_value = "0")]
+    room: u32,
+    /// Number of action intervals between sampling and applying inputs in
+    /// networked games
+    #[clap(long, value_parser, default_value = "3")]
+    input_delay: u32,
+    #[clap(long, value_parser, default_value = "0")]
+    seed: u64,
+    /// Address on which to accept connections from external bots
+    #[clap(long, value_parser)]
+    bot_server: Option<String>,
 }
 
 fn set_window_icon(windows: NonSend<WinitWindows>) {
@@ -62,6 +157,12 @@ fn set_window_icon(windows: NonSend<WinitWindows>) {
     primary.set_window_icon(Some(icon));
 }
 
+/// Reports an invalid configuration and exits without starting the game.
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+
 fn main() {
     let args = Args::parse();
     let mut settings = Settings::default();
@@ -81,12 +182,48 @@ fn main() {
     settings.asteroid_count = args.asteroid_count;
     settings.continuous_collision_detection = args.ccd;
     settings.respawn_time = args.respawn_time;
+    settings.powerup_interval = args.powerup_interval;
     settings.opponent_stats_multiplier = args.opponent_stats_multiplier;
+    settings.hull = args.hull;
+    settings.opponent_hull = args.opponent_hull;
+    settings.arena_size = Vec2::new(args.arena_width, args.arena_height);
+    settings.camera_mode = args.camera;
+    if args.record_frames.is_some() {
+        settings.software_render_width = Some(args.record_width);
+    }
+    settings.record_frames = args.record_frames;
+    settings.record_replay = args.record_replay;
+    settings.metrics_log = args.metrics_log;
+    settings.record_demonstrations = args.record_demonstrations;
+    settings.sensor_range = args.sensor_range;
+    settings.observation_history = args.observation_history;
+    settings.agent_loadout = args.agent_loadout;
+    settings.observe_ids = args.observe_ids;
+    settings.kill_rewards = args.kill_rewards;
+    settings.minimap_sensor_range = args.minimap_sensor_range;
     settings.human_player = args.human_player;
     settings.opponent_policy = args.agent_asset;
     settings.physics_debug_render = args.physics_debug_render;
+    settings.agent_debug_render = args.agent_debug_render;
     settings.log_diagnostics = args.log_diagnostics;
-    let mut app = bevy_starfighter::app(settings, vec![]);
+    settings.controllers = args.controllers;
+    settings.continuous_control = args.continuous_control;
+    settings.seed = args.seed;
+    settings.network = args.relay.map(|relay| NetworkSettings {
+        relay,
+        room: args.room,
+        input_delay: args.input_delay,
+    });
+    settings.bot_server = args.bot_server;
+    if args.split_keyboard {
+        settings.input = InputConfig::split_keyboard();
+    }
+    if let Some(path) = &args.input_config {
+        settings.input =
+            InputConfig::load(path).unwrap_or_else(|e| exit_with_error(e));
+    }
+    let mut app = bevy_starfighter::try_app(settings, vec![])
+        .unwrap_or_else(|e| exit_with_error(e));
 
     info!("Starting launcher: Native");
     if !args.headless {
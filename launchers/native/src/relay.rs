@@ -0,0 +1,208 @@
+//! Relay server for networked games.
+//!
+//! Forwards every packet to all other clients in the same room. Native
+//! clients connect over UDP, web clients over WebSockets.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+use bevy_starfighter::net::packet_room;
+use clap::Parser;
+use tungstenite::handshake::server::NoCallback;
+use tungstenite::handshake::MidHandshake;
+use tungstenite::{HandshakeError, Message, ServerHandshake, WebSocket};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Address of the UDP socket for native clients
+    #[clap(long, value_parser, default_value = "0.0.0.0:4000")]
+    udp: String,
+    /// Address of the WebSocket listener for web clients
+    #[clap(long, value_parser, default_value = "0.0.0.0:4001")]
+    websocket: String,
+    /// Seconds after which silent UDP clients and unfinished WebSocket
+    /// handshakes are dropped
+    #[clap(long, value_parser, default_value = "30")]
+    timeout: u64,
+}
+
+enum Client {
+    Udp(SocketAddr),
+    WebSocket(Box<WebSocket<TcpStream>>),
+}
+
+type Handshake = MidHandshake<ServerHandshake<TcpStream, NoCallback>>;
+
+struct Peer {
+    client: Client,
+    room: Option<u32>,
+    last_seen: Instant,
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let udp = UdpSocket::bind(&args.udp)?;
+    udp.set_nonblocking(true)?;
+    let listener = TcpListener::bind(&args.websocket)?;
+    listener.set_nonblocking(true)?;
+    println!(
+        "Relaying UDP on {} and WebSockets on {}",
+        args.udp, args.websocket
+    );
+
+    let timeout = Duration::from_secs(args.timeout);
+    let mut peers: Vec<Peer> = vec![];
+    let mut udp_peers: HashMap<SocketAddr, usize> = HashMap::new();
+    let mut pending: Vec<(SocketAddr, Instant, Handshake)> = vec![];
+    let mut buf = [0; 1024];
+    loop {
+        let mut received: Vec<(usize, Vec<u8>)> = vec![];
+
+        // Handshakes are resumed on every iteration so that a slow client
+        // can't stall the relay
+        let mut handshakes: Vec<_> = pending
+            .drain(..)
+            .map(|(addr, started, handshake)| {
+                (addr, started, handshake.handshake())
+            })
+            .collect();
+        match listener.accept() {
+            Ok((stream, addr)) => match stream.set_nonblocking(true) {
+                Ok(()) => handshakes.push((
+                    addr,
+                    Instant::now(),
+                    tungstenite::accept(stream),
+                )),
+                Err(e) => {
+                    eprintln!("Dropping WebSocket client {}: {}", addr, e)
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => eprintln!("Failed to accept WebSocket client: {}", e),
+        }
+        for (addr, started, handshake) in handshakes {
+            match handshake {
+                Ok(ws) => {
+                    println!("WebSocket client connected: {}", addr);
+                    peers.push(Peer {
+                        client: Client::WebSocket(Box::new(ws)),
+                        room: None,
+                        last_seen: Instant::now(),
+                    });
+                }
+                Err(HandshakeError::Interrupted(handshake))
+                    if started.elapsed() <= timeout =>
+                {
+                    pending.push((addr, started, handshake))
+                }
+                Err(HandshakeError::Interrupted(_)) => {
+                    eprintln!("Handshake with {} timed out", addr)
+                }
+                Err(HandshakeError::Failure(e)) => {
+                    eprintln!("Handshake with {} failed: {}", addr, e)
+                }
+            }
+        }
+
+        loop {
+            match udp.recv_from(&mut buf) {
+                Ok((len, addr)) => {
+                    let index = *udp_peers.entry(addr).or_insert_with(|| {
+                        println!("UDP client connected: {}", addr);
+                        peers.push(Peer {
+                            client: Client::Udp(addr),
+                            room: None,
+                            last_seen: Instant::now(),
+                        });
+                        peers.len() - 1
+                    });
+                    received.push((index, buf[..len].to_vec()));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => eprintln!("Failed to receive packet: {}", e),
+            }
+        }
+
+        let mut closed = vec![];
+        for (index, peer) in peers.iter_mut().enumerate() {
+            if let Client::WebSocket(ws) = &mut peer.client {
+                loop {
+                    match ws.read_message() {
+                        Ok(Message::Binary(packet)) => {
+                            received.push((index, packet))
+                        }
+                        Ok(_) => {}
+                        Err(tungstenite::Error::Io(e))
+                            if e.kind() == ErrorKind::WouldBlock =>
+                        {
+                            break
+                        }
+                        Err(_) => {
+                            closed.push(index);
+                            break;
+                        }
+                    }
+                }
+            } else if peer.last_seen.elapsed() > timeout {
+                closed.push(index);
+            }
+        }
+
+        for (sender, packet) in received {
+            let room = match packet_room(&packet) {
+                Some(room) => room,
+                None => continue,
+            };
+            peers[sender].room = Some(room);
+            peers[sender].last_seen = Instant::now();
+            for (index, peer) in peers.iter_mut().enumerate() {
+                if index == sender || peer.room != Some(room) {
+                    continue;
+                }
+                match &mut peer.client {
+                    Client::Udp(addr) => {
+                        if let Err(e) = udp.send_to(&packet, *addr) {
+                            eprintln!("Failed to send to {}: {}", addr, e);
+                        }
+                    }
+                    Client::WebSocket(ws) => {
+                        match ws.write_message(Message::Binary(packet.clone()))
+                        {
+                            Ok(()) => {}
+                            // Queued, will be flushed on the next write
+                            Err(tungstenite::Error::Io(e))
+                                if e.kind() == ErrorKind::WouldBlock => {}
+                            Err(_) => closed.push(index),
+                        }
+                    }
+                }
+            }
+        }
+
+        if !closed.is_empty() {
+            closed.sort_unstable();
+            closed.dedup();
+            for index in closed.into_iter().rev() {
+                let peer = peers.remove(index);
+                if let Client::Udp(addr) = peer.client {
+                    println!("UDP client timed out: {}", addr);
+                } else {
+                    println!("WebSocket client disconnected");
+                }
+            }
+            udp_peers = peers
+                .iter()
+                .enumerate()
+                .filter_map(|(i, peer)| match peer.client {
+                    Client::Udp(addr) => Some((addr, i)),
+                    Client::WebSocket(_) => None,
+                })
+                .collect();
+        }
+
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,40 @@
+//! Renders a replay recorded with `--record-replay` into a video.
+//!
+//! The game is re-simulated headless from the replay's seed, settings and
+//! actions, and drawn with the software renderer. Writes an animated GIF if
+//! the output ends in `.gif`, otherwise a directory of numbered PNGs.
+
+use bevy_starfighter::replay::{export_video, Replay, VideoOptions};
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Replay file written with `--record-replay`
+    #[clap(value_parser)]
+    replay: String,
+    /// GIF file or directory for PNG frames
+    #[clap(long, value_parser, default_value = "replay.gif")]
+    output: String,
+    /// Width of the video in pixels
+    #[clap(long, value_parser, default_value = "800")]
+    width: u32,
+    /// Number of simulated frames per frame of video
+    #[clap(long, value_parser, default_value = "3")]
+    frame_interval: u32,
+}
+
+fn main() -> Result<(), String> {
+    let args = Args::parse();
+    let replay = Replay::load(&args.replay)?;
+    let frames = export_video(
+        &replay,
+        &VideoOptions {
+            output: args.output.clone(),
+            width: args.width,
+            frame_interval: args.frame_interval,
+        },
+    )?;
+    println!("Wrote {} frames to {}", frames, args.output);
+    Ok(())
+}
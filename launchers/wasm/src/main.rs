@@ -3,7 +3,8 @@ use stylist::yew::styled_component;
 use stylist::{css, global_style};
 use yew::prelude::*;
 
-use bevy_starfighter::LAUNCHER_TITLE;
+use bevy_starfighter::net::NetworkSettings;
+use bevy_starfighter::{Controller, LAUNCHER_TITLE};
 
 fn set_window_title(title: &str) {
     web_sys::window()
@@ -12,6 +13,26 @@ fn set_window_title(title: &str) {
         .set_title(title);
 }
 
+/// Returns the value of a query parameter of the page URL.
+fn query_param(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Reports an error that keeps the game from starting, before Bevy has set
+/// up logging.
+fn show_error(message: &str) {
+    web_sys::console::error_1(&message.into());
+    if let Some(window) = web_sys::window() {
+        let _ = window.alert_with_message(message);
+    }
+}
+
 fn set_global_css() {
     global_style! {
         r#"
@@ -54,16 +75,57 @@ fn main() {
     // Mount the DOM
     yew::start_app::<Root>();
     // Start the Bevy App
-    let settings = bevy_starfighter::Settings {
-        players: 2,
+    let mut settings = bevy_starfighter::Settings {
         ai_action_interval: Some(12),
         continuous_collision_detection: true,
-        opponent_policy: Some("230111-134322-versus-reldir-1024m".to_string()),
         disable_bloom: true,
-        human_player: true,
+        controllers: vec![
+            Controller::Human(vec![0]),
+            Controller::Policy("230111-134322-versus-reldir-1024m".to_string()),
+        ],
         ..Default::default()
     };
-    let mut app = bevy_starfighter::app(settings, vec![]);
+    // Networked game against another human, e.g.
+    // `?relay=ws://localhost:4001&room=7&player=1`
+    let mut player_error = None;
+    if let Some(relay) = query_param("relay") {
+        settings.controllers = vec![Controller::Remote, Controller::Remote];
+        let player_count = settings.controllers.len();
+        let player = match query_param("player") {
+            Some(p) => match p.parse::<usize>() {
+                Ok(player) if player < player_count => player,
+                _ => {
+                    player_error = Some(format!(
+                        "Invalid player {:?}, expected 0 to {}, playing as \
+                         player 0",
+                        p,
+                        player_count - 1
+                    ));
+                    0
+                }
+            },
+            None => 0,
+        };
+        settings.controllers[player] = Controller::Human(vec![0]);
+        settings.network = Some(NetworkSettings {
+            relay,
+            room: query_param("room")
+                .and_then(|r| r.parse().ok())
+                .unwrap_or(0),
+            ..Default::default()
+        });
+    }
+    let mut app = match bevy_starfighter::try_app(settings, vec![]) {
+        Ok(app) => app,
+        Err(e) => {
+            show_error(&format!("Unable to start the game: {}", e));
+            return;
+        }
+    };
+    // Only reported now that the app has set up logging
+    if let Some(message) = player_error {
+        error!("{}", message);
+    }
     info!("Starting launcher: WASM");
     app.run();
 }
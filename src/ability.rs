@@ -0,0 +1,277 @@
+//! Manually triggered abilities selected with `act::FighterAction.ability`.
+//!
+//! Every ability costs energy, which slowly regenerates, and has its own
+//! cooldown:
+//! - the directional shield blocks projectiles hitting the front of the
+//!   fighter for a short time,
+//! - the afterburner boost raises thrust and top speed for a short time,
+//! - blink teleports the fighter a short distance forward,
+//! - the EMP destroys all enemy projectiles within a radius.
+
+use bevy::prelude::*;
+
+use crate::{act, Bullet, CollisionQueue, Settings};
+
+const MAX_ENERGY: f32 = 100.0;
+/// Energy regenerated per frame.
+const ENERGY_REGEN: f32 = 0.2;
+const SHIELD_DURATION: i32 = 90;
+/// Cosine of the largest angle from the nose at which the directional shield
+/// blocks projectiles.
+const SHIELD_ARC_COS: f32 = 0.5;
+const BOOST_DURATION: i32 = 60;
+const BOOST_THRUST_MULTIPLIER: f32 = 2.0;
+const BOOST_SPEED_MULTIPLIER: f32 = 1.8;
+const BLINK_DISTANCE: f32 = 250.0;
+const EMP_RADIUS: f32 = 300.0;
+
+/// Energy cost and cooldown of each ability, indexed by `slot`.
+const COSTS: [f32; 4] = [30.0, 20.0, 35.0, 50.0];
+const COOLDOWNS: [i32; 4] = [300, 180, 240, 600];
+
+fn slot(ability: act::Ability) -> Option<usize> {
+    match ability {
+        act::Ability::None => None,
+        act::Ability::Shield => Some(0),
+        act::Ability::Boost => Some(1),
+        act::Ability::Blink => Some(2),
+        act::Ability::Emp => Some(3),
+    }
+}
+
+/// Energy, cooldowns and active effects of a fighter's abilities.
+#[derive(Component)]
+pub(crate) struct Abilities {
+    energy: f32,
+    cooldowns: [i32; 4],
+    shield_time: i32,
+    boost_time: i32,
+}
+
+/// Child entity showing the directional shield while it is raised.
+#[derive(Component)]
+pub(crate) struct FrontShield;
+
+/// Sent when a fighter triggers an EMP.
+pub(crate) struct EmpBlast {
+    pub(crate) position: Vec2,
+    pub(crate) player_id: usize,
+}
+
+impl Abilities {
+    pub(crate) fn new() -> Abilities {
+        Abilities {
+            energy: MAX_ENERGY,
+            cooldowns: [0; 4],
+            shield_time: 0,
+            boost_time: 0,
+        }
+    }
+
+    /// Starts `ability` if it is off cooldown and there is enough energy,
+    /// returns whether it was triggered.
+    pub(crate) fn try_activate(&mut self, ability: act::Ability) -> bool {
+        let slot = match slot(ability) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        if self.cooldowns[slot] > 0 || self.energy < COSTS[slot] {
+            return false;
+        }
+        self.energy -= COSTS[slot];
+        self.cooldowns[slot] = COOLDOWNS[slot];
+        match ability {
+            act::Ability::Shield => self.shield_time = SHIELD_DURATION,
+            act::Ability::Boost => self.boost_time = BOOST_DURATION,
+            _ => {}
+        }
+        true
+    }
+
+    /// Fraction of the maximum energy that is available.
+    pub(crate) fn energy(&self) -> f32 {
+        self.energy / MAX_ENERGY
+    }
+
+    /// Fraction of the cooldown of `ability` that remains.
+    pub(crate) fn cooldown(&self, ability: act::Ability) -> f32 {
+        slot(ability).map_or(0.0, |slot| {
+            self.cooldowns[slot] as f32 / COOLDOWNS[slot] as f32
+        })
+    }
+
+    pub(crate) fn shield_active(&self) -> bool {
+        self.shield_time > 0
+    }
+
+    pub(crate) fn boost_active(&self) -> bool {
+        self.boost_time > 0
+    }
+
+    pub(crate) fn thrust_multiplier(&self) -> f32 {
+        if self.boost_active() {
+            BOOST_THRUST_MULTIPLIER
+        } else {
+            1.0
+        }
+    }
+
+    pub(crate) fn speed_multiplier(&self) -> f32 {
+        if self.boost_active() {
+            BOOST_SPEED_MULTIPLIER
+        } else {
+            1.0
+        }
+    }
+
+    /// Whether the directional shield of a fighter at `fighter` stops a
+    /// projectile hitting it at `projectile`.
+    pub(crate) fn blocks(&self, fighter: &Transform, projectile: Vec3) -> bool {
+        let (direction_x, direction_y) = crate::transform_to_direction(fighter);
+        let offset = (projectile - fighter.translation).truncate();
+        self.shield_active()
+            && offset != Vec2::ZERO
+            && offset.normalize().dot(Vec2::new(direction_x, direction_y))
+                >= SHIELD_ARC_COS
+    }
+}
+
+/// Position after blinking forward from `position`, kept within an arena
+/// that extends `bounds` from the origin in each direction.
+pub(crate) fn blink_destination(
+    position: Vec3,
+    direction: Vec2,
+    bounds: Vec2,
+) -> Vec3 {
+    let destination = position + (direction * BLINK_DISTANCE).extend(0.0);
+    destination
+        .truncate()
+        .clamp(-bounds, bounds)
+        .extend(position.z)
+}
+
+pub(crate) fn tick_abilities(
+    settings: Res<Settings>,
+    mut abilities: Query<(&mut Abilities, &Children)>,
+    mut front_shields: Query<&mut Visibility, With<FrontShield>>,
+) {
+    let frames = settings.frameskip as i32;
+    for (mut abilities, children) in abilities.iter_mut() {
+        abilities.energy = (abilities.energy
+            + ENERGY_REGEN * settings.frameskip as f32)
+            .min(MAX_ENERGY);
+        for cooldown in abilities.cooldowns.iter_mut() {
+            *cooldown = (*cooldown - frames).max(0);
+        }
+        abilities.shield_time = (abilities.shield_time - frames).max(0);
+        abilities.boost_time = (abilities.boost_time - frames).max(0);
+        let shield_active = abilities.shield_active();
+        for child in children.iter() {
+            if let Ok(mut visibility) = front_shields.get_mut(*child) {
+                visibility.is_visible = shield_active;
+            }
+        }
+    }
+}
+
+pub(crate) fn emp(
+    mut cmd: Commands,
+    mut blasts: EventReader<EmpBlast>,
+    mut queue: ResMut<CollisionQueue>,
+    bullets: Query<(Entity, &Bullet, &Transform)>,
+) {
+    for blast in blasts.iter() {
+        for (entity, bullet, transform) in bullets.iter() {
+            if bullet.player_id != blast.player_id
+                && bullet.weapon != act::Weapon::Laser
+                && transform.translation.truncate().distance(blast.position)
+                    <= EMP_RADIUS
+            {
+                queue.despawn(&mut cmd, entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activation_needs_energy_and_no_cooldown() {
+        let mut abilities = Abilities::new();
+        assert!(!abilities.try_activate(act::Ability::None));
+        assert!(abilities.try_activate(act::Ability::Shield));
+        assert!(abilities.shield_active());
+        assert_eq!(abilities.cooldown(act::Ability::Shield), 1.0);
+        assert!(!abilities.try_activate(act::Ability::Shield));
+        assert!(abilities.try_activate(act::Ability::Emp));
+        // 20 energy left, not enough for a blink
+        assert!(!abilities.try_activate(act::Ability::Blink));
+        assert_eq!(abilities.cooldown(act::Ability::Blink), 0.0);
+        assert!(abilities.try_activate(act::Ability::Boost));
+        assert!(abilities.boost_active());
+        assert_eq!(abilities.energy(), 0.0);
+
+        abilities.energy = MAX_ENERGY;
+        assert!(!abilities.try_activate(act::Ability::Shield));
+        abilities.cooldowns = [0; 4];
+        assert!(abilities.try_activate(act::Ability::Shield));
+    }
+
+    #[test]
+    fn shield_blocks_projectiles_in_front() {
+        let mut abilities = Abilities::new();
+        let fighter = Transform::from_xyz(100.0, 100.0, 0.0);
+        let ahead = Vec3::new(100.0, 150.0, 0.0);
+        assert!(!abilities.blocks(&fighter, ahead));
+        abilities.try_activate(act::Ability::Shield);
+
+        assert!(abilities.blocks(&fighter, ahead));
+        assert!(abilities.blocks(&fighter, Vec3::new(125.0, 150.0, 0.0)));
+        assert!(!abilities.blocks(&fighter, Vec3::new(150.0, 100.0, 0.0)));
+        assert!(!abilities.blocks(&fighter, Vec3::new(100.0, 50.0, 0.0)));
+        assert!(!abilities.blocks(&fighter, fighter.translation));
+        // Turned left, the fighter faces -x
+        let turned = fighter
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!(!abilities.blocks(&turned, ahead));
+        assert!(abilities.blocks(&turned, Vec3::new(50.0, 100.0, 0.0)));
+    }
+
+    #[test]
+    fn emp_destroys_nearby_enemy_projectiles() {
+        let mut app = App::new();
+        app.init_resource::<CollisionQueue>()
+            .add_event::<EmpBlast>()
+            .add_system(emp);
+        let mut spawn = |player_id, weapon, x| {
+            app.world
+                .spawn((
+                    Bullet {
+                        remaining_lifetime: 24,
+                        player_id,
+                        weapon,
+                        damage: 1.0,
+                    },
+                    Transform::from_xyz(x, 0.0, 0.0),
+                ))
+                .id()
+        };
+        let enemy = spawn(2, act::Weapon::Gun, 100.0);
+        let distant = spawn(2, act::Weapon::Gun, EMP_RADIUS + 1.0);
+        let own = spawn(1, act::Weapon::Gun, 100.0);
+        let beam = spawn(2, act::Weapon::Laser, 100.0);
+        app.world.send_event(EmpBlast {
+            position: Vec2::ZERO,
+            player_id: 1,
+        });
+
+        app.update();
+
+        assert!(app.world.get_entity(enemy).is_none());
+        for remaining in [distant, own, beam] {
+            assert!(app.world.get_entity(remaining).is_some());
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Overlay visualizing what agents observe and decide.
+//!
+//! Toggled with F3 or enabled from the start with
+//! `Settings.agent_debug_render`, much like `Settings.physics_debug_render`
+//! shows colliders. For every player controlled by an agent, the overlay
+//! connects the fighter whose direction defines the ego frame to each entity
+//! in its last observation, draws the axes of that frame (green forward, blue
+//! left, as used by the `rel*` features), and labels each of the player's
+//! fighters with the last chosen `FighterAction`. The `Agent` API only returns
+//! sampled actions, so action probabilities and value estimates are not
+//! available to show.
+
+use std::collections::HashMap;
+
+use bevy::prelude::shape::Quad;
+use bevy::prelude::*;
+
+use crate::{act, Fighter, Observation, Settings, FIGHTER_COLORS};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+const FONT: &str = "fonts/FiraSans-Bold.ttf";
+const FONT_SIZE: f32 = 18.0;
+const OVERLAY_Z: f32 = 5.0;
+const AXIS_LENGTH: f32 = 150.0;
+const LINE_WIDTH: f32 = 2.0;
+const MARKER_SIZE: f32 = 10.0;
+/// Offset of action labels from the center of their fighter.
+const LABEL_OFFSET: Vec2 = Vec2::new(0.0, 60.0);
+
+/// Last observations and actions of agents, recorded by `ai` while enabled.
+#[derive(Resource, Default)]
+pub(crate) struct AgentDebug {
+    pub(crate) enabled: bool,
+    pub(crate) observations: HashMap<usize, Observation>,
+    pub(crate) actions: HashMap<Entity, act::FighterAction>,
+}
+
+pub(crate) struct AgentDebugPlugin;
+
+impl Plugin for AgentDebugPlugin {
+    fn build(&self, app: &mut App) {
+        let enabled = app.world.resource::<Settings>().agent_debug_render;
+        app.insert_resource(AgentDebug {
+            enabled,
+            ..default()
+        })
+        .add_startup_system(setup_overlay)
+        .add_system(toggle_overlay)
+        .add_system(draw_overlay.after(crate::ai).after(toggle_overlay));
+    }
+}
+
+/// Rectangle of the overlay, reused across frames.
+#[derive(Component)]
+struct OverlayShape;
+
+/// Action label of the overlay, reused across frames.
+#[derive(Component)]
+struct OverlayLabel;
+
+type Shapes = (With<OverlayShape>, Without<OverlayLabel>);
+type Labels = (With<OverlayLabel>, Without<OverlayShape>);
+type NotOverlay = (Without<OverlayShape>, Without<OverlayLabel>);
+
+#[derive(Resource)]
+struct OverlayAssets {
+    mesh: Handle<Mesh>,
+    font: Handle<Font>,
+    /// Translucent color of each player for lines to observed entities.
+    players: Vec<Handle<ColorMaterial>>,
+    forward: Handle<ColorMaterial>,
+    left: Handle<ColorMaterial>,
+}
+
+fn setup_overlay(
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut material = |color: Color| materials.add(ColorMaterial::from(color));
+    cmd.insert_resource(OverlayAssets {
+        mesh: meshes.add(Quad::new(Vec2::ONE).into()),
+        font: asset_server.load(FONT),
+        players: FIGHTER_COLORS
+            .iter()
+            .map(|color| {
+                let mut color = *color;
+                material(*color.set_a(0.35))
+            })
+            .collect(),
+        forward: material(Color::rgb(0.2, 1.0, 0.2)),
+        left: material(Color::rgb(0.3, 0.5, 1.0)),
+    });
+}
+
+fn toggle_overlay(keys: Res<Input<KeyCode>>, mut debug: ResMut<AgentDebug>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        debug.enabled = !debug.enabled;
+        if !debug.enabled {
+            debug.observations.clear();
+            debug.actions.clear();
+        }
+    }
+}
+
+/// Transform of a rectangle of the given `width` from `from` to `to`.
+fn line(from: Vec2, to: Vec2, width: f32) -> Transform {
+    let offset = to - from;
+    Transform::from_translation(((from + to) / 2.0).extend(OVERLAY_Z))
+        .with_rotation(Quat::from_rotation_z(offset.y.atan2(offset.x)))
+        .with_scale(Vec3::new(offset.length(), width, 1.0))
+}
+
+/// Positions the shapes and labels of the overlay, reusing those of previous
+/// frames and hiding those left over.
+#[allow(clippy::too_many_arguments)]
+fn draw_overlay(
+    mut cmd: Commands,
+    mut debug: ResMut<AgentDebug>,
+    assets: Res<OverlayAssets>,
+    mut shapes: Query<
+        (&mut Transform, &mut Handle<ColorMaterial>, &mut Visibility),
+        Shapes,
+    >,
+    mut labels: Query<(&mut Text, &mut Transform, &mut Visibility), Labels>,
+    fighters: Query<(&Fighter, &Transform), NotOverlay>,
+    mut shape_pool: Local<Vec<Entity>>,
+    mut label_pool: Local<Vec<Entity>>,
+) {
+    // Actions of destroyed fighters would otherwise pile up
+    debug
+        .actions
+        .retain(|fighter, _| fighters.contains(*fighter));
+    let mut lines = vec![];
+    let mut texts = vec![];
+    // Both are empty while the overlay is disabled, see `toggle_overlay`
+    for (player, observation) in debug.observations.iter() {
+        // The last actor defines the ego frame, see `ObservationQueries`
+        let ego = match observation.fighters.last() {
+            Some(ego) => ego,
+            None => continue,
+        };
+        let origin = Vec2::new(ego.x, ego.y);
+        let forward = Vec2::new(ego.direction_x, ego.direction_y);
+        let material = &assets.players[player % assets.players.len()];
+        let observed = observation
+            .enemy_fighters
+            .iter()
+            .map(|e| Vec2::new(e.x, e.y))
+            .chain(observation.asteroids.iter().map(|e| Vec2::new(e.x, e.y)))
+            .chain(observation.bullets.iter().map(|e| Vec2::new(e.x, e.y)))
+            .chain(observation.powerups.iter().map(|e| Vec2::new(e.x, e.y)));
+        for position in observed {
+            lines.push((material, line(origin, position, LINE_WIDTH)));
+            let corner = position - Vec2::X * MARKER_SIZE / 2.0;
+            lines.push((
+                material,
+                line(corner, corner + Vec2::X * MARKER_SIZE, MARKER_SIZE),
+            ));
+        }
+        lines.push((
+            &assets.forward,
+            line(origin, origin + forward * AXIS_LENGTH, LINE_WIDTH),
+        ));
+        lines.push((
+            &assets.left,
+            line(origin, origin + forward.perp() * AXIS_LENGTH, LINE_WIDTH),
+        ));
+    }
+    for (fighter, action) in debug.actions.iter() {
+        let (fighter, transform) = match fighters.get(*fighter) {
+            Ok(fighter) => fighter,
+            Err(_) => continue,
+        };
+        let label = format!(
+            "{:?} {:?} {:?}\n{:?} {:?}",
+            action.thrust,
+            action.turn,
+            action.shoot,
+            action.weapon,
+            action.ability,
+        );
+        let text = Text::from_section(
+            label,
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: FONT_SIZE,
+                color: FIGHTER_COLORS[fighter.player_id % FIGHTER_COLORS.len()],
+            },
+        )
+        .with_alignment(TextAlignment::CENTER);
+        let transform = Transform::from_translation(
+            (transform.translation.truncate() + LABEL_OFFSET).extend(OVERLAY_Z),
+        );
+        texts.push((text, transform));
+    }
+
+    let count = lines.len();
+    for (k, (material, transform)) in lines.into_iter().enumerate() {
+        if let Some((mut shape_transform, mut shape_material, mut visibility)) =
+            shape_pool
+                .get(k)
+                .and_then(|shape| shapes.get_mut(*shape).ok())
+        {
+            *shape_transform = transform;
+            *shape_material = material.clone();
+            visibility.is_visible = true;
+            continue;
+        }
+        let shape = cmd
+            .spawn(ColorMesh2dBundle {
+                mesh: assets.mesh.clone().into(),
+                material: material.clone(),
+                transform,
+                ..default()
+            })
+            .insert(OverlayShape)
+            .id();
+        shape_pool.push(shape);
+    }
+    for shape in shape_pool.iter().skip(count) {
+        if let Ok((_, _, mut visibility)) = shapes.get_mut(*shape) {
+            visibility.is_visible = false;
+        }
+    }
+
+    let count = texts.len();
+    for (k, (text, transform)) in texts.into_iter().enumerate() {
+        if let Some((mut label_text, mut label_transform, mut visibility)) =
+            label_pool
+                .get(k)
+                .and_then(|label| labels.get_mut(*label).ok())
+        {
+            *label_text = text;
+            *label_transform = transform;
+            visibility.is_visible = true;
+            continue;
+        }
+        let label = cmd
+            .spawn(Text2dBundle {
+                text,
+                transform,
+                ..default()
+            })
+            .insert(OverlayLabel)
+            .id();
+        label_pool.push(label);
+    }
+    for label in label_pool.iter().skip(count) {
+        if let Ok((_, _, mut visibility)) = labels.get_mut(*label) {
+            visibility.is_visible = false;
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Camera controller with fixed, follow, framing and free spectator modes.
+//!
+//! C cycles through the modes and Tab through the fighters to follow. In the
+//! free mode, the mouse wheel or +/- zooms and dragging with the right mouse
+//! button pans. In every mode the camera is kept within the arena, and zooming
+//! out stops once the whole arena is visible.
+
+use std::str::FromStr;
+
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::{Fighter, Settings};
+
+const CYCLE_MODE_KEY: KeyCode = KeyCode::C;
+const CYCLE_TARGET_KEY: KeyCode = KeyCode::Tab;
+/// Fraction of the arena-fitting zoom used when following a fighter.
+const FOLLOW_ZOOM: f32 = 0.5;
+/// Space kept around the fighters when framing them.
+const FRAME_MARGIN: f32 = 300.0;
+/// Smallest fraction of the arena-fitting zoom when framing or zooming in.
+const MIN_ZOOM: f32 = 0.25;
+/// Rate at which the camera approaches its goal, per second.
+const SMOOTHING: f32 = 5.0;
+/// Zoom change per line scrolled with the mouse wheel.
+const ZOOM_STEP: f32 = 1.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Shows the whole arena.
+    Fixed,
+    /// Follows a single fighter, selected with Tab.
+    Follow,
+    /// Zooms and pans to keep all fighters in view.
+    Frame,
+    /// Panned and zoomed with the mouse.
+    Free,
+}
+
+impl CameraMode {
+    const ALL: [CameraMode; 4] = [
+        CameraMode::Fixed,
+        CameraMode::Follow,
+        CameraMode::Frame,
+        CameraMode::Free,
+    ];
+
+    fn next(self) -> CameraMode {
+        let index = CameraMode::ALL.iter().position(|m| *m == self).unwrap();
+        CameraMode::ALL[(index + 1) % CameraMode::ALL.len()]
+    }
+}
+
+impl FromStr for CameraMode {
+    type Err = String;
+
+    /// Parses `fixed`, `follow`, `frame` or `free`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(CameraMode::Fixed),
+            "follow" => Ok(CameraMode::Follow),
+            "frame" => Ok(CameraMode::Frame),
+            "free" => Ok(CameraMode::Free),
+            _ => Err(format!("invalid camera mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct CameraController {
+    mode: CameraMode,
+    /// Fighter followed in `CameraMode::Follow`.
+    target: Option<Entity>,
+    /// Center of the view in `CameraMode::Free`.
+    position: Vec2,
+    /// Zoom in `CameraMode::Free`, relative to the arena-fitting zoom.
+    zoom: f32,
+}
+
+pub(crate) struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        let mode = app.world.resource::<Settings>().camera_mode;
+        app.insert_resource(CameraController {
+            mode,
+            target: None,
+            position: Vec2::ZERO,
+            zoom: 1.0,
+        })
+        .add_system(control_camera)
+        .add_system(move_camera.after(control_camera));
+    }
+}
+
+fn control_camera(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut motion: EventReader<MouseMotion>,
+    mut controller: ResMut<CameraController>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    fighters: Query<(Entity, &Fighter)>,
+) {
+    if keys.just_pressed(CYCLE_MODE_KEY) {
+        controller.mode = controller.mode.next();
+        if controller.mode == CameraMode::Free {
+            // Start panning from wherever the camera currently is
+            if let Ok((transform, _)) = cameras.get_single() {
+                controller.position = transform.translation.truncate();
+            }
+        }
+    }
+    let mut targets = fighters
+        .iter()
+        .map(|(entity, fighter)| (fighter.player_id, entity))
+        .collect::<Vec<_>>();
+    targets.sort();
+    let current = controller
+        .target
+        .and_then(|target| targets.iter().position(|(_, e)| *e == target));
+    if keys.just_pressed(CYCLE_TARGET_KEY) {
+        controller.mode = CameraMode::Follow;
+        let next = current.map_or(0, |i| i + 1);
+        controller.target =
+            targets.get(next % targets.len().max(1)).map(|t| t.1);
+    } else if current.is_none() {
+        // Fall back to the first fighter once the target is destroyed
+        controller.target = targets.first().map(|t| t.1);
+    }
+
+    let mut zoom = 1.0;
+    for event in wheel.iter() {
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 100.0,
+        };
+        zoom *= ZOOM_STEP.powf(-lines);
+    }
+    if keys.just_pressed(KeyCode::Equals) {
+        zoom /= ZOOM_STEP * ZOOM_STEP;
+    }
+    if keys.just_pressed(KeyCode::Minus) {
+        zoom *= ZOOM_STEP * ZOOM_STEP;
+    }
+    let drag = motion.iter().map(|event| event.delta).sum::<Vec2>();
+    if controller.mode != CameraMode::Free {
+        return;
+    }
+    controller.zoom = (controller.zoom * zoom).clamp(MIN_ZOOM, 1.0);
+    if mouse_buttons.pressed(MouseButton::Right) {
+        if let Ok((_, projection)) = cameras.get_single() {
+            // Screen coordinates point down, world coordinates up
+            controller.position +=
+                Vec2::new(-drag.x, drag.y) * projection.scale;
+        }
+    }
+}
+
+/// Moves the camera smoothly towards the view selected by the current mode.
+fn move_camera(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut controller: ResMut<CameraController>,
+    mut cameras: Query<(&Camera, &mut Transform, &mut OrthographicProjection)>,
+    fighters: Query<&Transform, (With<Fighter>, Without<Camera>)>,
+) {
+    let (camera, mut transform, mut projection) = match cameras.get_single_mut()
+    {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let viewport = match camera.logical_viewport_size() {
+        Some(size) if size.min_element() > 0.0 => size,
+        _ => return,
+    };
+    let followed = controller
+        .target
+        .and_then(|target| fighters.get(target).ok())
+        .map(|t| t.translation.truncate());
+    let positions = fighters
+        .iter()
+        .map(|t| t.translation.truncate())
+        .collect::<Vec<_>>();
+    let (position, scale) = view(
+        &controller,
+        settings.arena_size,
+        viewport,
+        followed,
+        &positions,
+    );
+    if controller.mode == CameraMode::Free {
+        controller.position = position;
+    }
+
+    let t = 1.0 - (-SMOOTHING * time.delta_seconds()).exp();
+    let current = transform.translation.truncate();
+    transform.translation =
+        current.lerp(position, t).extend(transform.translation.z);
+    projection.scale += (scale - projection.scale) * t;
+}
+
+/// Center and scale in world units per pixel of the view selected by the
+/// mode of `controller`, given the position of the `followed` fighter and
+/// those of all `fighters`.
+fn view(
+    controller: &CameraController,
+    arena: Vec2,
+    viewport: Vec2,
+    followed: Option<Vec2>,
+    fighters: &[Vec2],
+) -> (Vec2, f32) {
+    // World units per pixel at which the whole arena is visible
+    let fit_scale = (arena / viewport).max_element();
+    let (position, scale) = match controller.mode {
+        CameraMode::Fixed => (Vec2::ZERO, fit_scale),
+        CameraMode::Follow => {
+            (followed.unwrap_or(Vec2::ZERO), fit_scale * FOLLOW_ZOOM)
+        }
+        CameraMode::Frame => {
+            if fighters.is_empty() {
+                (Vec2::ZERO, fit_scale)
+            } else {
+                let min = fighters
+                    .iter()
+                    .fold(Vec2::splat(f32::MAX), |a, b| a.min(*b));
+                let max = fighters
+                    .iter()
+                    .fold(Vec2::splat(f32::MIN), |a, b| a.max(*b));
+                let size = max - min + 2.0 * FRAME_MARGIN;
+                let scale = (size / viewport).max_element();
+                (
+                    (min + max) / 2.0,
+                    scale.clamp(fit_scale * MIN_ZOOM, fit_scale),
+                )
+            }
+        }
+        CameraMode::Free => (controller.position, fit_scale * controller.zoom),
+    };
+    // Keep the view within the arena, centering it along any axis on which
+    // the whole arena is visible
+    let free_space = (arena - viewport * scale).max(Vec2::ZERO) / 2.0;
+    (position.clamp(-free_space, free_space), scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(mode: CameraMode) -> CameraController {
+        CameraController {
+            mode,
+            target: None,
+            position: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+
+    #[test]
+    fn views_of_an_arena_larger_than_the_viewport() {
+        let arena = Vec2::new(4000.0, 2000.0);
+        let viewport = Vec2::new(1000.0, 1000.0);
+        let view_of = |controller: &CameraController, fighters: &[Vec2]| {
+            view(
+                controller,
+                arena,
+                viewport,
+                fighters.first().copied(),
+                fighters,
+            )
+        };
+
+        assert_eq!(
+            view_of(&controller(CameraMode::Fixed), &[]),
+            (Vec2::ZERO, 4.0)
+        );
+        // Half the arena is visible, which leaves no room to pan vertically
+        let follow = controller(CameraMode::Follow);
+        assert_eq!(view_of(&follow, &[]), (Vec2::ZERO, 2.0));
+        assert_eq!(
+            view_of(&follow, &[Vec2::new(1900.0, 900.0)]),
+            (Vec2::new(1000.0, 0.0), 2.0)
+        );
+
+        let frame = controller(CameraMode::Frame);
+        assert_eq!(view_of(&frame, &[]), (Vec2::ZERO, 4.0));
+        // Close fighters are framed at the smallest zoom
+        assert_eq!(
+            view_of(
+                &frame,
+                &[Vec2::new(1400.0, 300.0), Vec2::new(1600.0, 500.0)]
+            ),
+            (Vec2::new(1500.0, 400.0), 1.0)
+        );
+        // Distant fighters are framed at most at the arena-fitting zoom
+        assert_eq!(
+            view_of(
+                &frame,
+                &[Vec2::new(-1800.0, 0.0), Vec2::new(1800.0, 800.0)]
+            ),
+            (Vec2::ZERO, 4.0)
+        );
+        // Framed at twice the zoom, the view can't pan vertically
+        let size = Vec2::new(1400.0, 600.0) + 2.0 * FRAME_MARGIN;
+        assert_eq!(
+            view_of(
+                &frame,
+                &[Vec2::new(-1000.0, -500.0), Vec2::new(400.0, 100.0)]
+            ),
+            (Vec2::new(-300.0, 0.0), size.max_element() / 1000.0)
+        );
+
+        let mut free = controller(CameraMode::Free);
+        free.position = Vec2::new(-5000.0, 5000.0);
+        free.zoom = MIN_ZOOM;
+        assert_eq!(view_of(&free, &[]), (Vec2::new(-1500.0, 500.0), 1.0));
+    }
+
+    #[test]
+    fn views_of_an_arena_smaller_than_the_viewport() {
+        let arena = Vec2::new(500.0, 500.0);
+        let viewport = Vec2::new(1000.0, 2000.0);
+        let view_of = |controller: &CameraController, fighters: &[Vec2]| {
+            view(
+                controller,
+                arena,
+                viewport,
+                fighters.first().copied(),
+                fighters,
+            )
+        };
+
+        // The whole arena is visible, centered along both axes
+        let fixed = controller(CameraMode::Fixed);
+        assert_eq!(view_of(&fixed, &[]), (Vec2::ZERO, 0.5));
+        let frame = controller(CameraMode::Frame);
+        let fighters = [Vec2::new(-200.0, -200.0), Vec2::new(200.0, 200.0)];
+        assert_eq!(view_of(&frame, &fighters), (Vec2::ZERO, 0.5));
+        // Zoomed in, the view pans horizontally but the arena's height is
+        // still visible
+        let follow = controller(CameraMode::Follow);
+        assert_eq!(
+            view_of(&follow, &[Vec2::new(200.0, 200.0)]),
+            (Vec2::new(125.0, 0.0), 0.25)
+        );
+        let mut free = controller(CameraMode::Free);
+        free.position = Vec2::new(300.0, -300.0);
+        free.zoom = MIN_ZOOM;
+        assert_eq!(view_of(&free, &[]), (Vec2::new(187.5, -125.0), 0.125));
+    }
+}
@@ -0,0 +1,452 @@
+//! Dataset of human demonstrations for behavior cloning.
+//!
+//! With `Settings.record_demonstrations`, every action of a local human is
+//! written to a JSON lines file along with the observation that the `ai`
+//! system would have built for the human's player at the same time. The first
+//! line describes the observation and action spaces with entity-gym's names:
+//! the features of each entity type and the labels of the `FighterAction`
+//! categorical action, in the spaces chosen by `Settings.agent_loadout` and
+//! `Settings.observe_ids`. Each following line is one step of one player, with
+//! the features of every entity type, the index of each actor's action and the
+//! player's score, from which rewards are derived. `demonstrations.py` loads
+//! the file as entity-gym `Observation`s for pretraining policies.
+//! Humans of players in `Settings.continuous_control` aren't recorded, since
+//! their actions don't map onto the categorical action space.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use bevy::log;
+use bevy::prelude::*;
+use entity_gym_rs::agent::{Action, Featurizable};
+use serde::Serialize;
+
+use crate::{act, entity, GameSummary, ObservationQueries, Players, Settings};
+
+/// Name of the action in the action space, as used by `create_env`.
+const ACTION_NAME: &str = "FighterAction";
+
+#[derive(Serialize)]
+struct Header {
+    /// Feature names of each entity type.
+    obs_space: HashMap<&'static str, Vec<String>>,
+    /// Labels of each categorical action.
+    action_space: HashMap<&'static str, Vec<String>>,
+    /// Entity type of the actors.
+    actor: &'static str,
+}
+
+#[derive(Serialize)]
+struct Step {
+    episode: usize,
+    player: usize,
+    /// Features of the entities of each type, actors first.
+    features: HashMap<&'static str, Vec<Vec<f32>>>,
+    /// Index of the action chosen by each actor.
+    actions: HashMap<&'static str, Vec<u64>>,
+    score: f32,
+}
+
+/// Last line of every game.
+#[derive(Serialize)]
+struct EpisodeEnd {
+    episode: usize,
+    final_scores: Vec<f32>,
+}
+
+/// Name and feature names of an entity type, with the ID if `ids` is set.
+fn space<E: Featurizable>(ids: bool) -> (&'static str, Vec<String>) {
+    let names = if ids {
+        entity::Identified::<E>::feature_names()
+    } else {
+        E::feature_names()
+    };
+    (E::name(), names)
+}
+
+/// Name of an entity type and the features of `entities`, with their IDs if
+/// `ids` is set.
+fn features<E: Featurizable>(
+    entities: Vec<entity::Identified<E>>,
+    ids: bool,
+) -> (&'static str, Vec<Vec<f32>>) {
+    let features = if ids {
+        entities.iter().map(|e| e.featurize()).collect()
+    } else {
+        entities.iter().map(|e| e.entity.featurize()).collect()
+    };
+    (E::name(), features)
+}
+
+/// Open dataset file and the number of games recorded so far.
+#[derive(Resource)]
+pub(crate) struct Demonstrations {
+    writer: Option<BufWriter<File>>,
+    episode: usize,
+}
+
+impl Demonstrations {
+    fn create(
+        path: &str,
+        settings: &Settings,
+    ) -> std::io::Result<Demonstrations> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let ids = settings.observe_ids;
+        let (mut obs_space, labels) = if settings.agent_loadout {
+            (
+                vec![
+                    space::<entity::Fighter>(ids),
+                    space::<entity::EnemyFighter>(ids),
+                    space::<entity::Asteroid>(ids),
+                    space::<entity::Bullet>(ids),
+                ],
+                act::FighterAction::labels(),
+            )
+        } else {
+            (
+                vec![
+                    space::<entity::classic::Fighter>(ids),
+                    space::<entity::classic::EnemyFighter>(ids),
+                    space::<entity::classic::Asteroid>(ids),
+                    space::<entity::classic::Bullet>(ids),
+                ],
+                act::classic::FighterAction::labels(),
+            )
+        };
+        if settings.powerups_enabled() {
+            obs_space.push(space::<entity::PowerUp>(ids));
+        }
+        if settings.observation_history > 0 {
+            obs_space.push(space::<entity::PastFighter>(ids));
+        }
+        let header = Header {
+            obs_space: obs_space.into_iter().collect(),
+            action_space: [(ACTION_NAME, labels)].into_iter().collect(),
+            actor: entity::Fighter::name(),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+        Ok(Demonstrations {
+            writer: Some(writer),
+            episode: 0,
+        })
+    }
+
+    fn write(&mut self, line: &impl Serialize) {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let result = serde_json::to_string(line)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(writer, "{}", line));
+        if let Err(e) = result {
+            log::error!("Failed to record demonstration: {}", e);
+            self.writer = None;
+        }
+    }
+}
+
+pub(crate) struct DemonstrationsPlugin;
+
+impl Plugin for DemonstrationsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = app.world.resource::<Settings>();
+        let demonstrations = match &settings.record_demonstrations {
+            Some(path) => Demonstrations::create(path, settings),
+            None => return,
+        };
+        let demonstrations = match demonstrations {
+            Ok(demonstrations) => demonstrations,
+            Err(e) => {
+                log::error!("Failed to create demonstrations file: {}", e);
+                return;
+            }
+        };
+        app.insert_resource(demonstrations)
+            .add_system(end_episode.after(crate::reset));
+    }
+}
+
+/// Records the actions of local humans in this frame with the observation of
+/// their player. Runs before `fighter_actions` so that observations are of the
+/// state in which the action was chosen, like those of agents.
+pub(crate) fn record_demonstrations(
+    mut demonstrations: Option<ResMut<Demonstrations>>,
+    mut action_events: EventReader<(act::FighterAction, Entity)>,
+    players: NonSend<Players>,
+    observations: ObservationQueries,
+) {
+    let demonstrations = match &mut demonstrations {
+        Some(demonstrations) => demonstrations,
+        None => return,
+    };
+    let actions = action_events
+        .iter()
+        .map(|(action, entity)| (*entity, *action))
+        .collect::<HashMap<_, _>>();
+    if actions.is_empty() {
+        return;
+    }
+    for (i, player) in players.0.iter().enumerate() {
+        if player.humans.is_empty() {
+            continue;
+        }
+        // Actors of the observation, see `ObservationQueries::observe`
+        let actors = player
+            .ids
+            .iter()
+            .filter(|id| {
+                observations.fighters.get(**id).is_ok()
+                    && observations.abilities.get(**id).is_ok()
+            })
+            .collect::<Vec<_>>();
+        let loadout = observations.settings.agent_loadout;
+        let chosen = actors
+            .iter()
+            .map(|id| {
+                actions.get(id).map(|&action| {
+                    if loadout {
+                        action.to_u64()
+                    } else {
+                        act::classic::FighterAction::from(action).to_u64()
+                    }
+                })
+            })
+            .collect::<Option<Vec<_>>>();
+        let chosen = match chosen {
+            Some(chosen) if !chosen.is_empty() => chosen,
+            _ => continue,
+        };
+        let observation = observations.observe(i, &player.ids);
+        let history_enabled = observations.settings.observation_history > 0;
+        let powerups_enabled = observations.settings.powerups_enabled();
+        let ids = observations.settings.observe_ids;
+        let mut step_features = if loadout {
+            vec![
+                features(observation.fighters, ids),
+                features(observation.enemy_fighters, ids),
+                features(observation.asteroids, ids),
+                features(observation.bullets, ids),
+            ]
+        } else {
+            let classic = observation.classic();
+            vec![
+                features(classic.fighters, ids),
+                features(classic.enemy_fighters, ids),
+                features(classic.asteroids, ids),
+                features(classic.bullets, ids),
+            ]
+        };
+        if powerups_enabled {
+            step_features.push(features(observation.powerups, ids));
+        }
+        if history_enabled {
+            step_features.push(features(observation.past_fighters, ids));
+        }
+        let step = Step {
+            episode: demonstrations.episode,
+            player: i,
+            features: step_features.into_iter().collect(),
+            actions: [(ACTION_NAME, chosen)].into_iter().collect(),
+            score: observation.score,
+        };
+        demonstrations.write(&step);
+    }
+}
+
+/// Writes the final scores of the game, which complete the rewards of its
+/// last steps.
+fn end_episode(
+    mut summaries: EventReader<GameSummary>,
+    mut demonstrations: ResMut<Demonstrations>,
+    players: NonSend<Players>,
+) {
+    for GameSummary(stats) in summaries.iter() {
+        let end = EpisodeEnd {
+            episode: demonstrations.episode,
+            final_scores: (0..players.0.len())
+                .map(|i| stats.score(i))
+                .collect(),
+        };
+        demonstrations.write(&end);
+        demonstrations.episode += 1;
+        if let Some(writer) = &mut demonstrations.writer {
+            if let Err(e) = writer.flush() {
+                log::error!("Failed to record demonstrations: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::input;
+    use crate::Controller;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "bevy-starfighter-demonstrations-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn read_lines(path: &str) -> Vec<Value> {
+        let lines = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(path).unwrap();
+        lines
+    }
+
+    /// Feature names of `E` as registered by `python::register_entity`.
+    fn registered<E: Featurizable>(ids: bool) -> (String, Value) {
+        let names = if ids {
+            entity::Identified::<E>::feature_names()
+        } else {
+            E::feature_names()
+        };
+        (E::name().to_string(), names.into())
+    }
+
+    #[test]
+    fn header_matches_create_env_spaces() {
+        // Entity types and actions that `python::create_env` registers for
+        // `Config.loadout` and `Config.ids`
+        let cases = [
+            (
+                false,
+                false,
+                vec![
+                    registered::<entity::classic::Fighter>(false),
+                    registered::<entity::classic::EnemyFighter>(false),
+                    registered::<entity::classic::Asteroid>(false),
+                    registered::<entity::classic::Bullet>(false),
+                ],
+                act::classic::FighterAction::labels(),
+            ),
+            (
+                true,
+                false,
+                vec![
+                    registered::<entity::Fighter>(false),
+                    registered::<entity::EnemyFighter>(false),
+                    registered::<entity::Asteroid>(false),
+                    registered::<entity::Bullet>(false),
+                ],
+                act::FighterAction::labels(),
+            ),
+            (
+                false,
+                true,
+                vec![
+                    registered::<entity::classic::Fighter>(true),
+                    registered::<entity::classic::EnemyFighter>(true),
+                    registered::<entity::classic::Asteroid>(true),
+                    registered::<entity::classic::Bullet>(true),
+                ],
+                act::classic::FighterAction::labels(),
+            ),
+        ];
+        for (loadout, ids, obs_space, labels) in cases {
+            let path = temp_path("header");
+            let settings = Settings {
+                agent_loadout: loadout,
+                observe_ids: ids,
+                ..default()
+            };
+            drop(Demonstrations::create(&path, &settings).unwrap());
+            let lines = read_lines(&path);
+            assert_eq!(lines.len(), 1);
+            let header = &lines[0];
+            let obs_space =
+                obs_space.into_iter().collect::<serde_json::Map<_, _>>();
+            assert_eq!(header["obs_space"], Value::Object(obs_space));
+            assert_eq!(
+                header["action_space"][ACTION_NAME],
+                Value::from(labels)
+            );
+            assert_eq!(header["actor"], entity::Fighter::name());
+            if ids {
+                let fighter = &header["obs_space"][entity::Fighter::name()];
+                assert_eq!(fighter[0], "id");
+            }
+        }
+    }
+
+    #[test]
+    fn records_human_steps_and_episode_end() {
+        let path = temp_path("steps");
+        let mut app = crate::app(
+            Settings {
+                seed: 3,
+                headless: true,
+                players: 2,
+                controllers: vec![Controller::Human(vec![0]), Controller::Idle],
+                max_game_length: 30,
+                record_demonstrations: Some(path.clone()),
+                ..default()
+            },
+            vec![],
+        );
+        // Headless apps don't read input, so the thrust key is pressed
+        // directly for the human's bindings
+        let mut keys = Input::<KeyCode>::default();
+        keys.press(input::Bindings::default().thrust[0]);
+        app.insert_resource(keys)
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .add_system(input::human_input);
+        for _ in 0..60 {
+            app.update();
+        }
+        drop(app);
+
+        let lines = read_lines(&path);
+        let header = &lines[0];
+        let obs_space = header["obs_space"].as_object().unwrap();
+        let thrust = act::classic::FighterAction {
+            thrust: act::Thrust::On,
+            shoot: act::Shoot::Off,
+            turn: act::Turn::None,
+        }
+        .to_u64();
+        let end = lines
+            .iter()
+            .position(|line| line.get("final_scores").is_some())
+            .expect("game didn't end");
+        assert!(end > 1, "no steps were recorded");
+        for step in &lines[1..end] {
+            assert_eq!(step["episode"], 0);
+            assert_eq!(step["player"], 0);
+            assert_eq!(step["actions"][ACTION_NAME], Value::from(vec![thrust]));
+            assert!(step["score"].is_number());
+            let features = step["features"].as_object().unwrap();
+            assert_eq!(
+                features.keys().collect::<Vec<_>>(),
+                obs_space.keys().collect::<Vec<_>>()
+            );
+            for (name, rows) in features {
+                let num_feats = obs_space[name].as_array().unwrap().len();
+                for row in rows.as_array().unwrap() {
+                    assert_eq!(row.as_array().unwrap().len(), num_feats);
+                }
+            }
+            assert_eq!(
+                features[entity::Fighter::name()].as_array().unwrap().len(),
+                1
+            );
+        }
+        let episode_end = &lines[end];
+        assert_eq!(episode_end["episode"], 0);
+        assert_eq!(episode_end["final_scores"].as_array().unwrap().len(), 2);
+        assert!(lines[end + 1..].iter().all(|line| line["episode"] != 0));
+    }
+}
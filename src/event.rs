@@ -0,0 +1,235 @@
+//! Gameplay events sent whenever something noteworthy happens.
+//!
+//! Collisions and actions are resolved in `detect_collisions` and
+//! `fighter_actions`, which only report what happened through these events.
+//! `Stats`, the particle effects and anything else that needs to react to
+//! kills, hits or respawns subscribe to them with an `EventReader`.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::{particles, GameOver, Stats};
+
+/// A fighter fired a weapon.
+pub(crate) struct BulletFired;
+
+/// A fighter lost hull points without being destroyed.
+pub(crate) struct FighterHit {
+    pub(crate) fighter: Entity,
+    /// Player whose projectile hit the fighter, `None` if it rammed an
+    /// asteroid.
+    pub(crate) attacker: Option<usize>,
+}
+
+/// The passive shield of a fighter absorbed a hit.
+pub(crate) struct ShieldBroken {
+    pub(crate) fighter: Entity,
+    /// Player whose projectile hit the fighter, `None` if it rammed an
+    /// asteroid.
+    pub(crate) attacker: Option<usize>,
+}
+
+pub(crate) struct FighterDestroyed {
+    pub(crate) victim: Entity,
+    pub(crate) victim_player: usize,
+    /// Player whose projectile destroyed the fighter, `None` if it rammed an
+    /// asteroid.
+    pub(crate) killer: Option<usize>,
+}
+
+/// A projectile damaged an asteroid, which may or may not be destroyed.
+pub(crate) struct AsteroidHit;
+
+pub(crate) struct AsteroidDestroyed {
+    pub(crate) asteroid: Entity,
+    /// Player whose projectile destroyed the asteroid, or whose fighter
+    /// rammed it.
+    pub(crate) attacker: Option<usize>,
+    pub(crate) rammed: bool,
+}
+
+/// A fighter was spawned once its respawn timer ran out. This includes the
+/// first fighters of opponents, while player 0 starts with fighters that are
+/// spawned directly.
+pub(crate) struct PlayerRespawned;
+
+/// Writers for the events sent while resolving collisions.
+#[derive(SystemParam)]
+pub(crate) struct GameEvents<'w, 's> {
+    pub(crate) fighter_hit: EventWriter<'w, 's, FighterHit>,
+    pub(crate) shield_broken: EventWriter<'w, 's, ShieldBroken>,
+    pub(crate) fighter_destroyed: EventWriter<'w, 's, FighterDestroyed>,
+    pub(crate) asteroid_hit: EventWriter<'w, 's, AsteroidHit>,
+    pub(crate) asteroid_destroyed: EventWriter<'w, 's, AsteroidDestroyed>,
+    pub(crate) effects: EventWriter<'w, 's, particles::Effect>,
+    pub(crate) game_over: EventWriter<'w, 's, GameOver>,
+}
+
+pub(crate) fn update_stats(
+    mut stats: ResMut<Stats>,
+    mut bullets_fired: EventReader<BulletFired>,
+    mut asteroid_hits: EventReader<AsteroidHit>,
+    mut asteroids_destroyed: EventReader<AsteroidDestroyed>,
+    mut fighter_hits: EventReader<FighterHit>,
+    mut shields_broken: EventReader<ShieldBroken>,
+    mut fighters_destroyed: EventReader<FighterDestroyed>,
+) {
+    stats.bullets_fired += bullets_fired.iter().count();
+    stats.bullet_hits += asteroid_hits.iter().count();
+    for event in asteroids_destroyed.iter() {
+        if let Some(attacker) = event.attacker {
+            let player = stats.player_mut(attacker);
+            if event.rammed {
+                player.rammed_asteroids += 1;
+            } else {
+                player.destroyed_asteroids += 1;
+            }
+        }
+        if !event.rammed {
+            stats.destroyed_asteroids += 1;
+        }
+    }
+    let hits = fighter_hits
+        .iter()
+        .map(|event| (event.fighter, event.attacker))
+        .chain(
+            shields_broken
+                .iter()
+                .map(|event| (event.fighter, event.attacker)),
+        );
+    for (fighter, attacker) in hits {
+        if let Some(attacker) = attacker {
+            let damagers = stats.damagers.entry(fighter).or_default();
+            if !damagers.contains(&attacker) {
+                damagers.push(attacker);
+            }
+        }
+    }
+    for event in fighters_destroyed.iter() {
+        stats.bullet_hits += 1;
+        if event.victim_player == 0 {
+            stats.destroyed_allies += 1;
+        } else {
+            stats.destroyed_opponents += 1;
+        }
+        stats.player_mut(event.victim_player).deaths += 1;
+        if let Some(killer) = event.killer {
+            stats.player_mut(killer).kills += 1;
+        }
+        let damagers = stats.damagers.remove(&event.victim).unwrap_or_default();
+        for player in damagers {
+            if Some(player) != event.killer && player != event.victim_player {
+                stats.player_mut(player).assists += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_app(kill_rewards: bool) -> App {
+        let mut app = App::new();
+        app.insert_resource(Stats {
+            kill_rewards,
+            ..Stats::default()
+        })
+        .add_event::<BulletFired>()
+        .add_event::<AsteroidHit>()
+        .add_event::<AsteroidDestroyed>()
+        .add_event::<FighterHit>()
+        .add_event::<ShieldBroken>()
+        .add_event::<FighterDestroyed>()
+        .add_system(update_stats);
+        app
+    }
+
+    #[test]
+    fn credits_kill_and_assists() {
+        let mut app = stats_app(true);
+        let victim = app.world.spawn_empty().id();
+        app.world.send_event(FighterHit {
+            fighter: victim,
+            attacker: Some(2),
+        });
+        app.world.send_event(ShieldBroken {
+            fighter: victim,
+            attacker: Some(0),
+        });
+        // Damage from the victim's own team never counts as an assist
+        app.world.send_event(FighterHit {
+            fighter: victim,
+            attacker: Some(1),
+        });
+        app.update();
+        app.world.send_event(FighterDestroyed {
+            victim,
+            victim_player: 1,
+            killer: Some(0),
+        });
+        app.update();
+
+        let stats = app.world.resource::<Stats>();
+        assert_eq!(stats.player(0).kills, 1);
+        assert_eq!(stats.player(0).assists, 0);
+        assert_eq!(stats.player(1).deaths, 1);
+        assert_eq!(stats.player(1).assists, 0);
+        assert_eq!(stats.player(2).assists, 1);
+        assert_eq!(stats.player(2).kills, 0);
+        assert_eq!(stats.destroyed_opponents, 1);
+        assert!(stats.damagers.is_empty());
+    }
+
+    fn play_scores(kill_rewards: bool) -> Vec<f32> {
+        let mut app = stats_app(kill_rewards);
+        let [asteroid1, asteroid2, opponent1, opponent2, player] =
+            [(); 5].map(|_| app.world.spawn_empty().id());
+        app.world.send_event(AsteroidDestroyed {
+            asteroid: asteroid1,
+            attacker: Some(0),
+            rammed: false,
+        });
+        app.world.send_event(AsteroidDestroyed {
+            asteroid: asteroid2,
+            attacker: Some(0),
+            rammed: true,
+        });
+        app.world.send_event(FighterHit {
+            fighter: opponent1,
+            attacker: Some(2),
+        });
+        app.world.send_event(FighterDestroyed {
+            victim: opponent1,
+            victim_player: 1,
+            killer: Some(0),
+        });
+        // Rammed an asteroid, which destroys it without a killer
+        app.world.send_event(FighterDestroyed {
+            victim: opponent2,
+            victim_player: 1,
+            killer: None,
+        });
+        app.world.send_event(FighterDestroyed {
+            victim: player,
+            victim_player: 0,
+            killer: Some(1),
+        });
+        app.update();
+
+        let stats = app.world.resource::<Stats>();
+        assert_eq!(stats.player(0).destroyed_asteroids, 1);
+        assert_eq!(stats.player(0).rammed_asteroids, 1);
+        (0..3).map(|player| stats.score(player)).collect()
+    }
+
+    #[test]
+    fn kill_rewards_score_kills_and_assists() {
+        // Projectile asteroid and a kill, but neither the rammed asteroid nor
+        // the opponent that crashed into one
+        assert_eq!(play_scores(true), vec![2.0, 10.0, 5.0]);
+        // One destroyed asteroid, both destroyed opponents, and opponents
+        // share the reward for destroying player 0
+        assert_eq!(play_scores(false), vec![3.0, 10.0, 10.0]);
+    }
+}
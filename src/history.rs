@@ -0,0 +1,168 @@
+//! Past states of fighters for observations with temporal context.
+//!
+//! With `Settings.observation_history` set to K, a snapshot of every fighter
+//! is taken each time agents act, and observations include the last K
+//! snapshots of each fighter that still exists as `entity::PastFighter`
+//! entities with the number of frames since the snapshot as their `age`.
+//! Snapshots are keyed by the fighter's entity, which stays the same for as
+//! long as the fighter lives, so every past state belongs to a fighter in the
+//! current observation.
+
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{transform_to_direction, Fighter, RemainingTime, Settings};
+
+pub(crate) struct FighterSnapshot {
+    pub(crate) player_id: usize,
+    pub(crate) position: Vec2,
+    pub(crate) velocity: Vec2,
+    pub(crate) direction: Vec2,
+}
+
+pub(crate) struct Snapshot {
+    /// `RemainingTime` when the snapshot was taken.
+    pub(crate) remaining_time: i32,
+    pub(crate) fighters: HashMap<Entity, FighterSnapshot>,
+}
+
+/// Last `Settings.observation_history` snapshots, most recent first.
+#[derive(Resource, Default)]
+pub(crate) struct ObservationHistory {
+    pub(crate) snapshots: VecDeque<Snapshot>,
+}
+
+/// Takes a snapshot whenever agents act, after they observed the previous
+/// ones.
+pub(crate) fn record_history(
+    settings: Res<Settings>,
+    remaining_time: Res<RemainingTime>,
+    mut history: ResMut<ObservationHistory>,
+    fighters: Query<(Entity, &Fighter, &Transform, &Velocity)>,
+) {
+    let action_interval = settings
+        .ai_action_interval
+        .unwrap_or(settings.action_interval);
+    if remaining_time.0 as u32 % action_interval != 0 {
+        return;
+    }
+    // The remaining time only goes up when a new game starts
+    if history
+        .snapshots
+        .front()
+        .map_or(false, |s| s.remaining_time <= remaining_time.0)
+    {
+        history.snapshots.clear();
+    }
+    history.snapshots.push_front(Snapshot {
+        remaining_time: remaining_time.0,
+        fighters: fighters
+            .iter()
+            .map(|(entity, fighter, transform, velocity)| {
+                let (direction_x, direction_y) =
+                    transform_to_direction(transform);
+                let snapshot = FighterSnapshot {
+                    player_id: fighter.player_id,
+                    position: transform.translation.truncate(),
+                    velocity: velocity.linvel,
+                    direction: Vec2::new(direction_x, direction_y),
+                };
+                (entity, snapshot)
+            })
+            .collect(),
+    });
+    history.snapshots.truncate(settings.observation_history);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::{ids, tests::fighter, ObservationQueries, Stats};
+
+    fn history_app(observation_history: usize) -> App {
+        let mut app = App::new();
+        app.insert_resource(Settings {
+            observation_history,
+            action_interval: 2,
+            ..default()
+        })
+        .insert_resource(RemainingTime(100))
+        .init_resource::<ObservationHistory>()
+        .init_resource::<Stats>()
+        .init_resource::<ids::EntityIds>()
+        .add_system(record_history);
+        app
+    }
+
+    fn spawn_fighter(app: &mut App, x: f32) -> Entity {
+        app.world
+            .spawn((
+                fighter(0),
+                Transform::from_xyz(x, 0.0, 0.0),
+                Velocity::zero(),
+            ))
+            .id()
+    }
+
+    /// Runs a frame at `remaining_time`.
+    fn update_at(app: &mut App, remaining_time: i32) {
+        app.world.resource_mut::<RemainingTime>().0 = remaining_time;
+        app.update();
+    }
+
+    fn snapshot_times(app: &App) -> Vec<i32> {
+        let history = app.world.resource::<ObservationHistory>();
+        history.snapshots.iter().map(|s| s.remaining_time).collect()
+    }
+
+    #[test]
+    fn keeps_the_last_snapshots_when_agents_act() {
+        let mut app = history_app(3);
+        spawn_fighter(&mut app, 0.0);
+        for remaining_time in (91..=100).rev() {
+            update_at(&mut app, remaining_time);
+        }
+        assert_eq!(snapshot_times(&app), vec![92, 94, 96]);
+    }
+
+    #[test]
+    fn clears_the_history_of_the_previous_game() {
+        let mut app = history_app(3);
+        spawn_fighter(&mut app, 0.0);
+        update_at(&mut app, 100);
+        update_at(&mut app, 98);
+        assert_eq!(snapshot_times(&app), vec![98, 100]);
+        update_at(&mut app, 100);
+        assert_eq!(snapshot_times(&app), vec![100]);
+    }
+
+    #[test]
+    fn observes_ages_of_fighters_that_still_exist() {
+        let mut app = history_app(2);
+        let survivor = spawn_fighter(&mut app, 1.0);
+        let destroyed = spawn_fighter(&mut app, 2.0);
+        update_at(&mut app, 100);
+        app.world
+            .get_mut::<Transform>(survivor)
+            .unwrap()
+            .translation
+            .x = 5.0;
+        update_at(&mut app, 98);
+        app.world.despawn(destroyed);
+        update_at(&mut app, 97);
+
+        let mut observations =
+            SystemState::<ObservationQueries>::new(&mut app.world);
+        let observation = observations.get_mut(&mut app.world).observe(0, &[]);
+        let past = observation
+            .past_fighters
+            .iter()
+            .map(|f| (f.x, f.age))
+            .collect::<Vec<_>>();
+        assert_eq!(past, vec![(5.0, 1), (1.0, 3)]);
+    }
+}
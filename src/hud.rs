@@ -0,0 +1,255 @@
+//! Heads-up display built with Bevy UI.
+//!
+//! The status panel in the top right corner shows the remaining time, lives,
+//! enemy count and difficulty, along with the hull, shield, gun and ability
+//! cooldowns of every fighter of player 0. With more than one player, a score
+//! panel per player runs along the bottom of the screen. Enemies outside of
+//! the camera's view are marked by indicators at the edge of the screen.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    ability, act, Fighter, Players, RemainingTime, Settings, Stats,
+    FIGHTER_COLORS,
+};
+
+const FONT: &str = "fonts/FiraSans-Bold.ttf";
+const FONT_SIZE: f32 = 24.0;
+const INDICATOR_SIZE: f32 = 14.0;
+
+pub(crate) struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_hud)
+            .add_system(update_status)
+            .add_system(update_score_panels)
+            .add_system(update_offscreen_indicators);
+    }
+}
+
+#[derive(Component)]
+struct StatusText;
+
+#[derive(Component)]
+struct ScorePanel(usize);
+
+/// Marks an enemy fighter outside of the camera's view.
+#[derive(Component)]
+struct OffscreenIndicator;
+
+fn spawn_hud(
+    mut cmd: Commands,
+    asset_server: Res<AssetServer>,
+    players: NonSend<Players>,
+) {
+    let font = asset_server.load(FONT);
+    cmd.spawn(
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: FONT_SIZE,
+                color: Color::WHITE,
+            },
+        )
+        .with_text_alignment(TextAlignment::TOP_RIGHT)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(10.0),
+                right: Val::Px(15.0),
+                ..default()
+            },
+            ..default()
+        }),
+    )
+    .insert(StatusText);
+    let player_count = players.0.len();
+    if player_count < 2 {
+        return;
+    }
+    for i in 0..player_count {
+        cmd.spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: FONT_SIZE,
+                    color: FIGHTER_COLORS[i % FIGHTER_COLORS.len()],
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(10.0),
+                    left: Val::Percent(
+                        100.0 * (i as f32 + 0.4) / player_count as f32,
+                    ),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(ScorePanel(i));
+    }
+}
+
+/// Formats the remaining fraction of a cooldown.
+fn cooldown(fraction: f32) -> String {
+    if fraction <= 0.0 {
+        "ready".to_string()
+    } else {
+        format!("{:.0}%", 100.0 * fraction)
+    }
+}
+
+fn update_status(
+    settings: Res<Settings>,
+    stats: Res<Stats>,
+    remaining_time: Res<RemainingTime>,
+    players: NonSend<Players>,
+    fighters: Query<(&Fighter, &ability::Abilities)>,
+    mut text: Query<&mut Text, With<StatusText>>,
+) {
+    let mut text = match text.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let seconds = (remaining_time.0.max(0) as f32 / settings.frame_rate) as u32;
+    let player = &players.0[0];
+    let enemies = fighters.iter().filter(|(f, _)| f.player_id != 0).count();
+    let mut status = format!(
+        "Time {}:{:02}\nLives {}\nEnemies {}\nDifficulty {}\n",
+        seconds / 60,
+        seconds % 60,
+        player.ids.len() + player.extra_lives as usize,
+        enemies,
+        settings.difficulty(stats.timesteps),
+    );
+    for id in &player.ids {
+        if let Ok((fighter, abilities)) = fighters.get(*id) {
+            let shield = if fighter.shield_active {
+                "ready".to_string()
+            } else {
+                cooldown(
+                    fighter.shield_cooldown as f32
+                        / fighter.shield_recharge_period as f32,
+                )
+            };
+            let gun = fighter.remaining_bullet_cooldown.max(0) as f32
+                / fighter.bullet_cooldown as f32;
+            status.push_str(&format!(
+                "\nHull {:.0}%  Shield {}  Gun {}\nEnergy {:.0}%  Front {}  \
+                 Boost {}  Blink {}  EMP {}\n",
+                100.0 * fighter.health.max(0.0) / fighter.max_health,
+                shield,
+                cooldown(gun.min(1.0)),
+                100.0 * abilities.energy(),
+                cooldown(abilities.cooldown(act::Ability::Shield)),
+                cooldown(abilities.cooldown(act::Ability::Boost)),
+                cooldown(abilities.cooldown(act::Ability::Blink)),
+                cooldown(abilities.cooldown(act::Ability::Emp)),
+            ));
+        }
+    }
+    text.sections[0].value = status;
+}
+
+fn update_score_panels(
+    stats: Res<Stats>,
+    mut panels: Query<(&ScorePanel, &mut Text)>,
+) {
+    for (panel, mut text) in panels.iter_mut() {
+        let player = stats.player(panel.0);
+        text.sections[0].value = format!(
+            "Player {}: {:.0}\nK/D/A {}/{}/{}",
+            panel.0,
+            stats.score(panel.0),
+            player.kills,
+            player.deaths,
+            player.assists,
+        );
+    }
+}
+
+/// Places an indicator at the edge of the screen in the direction of every
+/// enemy fighter the camera doesn't show.
+fn update_offscreen_indicators(
+    mut cmd: Commands,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    fighters: Query<(Entity, &Fighter, &GlobalTransform)>,
+    mut indicators: Query<&mut Style, With<OffscreenIndicator>>,
+    mut tracked: Local<HashMap<Entity, Entity>>,
+) {
+    let (camera, camera_transform) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let size = match camera.logical_viewport_size() {
+        Some(size) => size,
+        None => return,
+    };
+    let mut offscreen = HashMap::new();
+    for (entity, fighter, transform) in fighters.iter() {
+        if fighter.player_id == 0 {
+            continue;
+        }
+        let position = match camera
+            .world_to_viewport(camera_transform, transform.translation())
+        {
+            Some(position) => position,
+            None => continue,
+        };
+        if position.cmpge(Vec2::ZERO).all() && position.cmple(size).all() {
+            continue;
+        }
+        let half = INDICATOR_SIZE / 2.0;
+        let clamped = position.clamp(Vec2::splat(half), size - half);
+        offscreen.insert(entity, (fighter.player_id, clamped));
+    }
+    // Despawn indicators of fighters that are gone or back on screen
+    tracked.retain(|fighter, indicator| {
+        let keep = offscreen.contains_key(fighter);
+        if !keep {
+            cmd.entity(*indicator).despawn();
+        }
+        keep
+    });
+    for (fighter, (player_id, position)) in offscreen {
+        // The viewport's y axis points up, the UI's points down
+        let position = UiRect {
+            left: Val::Px(position.x - INDICATOR_SIZE / 2.0),
+            top: Val::Px(size.y - position.y - INDICATOR_SIZE / 2.0),
+            ..default()
+        };
+        if let Some(mut style) = tracked
+            .get(&fighter)
+            .and_then(|indicator| indicators.get_mut(*indicator).ok())
+        {
+            style.position = position;
+            continue;
+        }
+        let indicator = cmd
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position,
+                    size: Size::new(
+                        Val::Px(INDICATOR_SIZE),
+                        Val::Px(INDICATOR_SIZE),
+                    ),
+                    ..default()
+                },
+                background_color: FIGHTER_COLORS
+                    [player_id % FIGHTER_COLORS.len()]
+                .into(),
+                ..default()
+            })
+            .insert(OffscreenIndicator)
+            .id();
+        tracked.insert(fighter, indicator);
+    }
+}
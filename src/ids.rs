@@ -0,0 +1,145 @@
+//! Stable per-episode IDs of observed entities.
+//!
+//! Every fighter, asteroid, projectile and power-up is assigned the next free
+//! ID the frame after it spawns, before any system observes it, and keeps it
+//! until it despawns. IDs are sent to bots along with every observed entity
+//! (see `entity::Identified`), so that they can track entities across steps and
+//! refer to them with `act::Target`, and observed by policies with
+//! `Settings.observe_ids`. Numbering starts from 1 in every game, and
+//! 0 never refers to an entity.
+//!
+//! Entities spawned in the same frame are numbered in the order their spawn
+//! commands were applied, as recorded by `record_spawn_order`. Unlike the
+//! `Entity` itself, which is allocated while systems may run in parallel,
+//! this order is the same whenever a game is re-simulated.
+
+use std::collections::HashMap;
+
+use bevy::ecs::system::{Command, EntityCommands};
+use bevy::prelude::*;
+
+use crate::{powerup, Asteroid, Bullet, Fighter, RemainingTime};
+
+type Observed = Or<(
+    With<Fighter>,
+    With<Asteroid>,
+    With<Bullet>,
+    With<powerup::PowerUp>,
+)>;
+
+#[derive(Resource, Default)]
+pub(crate) struct EntityIds {
+    ids: HashMap<Entity, u32>,
+    entities: HashMap<u32, Entity>,
+    next: u32,
+    /// `RemainingTime` when IDs were last assigned, which only goes up when a
+    /// new game starts.
+    remaining_time: i32,
+    /// Number of observed entities spawned so far.
+    spawned: u64,
+}
+
+/// Position of an observed entity in the order of all spawns.
+#[derive(Component)]
+pub(crate) struct SpawnOrder(u64);
+
+pub(crate) trait RecordSpawnOrder {
+    /// Gives the entity its `SpawnOrder` once the spawn command is applied.
+    fn record_spawn_order(&mut self) -> &mut Self;
+}
+
+impl RecordSpawnOrder for EntityCommands<'_, '_, '_> {
+    fn record_spawn_order(&mut self) -> &mut Self {
+        let entity = self.id();
+        self.commands().add(InsertSpawnOrder(entity));
+        self
+    }
+}
+
+struct InsertSpawnOrder(Entity);
+
+impl Command for InsertSpawnOrder {
+    fn write(self, world: &mut World) {
+        let order = match world.get_resource_mut::<EntityIds>() {
+            Some(mut ids) => {
+                ids.spawned += 1;
+                ids.spawned
+            }
+            None => return,
+        };
+        if let Some(mut entity) = world.get_entity_mut(self.0) {
+            entity.insert(SpawnOrder(order));
+        }
+    }
+}
+
+impl EntityIds {
+    /// ID of `entity`, or 0 if it has none.
+    pub(crate) fn id(&self, entity: Entity) -> u32 {
+        self.ids.get(&entity).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn entity(&self, id: u32) -> Option<Entity> {
+        self.entities.get(&id).copied()
+    }
+}
+
+pub(crate) fn assign_ids(
+    remaining_time: Res<RemainingTime>,
+    mut ids: ResMut<EntityIds>,
+    observed: Query<(Entity, Option<&SpawnOrder>), Observed>,
+) {
+    let ids = &mut *ids;
+    if remaining_time.0 > ids.remaining_time {
+        ids.next = 1;
+    }
+    ids.remaining_time = remaining_time.0;
+    ids.ids.retain(|entity, _| observed.contains(*entity));
+    ids.entities.retain(|_, entity| observed.contains(*entity));
+    // Number new entities in spawn order, which keeps IDs the same when
+    // replays are re-simulated
+    let mut new = observed
+        .iter()
+        .filter(|(entity, _)| !ids.ids.contains_key(entity))
+        .map(|(entity, order)| (order.map(|order| order.0), entity))
+        .collect::<Vec<_>>();
+    new.sort();
+    for (_, entity) in new {
+        ids.ids.insert(entity, ids.next);
+        ids.entities.insert(ids.next, entity);
+        ids.next += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+
+    #[test]
+    fn numbers_new_entities_in_spawn_order() {
+        let mut app = App::new();
+        app.insert_resource(RemainingTime(100))
+            .init_resource::<EntityIds>()
+            .add_system(assign_ids);
+        let mut queue = CommandQueue::default();
+        let mut cmd = Commands::new(&mut queue, &app.world);
+        let asteroid = || Asteroid {
+            health: 2.0,
+            radius: 20.0,
+        };
+        let first = cmd.spawn(asteroid()).id();
+        let second = cmd.spawn(asteroid()).id();
+        // Recorded in the opposite order of the entities themselves
+        cmd.entity(second).record_spawn_order();
+        cmd.entity(first).record_spawn_order();
+        queue.apply(&mut app.world);
+        app.update();
+
+        let ids = app.world.resource::<EntityIds>();
+        assert_eq!(ids.id(second), 1);
+        assert_eq!(ids.id(first), 2);
+        assert_eq!(ids.entity(1), Some(second));
+    }
+}
@@ -0,0 +1,569 @@
+//! Mapping of keyboard and gamepad input onto `act::FighterAction`.
+//!
+//! Bindings are loaded from a RON file (see `bindings.ron` in the repository
+//! root) and describe one binding set per local human player. Binding sets are
+//! assigned to players with `Controller::Human`. Humans of players in
+//! `Settings.continuous_control` act with `act::ContinuousAction` instead, so
+//! that the sticks of analog gamepads thrust and turn proportionally to their
+//! deflection.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{act, Players, RemainingTime, Settings};
+
+/// Binding sets for all local human players.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputConfig {
+    pub players: Vec<Bindings>,
+}
+
+/// Keyboard and gamepad bindings controlling a single fighter.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bindings {
+    pub thrust: Vec<KeyCode>,
+    pub stop: Vec<KeyCode>,
+    pub turn_left: Vec<KeyCode>,
+    pub turn_right: Vec<KeyCode>,
+    pub shoot: Vec<KeyCode>,
+    /// Keys selecting each weapon, in the order of `act::Weapon::ALL`.
+    pub weapons: Vec<KeyCode>,
+    pub next_weapon: Vec<KeyCode>,
+    pub shield: Vec<KeyCode>,
+    pub boost: Vec<KeyCode>,
+    pub blink: Vec<KeyCode>,
+    pub emp: Vec<KeyCode>,
+    /// Locks onto the enemy closest to the fighter's heading, or releases the
+    /// lock, see `targeting`.
+    pub target: Vec<KeyCode>,
+    pub gamepad: Option<GamepadBindings>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadBindings {
+    /// Id of the gamepad, assigned by Bevy in order of connection.
+    pub id: usize,
+    pub turn_axis: GamepadAxisType,
+    pub thrust_axis: GamepadAxisType,
+    pub thrust: Vec<GamepadButtonType>,
+    pub stop: Vec<GamepadButtonType>,
+    pub shoot: Vec<GamepadButtonType>,
+    pub next_weapon: Vec<GamepadButtonType>,
+    pub shield: Vec<GamepadButtonType>,
+    pub boost: Vec<GamepadButtonType>,
+    pub blink: Vec<GamepadButtonType>,
+    pub emp: Vec<GamepadButtonType>,
+    /// Stick deflection required for a quarter turn.
+    pub quarter_turn_threshold: f32,
+    /// Stick deflection required for a full turn.
+    pub full_turn_threshold: f32,
+    /// Stick deflection required to thrust forward or brake.
+    pub thrust_threshold: f32,
+    /// Stick deflection below which continuous actions ignore the stick.
+    pub dead_zone: f32,
+}
+
+impl InputConfig {
+    /// Reads bindings from a RON file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Result<InputConfig, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+        ron::from_str(&contents)
+            .map_err(|e| format!("failed to parse {}: {}", path, e))
+    }
+
+    /// Two humans sharing one keyboard, the second one using the arrow keys.
+    pub fn split_keyboard() -> InputConfig {
+        InputConfig {
+            players: vec![
+                Bindings::wasd(),
+                Bindings {
+                    gamepad: None,
+                    ..Bindings::arrows()
+                },
+            ],
+        }
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            players: vec![Bindings::default()],
+        }
+    }
+}
+
+impl Bindings {
+    pub fn wasd() -> Bindings {
+        Bindings {
+            thrust: vec![KeyCode::W],
+            stop: vec![KeyCode::S],
+            turn_left: vec![KeyCode::A],
+            turn_right: vec![KeyCode::D],
+            shoot: vec![KeyCode::Space],
+            ..Bindings::default()
+        }
+    }
+
+    pub fn arrows() -> Bindings {
+        Bindings {
+            thrust: vec![KeyCode::Up],
+            stop: vec![KeyCode::Down],
+            turn_left: vec![KeyCode::Left],
+            turn_right: vec![KeyCode::Right],
+            shoot: vec![KeyCode::RControl, KeyCode::Return],
+            weapons: vec![
+                KeyCode::Numpad1,
+                KeyCode::Numpad2,
+                KeyCode::Numpad3,
+                KeyCode::Numpad4,
+                KeyCode::Numpad5,
+            ],
+            next_weapon: vec![KeyCode::RShift],
+            shield: vec![KeyCode::Numpad0],
+            boost: vec![KeyCode::NumpadEnter],
+            blink: vec![KeyCode::NumpadAdd],
+            emp: vec![KeyCode::NumpadSubtract],
+            target: vec![KeyCode::NumpadMultiply],
+            gamepad: Some(GamepadBindings {
+                id: 1,
+                ..GamepadBindings::default()
+            }),
+        }
+    }
+
+    /// Computes the action selected by the current input state, firing the
+    /// currently selected `weapon`.
+    pub fn action(
+        &self,
+        keys: &Input<KeyCode>,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+        weapon: act::Weapon,
+    ) -> act::FighterAction {
+        let mut thrust = if keys.any_pressed(self.thrust.iter().copied()) {
+            act::Thrust::On
+        } else if keys.any_pressed(self.stop.iter().copied()) {
+            act::Thrust::Stop
+        } else {
+            act::Thrust::Off
+        };
+        let mut turn = if keys.any_pressed(self.turn_left.iter().copied()) {
+            act::Turn::Left
+        } else if keys.any_pressed(self.turn_right.iter().copied()) {
+            act::Turn::Right
+        } else {
+            act::Turn::None
+        };
+        let mut shoot = if keys.any_pressed(self.shoot.iter().copied()) {
+            act::Shoot::On
+        } else {
+            act::Shoot::Off
+        };
+        let pressed =
+            |bound: &[KeyCode]| keys.any_pressed(bound.iter().copied());
+        let mut ability = if pressed(&self.shield) {
+            act::Ability::Shield
+        } else if pressed(&self.boost) {
+            act::Ability::Boost
+        } else if pressed(&self.blink) {
+            act::Ability::Blink
+        } else if pressed(&self.emp) {
+            act::Ability::Emp
+        } else {
+            act::Ability::None
+        };
+        if let Some(gamepad) = &self.gamepad {
+            let pad = gamepad.action(buttons, axes);
+            // Keyboard input takes precedence over the gamepad
+            if thrust == act::Thrust::Off {
+                thrust = pad.thrust;
+            }
+            if turn == act::Turn::None {
+                turn = pad.turn;
+            }
+            if shoot == act::Shoot::Off {
+                shoot = pad.shoot;
+            }
+            if ability == act::Ability::None {
+                ability = pad.ability;
+            }
+        }
+        act::FighterAction {
+            thrust,
+            turn,
+            shoot,
+            weapon,
+            ability,
+        }
+    }
+
+    /// Like `action`, but thrusting and turning proportionally to the
+    /// deflection of the gamepad's sticks.
+    pub fn continuous_action(
+        &self,
+        keys: &Input<KeyCode>,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+        weapon: act::Weapon,
+    ) -> act::ContinuousAction {
+        let mut action = act::ContinuousAction::from(
+            self.action(keys, buttons, axes, weapon),
+        );
+        let gamepad = match &self.gamepad {
+            Some(gamepad) => gamepad,
+            None => return action,
+        };
+        let pressed =
+            |bound: &[KeyCode]| keys.any_pressed(bound.iter().copied());
+        let (stick_thrust, stick_turn) = gamepad.sticks(axes);
+        // Keyboard input takes precedence over the gamepad
+        if stick_thrust.abs() > gamepad.dead_zone
+            && !pressed(&self.thrust)
+            && !pressed(&self.stop)
+        {
+            action.thrust = stick_thrust;
+        }
+        if stick_turn.abs() > gamepad.dead_zone
+            && !pressed(&self.turn_left)
+            && !pressed(&self.turn_right)
+        {
+            action.turn = -stick_turn;
+        }
+        action
+    }
+
+    /// Returns the weapon selected by keys and buttons pressed this frame.
+    pub fn select_weapon(
+        &self,
+        keys: &Input<KeyCode>,
+        buttons: &Input<GamepadButton>,
+        current: act::Weapon,
+    ) -> act::Weapon {
+        if let Some(weapon) = self
+            .weapons
+            .iter()
+            .zip(act::Weapon::ALL)
+            .find(|(key, _)| keys.just_pressed(**key))
+            .map(|(_, weapon)| weapon)
+        {
+            return weapon;
+        }
+        let next = keys.any_just_pressed(self.next_weapon.iter().copied())
+            || self.gamepad.as_ref().map_or(false, |gamepad| {
+                gamepad.next_weapon.iter().any(|t| {
+                    buttons.just_pressed(GamepadButton::new(
+                        Gamepad::new(gamepad.id),
+                        *t,
+                    ))
+                })
+            });
+        if next {
+            let index = act::Weapon::ALL.iter().position(|w| *w == current);
+            act::Weapon::ALL
+                [index.map_or(0, |i| i + 1) % act::Weapon::ALL.len()]
+        } else {
+            current
+        }
+    }
+}
+
+impl GamepadBindings {
+    /// Deflection of the thrust and turn axes.
+    fn sticks(&self, axes: &Axis<GamepadAxis>) -> (f32, f32) {
+        let gamepad = Gamepad::new(self.id);
+        let axis = |axis_type: GamepadAxisType| {
+            axes.get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        (axis(self.thrust_axis), axis(self.turn_axis))
+    }
+
+    fn action(
+        &self,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+    ) -> act::FighterAction {
+        let gamepad = Gamepad::new(self.id);
+        let pressed = |types: &[GamepadButtonType]| {
+            types
+                .iter()
+                .any(|t| buttons.pressed(GamepadButton::new(gamepad, *t)))
+        };
+        let (stick_thrust, stick_turn) = self.sticks(axes);
+        let thrust = if pressed(&self.thrust)
+            || stick_thrust >= self.thrust_threshold
+        {
+            act::Thrust::On
+        } else if pressed(&self.stop) || stick_thrust <= -self.thrust_threshold
+        {
+            act::Thrust::Stop
+        } else {
+            act::Thrust::Off
+        };
+        let turn = if stick_turn <= -self.full_turn_threshold {
+            act::Turn::Left
+        } else if stick_turn <= -self.quarter_turn_threshold {
+            act::Turn::QuarterLeft
+        } else if stick_turn >= self.full_turn_threshold {
+            act::Turn::Right
+        } else if stick_turn >= self.quarter_turn_threshold {
+            act::Turn::QuarterRight
+        } else {
+            act::Turn::None
+        };
+        let shoot = if pressed(&self.shoot) {
+            act::Shoot::On
+        } else {
+            act::Shoot::Off
+        };
+        let ability = if pressed(&self.shield) {
+            act::Ability::Shield
+        } else if pressed(&self.boost) {
+            act::Ability::Boost
+        } else if pressed(&self.blink) {
+            act::Ability::Blink
+        } else if pressed(&self.emp) {
+            act::Ability::Emp
+        } else {
+            act::Ability::None
+        };
+        act::FighterAction {
+            thrust,
+            turn,
+            shoot,
+            weapon: act::Weapon::Gun,
+            ability,
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            thrust: vec![KeyCode::W, KeyCode::Up],
+            stop: vec![KeyCode::S, KeyCode::Down],
+            turn_left: vec![KeyCode::A, KeyCode::Left],
+            turn_right: vec![KeyCode::D, KeyCode::Right],
+            shoot: vec![KeyCode::Space],
+            weapons: vec![
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+            ],
+            next_weapon: vec![KeyCode::Q],
+            shield: vec![KeyCode::E],
+            boost: vec![KeyCode::LShift],
+            blink: vec![KeyCode::F],
+            emp: vec![KeyCode::R],
+            target: vec![KeyCode::T],
+            gamepad: Some(GamepadBindings::default()),
+        }
+    }
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        GamepadBindings {
+            id: 0,
+            turn_axis: GamepadAxisType::LeftStickX,
+            thrust_axis: GamepadAxisType::LeftStickY,
+            thrust: vec![GamepadButtonType::RightTrigger2],
+            stop: vec![GamepadButtonType::LeftTrigger2],
+            shoot: vec![
+                GamepadButtonType::South,
+                GamepadButtonType::RightTrigger,
+            ],
+            next_weapon: vec![GamepadButtonType::North],
+            shield: vec![GamepadButtonType::LeftTrigger],
+            boost: vec![GamepadButtonType::LeftThumb],
+            blink: vec![GamepadButtonType::East],
+            emp: vec![GamepadButtonType::West],
+            quarter_turn_threshold: 0.2,
+            full_turn_threshold: 0.75,
+            thrust_threshold: 0.5,
+            dead_zone: 0.1,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn human_input(
+    mut action_events: EventWriter<(act::FighterAction, Entity)>,
+    mut continuous_events: EventWriter<(act::ContinuousAction, Entity)>,
+    remaining_time: Res<RemainingTime>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    players: NonSend<Players>,
+) {
+    // Networked games sample inputs in `net::sync_inputs` instead
+    if remaining_time.0 as u32 % settings.action_interval != 0
+        || settings.network.is_some()
+    {
+        return;
+    }
+    for (i, player) in players.0.iter().enumerate() {
+        let continuous = settings.continuous_control.contains(&i);
+        for pilot in &player.humans {
+            let (fighter, bindings) = match (
+                pilot.fighter,
+                settings.input.players.get(pilot.bindings),
+            ) {
+                (Some(fighter), Some(bindings)) => (fighter, bindings),
+                _ => continue,
+            };
+            if continuous {
+                continuous_events.send((
+                    bindings.continuous_action(
+                        &keys,
+                        &buttons,
+                        &axes,
+                        pilot.weapon,
+                    ),
+                    fighter,
+                ));
+            } else {
+                action_events.send((
+                    bindings.action(&keys, &buttons, &axes, pilot.weapon),
+                    fighter,
+                ));
+            }
+        }
+    }
+}
+
+/// Weapons are selected on key press rather than sampled every action
+/// interval, so this runs every frame.
+pub(crate) fn select_weapons(
+    mut players: NonSendMut<Players>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    for pilot in players.0.iter_mut().flat_map(|p| &mut p.humans) {
+        if let Some(bindings) = settings.input.players.get(pilot.bindings) {
+            pilot.weapon =
+                bindings.select_weapon(&keys, &buttons, pilot.weapon);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct State {
+        keys: Input<KeyCode>,
+        buttons: Input<GamepadButton>,
+        axes: Axis<GamepadAxis>,
+    }
+
+    impl State {
+        fn new() -> State {
+            State {
+                keys: Input::default(),
+                buttons: Input::default(),
+                axes: Axis::default(),
+            }
+        }
+
+        fn stick(&mut self, id: usize, axis: GamepadAxisType, value: f32) {
+            self.axes
+                .set(GamepadAxis::new(Gamepad::new(id), axis), value);
+        }
+
+        fn action(&self, bindings: &Bindings) -> act::FighterAction {
+            bindings.action(
+                &self.keys,
+                &self.buttons,
+                &self.axes,
+                act::Weapon::Gun,
+            )
+        }
+    }
+
+    #[test]
+    fn keyboard() {
+        let mut state = State::new();
+        state.keys.press(KeyCode::W);
+        state.keys.press(KeyCode::A);
+        state.keys.press(KeyCode::Space);
+        state.keys.press(KeyCode::E);
+        let action = state.action(&Bindings::wasd());
+        assert_eq!(action.thrust, act::Thrust::On);
+        assert_eq!(action.turn, act::Turn::Left);
+        assert_eq!(action.shoot, act::Shoot::On);
+        assert_eq!(action.ability, act::Ability::Shield);
+
+        // The arrow keys belong to the second player
+        let action = state.action(&Bindings::arrows());
+        assert_eq!(action.thrust, act::Thrust::Off);
+        assert_eq!(action.turn, act::Turn::None);
+        assert_eq!(action.shoot, act::Shoot::Off);
+    }
+
+    #[test]
+    fn gamepad_sticks() {
+        let bindings = Bindings::default();
+        let mut state = State::new();
+        state.stick(0, GamepadAxisType::LeftStickX, -0.5);
+        state.stick(0, GamepadAxisType::LeftStickY, -0.6);
+        let action = state.action(&bindings);
+        assert_eq!(action.turn, act::Turn::QuarterLeft);
+        assert_eq!(action.thrust, act::Thrust::Stop);
+
+        state.stick(0, GamepadAxisType::LeftStickX, 0.9);
+        state.stick(0, GamepadAxisType::LeftStickY, 0.1);
+        let action = state.action(&bindings);
+        assert_eq!(action.turn, act::Turn::Right);
+        assert_eq!(action.thrust, act::Thrust::Off);
+
+        // Other gamepads are ignored
+        let action = state.action(&Bindings::arrows());
+        assert_eq!(action.turn, act::Turn::None);
+    }
+
+    #[test]
+    fn keyboard_overrides_gamepad() {
+        let mut state = State::new();
+        state.keys.press(KeyCode::A);
+        state.stick(0, GamepadAxisType::LeftStickX, 1.0);
+        state.buttons.press(GamepadButton::new(
+            Gamepad::new(0),
+            GamepadButtonType::South,
+        ));
+        let action = state.action(&Bindings::default());
+        assert_eq!(action.turn, act::Turn::Left);
+        assert_eq!(action.shoot, act::Shoot::On);
+    }
+
+    #[test]
+    fn weapon_selection() {
+        let bindings = Bindings::default();
+        let mut state = State::new();
+        state.keys.press(KeyCode::Key3);
+        let weapon = bindings.select_weapon(
+            &state.keys,
+            &state.buttons,
+            act::Weapon::Gun,
+        );
+        assert_eq!(weapon, act::Weapon::Laser);
+
+        let mut state = State::new();
+        state.keys.press(KeyCode::Q);
+        let weapon = bindings.select_weapon(
+            &state.keys,
+            &state.buttons,
+            act::Weapon::Mine,
+        );
+        assert_eq!(weapon, act::Weapon::Gun);
+    }
+}
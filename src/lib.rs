@@ -1,11 +1,38 @@
+mod ability;
+mod agent_debug;
+pub mod camera;
+#[cfg(not(target_arch = "wasm32"))]
+mod demonstrations;
+mod event;
+mod history;
+mod hud;
+mod ids;
+pub mod input;
+mod menu;
+#[cfg(not(target_arch = "wasm32"))]
+mod metrics;
+mod minimap;
+pub mod net;
+mod particles;
+mod powerup;
 #[cfg(feature = "python")]
 pub mod python;
+mod raster;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+pub mod storage;
+mod targeting;
+mod weapon;
 
 use bevy::app::AppExit;
 use bevy::app::ScheduleRunnerSettings;
 use bevy::asset::AssetPlugin;
 use bevy::core_pipeline::bloom::BloomSettings;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::ecs::schedule::ShouldRun;
+use bevy::ecs::system::{IntoPipeSystem, SystemParam};
 use bevy::prelude::shape::{Circle, Quad};
 use bevy::render::mesh::Indices;
 use bevy::render::mesh::MeshPlugin;
@@ -16,17 +43,27 @@ use bevy::time::FixedTimestep;
 use bevy::{log, prelude::*};
 use bevy_rapier2d::prelude::*;
 use entity_gym_rs::agent::{
-    self, Agent, AgentOps, Obs, RogueNetAsset, RogueNetAssetLoader,
+    self, Agent, AgentOps, Featurizable, Obs, RogueNetAsset,
+    RogueNetAssetLoader,
 };
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 use std::time::Duration;
 
+use ids::RecordSpawnOrder;
+use input::InputConfig;
+use net::NetworkSettings;
 #[cfg(feature = "python")]
 use python::Config;
 
 pub const LAUNCHER_TITLE: &str = "Bevy Starfighter";
+/// Width and height that `Settings.arena_size` must exceed, which leaves room
+/// for power-ups to spawn clear of the walls.
+pub const MIN_ARENA_SIZE: f32 = 2.0 * powerup::WALL_CLEARANCE;
 
 const FIGHTER_COLORS: [Color; 2] = [
     Color::Hsla {
@@ -44,11 +81,24 @@ const FIGHTER_COLORS: [Color; 2] = [
 ];
 const BULLET_COLORS: [Color; 2] =
     [Color::rgb(0.9, 0.9, 1.0), Color::rgb(1.0, 0.7, 0.7)];
+/// Thrust force of fighters before stat and mass multipliers.
+const BASE_THRUST: f32 = 1000000.0;
+/// Default `Settings.ram_damage_per_impulse`: ramming an asteroid with the
+/// momentum a fighter gains from one second of base thrust deals one hull
+/// point.
+const RAM_DAMAGE_PER_IMPULSE: f32 = 1.0 / BASE_THRUST;
+/// Fraction of a hull point below which the contact forces of one step aren't
+/// reported, since they barely add to ram damage.
+const RAM_DAMAGE_RESOLUTION: f32 = 0.01;
+/// Mixed into `Settings.seed` for `FireRng`, whose numbers would otherwise
+/// repeat those of `RngState`.
+const FIRE_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
 
+/// Policy assets of players controlled by `Controller::Policy`.
 #[derive(Resource)]
-struct OpponentHandle(Option<Handle<RogueNetAsset>>);
+struct PolicyHandles(Vec<Option<Handle<RogueNetAsset>>>);
 
-#[derive(Default, Debug, Resource)]
+#[derive(Clone, Default, Debug, Resource, Serialize, Deserialize)]
 struct Stats {
     bullets_fired: usize,
     timesteps: usize,
@@ -56,15 +106,74 @@ struct Stats {
     destroyed_asteroids: usize,
     destroyed_opponents: usize,
     destroyed_allies: usize,
+    /// Kills, deaths and assists of each player.
+    players: Vec<PlayerStats>,
+    /// Players that damaged each fighter, who are credited with an assist
+    /// if someone else destroys it.
+    #[serde(skip)]
+    damagers: HashMap<Entity, Vec<usize>>,
+    /// See `Settings.kill_rewards`.
+    #[serde(skip)]
+    kill_rewards: bool,
+}
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+struct PlayerStats {
+    kills: usize,
+    deaths: usize,
+    assists: usize,
+    /// Asteroids destroyed by the player's projectiles.
+    destroyed_asteroids: usize,
+    /// Asteroids destroyed by ramming them, which isn't rewarded since
+    /// fighters would learn to trade hull for score.
+    #[serde(default)]
+    rammed_asteroids: usize,
 }
 
 impl Stats {
+    fn new(settings: &Settings) -> Stats {
+        Stats {
+            kill_rewards: settings.kill_rewards,
+            ..Stats::default()
+        }
+    }
+
+    fn player(&self, player: usize) -> PlayerStats {
+        self.players.get(player).cloned().unwrap_or_default()
+    }
+
+    fn player_mut(&mut self, player: usize) -> &mut PlayerStats {
+        if self.players.len() <= player {
+            self.players.resize(player + 1, PlayerStats::default());
+        }
+        &mut self.players[player]
+    }
+
     fn player0_score(&self) -> f32 {
-        (self.destroyed_asteroids + self.destroyed_opponents) as f32
+        if self.kill_rewards {
+            let stats = self.player(0);
+            (stats.destroyed_asteroids + stats.kills) as f32
+        } else {
+            (self.destroyed_asteroids + self.destroyed_opponents) as f32
+        }
+    }
+
+    fn opponent_score(&self, player: usize) -> f32 {
+        let destroyed = if self.kill_rewards {
+            let stats = self.player(player);
+            stats.kills as f32 + 0.5 * stats.assists as f32
+        } else {
+            self.destroyed_allies as f32
+        };
+        10.0 * destroyed - self.timesteps as f32 * 0.001
     }
 
-    fn player1_score(&self) -> f32 {
-        10.0 * self.destroyed_allies as f32 - self.timesteps as f32 * 0.001
+    fn score(&self, player: usize) -> f32 {
+        if player == 0 {
+            self.player0_score()
+        } else {
+            self.opponent_score(player)
+        }
     }
 }
 
@@ -86,13 +195,137 @@ pub struct Settings {
     pub respawn_time: u32,
     pub opponent_stats_multiplier: f32,
     pub max_game_length: u32,
+    /// Shorthand for making player 0 `Controller::Human(vec![0])`, only used
+    /// when `controllers` is empty.
     pub human_player: bool,
     /// The interval at which the number of opponents is increased by one.
     pub difficulty_ramp: u32,
     pub opponent_policy: Option<String>,
+    /// Controller of each player. When empty, controllers are derived from
+    /// `human_player`, `agent_path`, `opponent_policy` and `random_ai`.
+    pub controllers: Vec<Controller>,
+    /// Players whose humans and bots act with `act::ContinuousAction` rather
+    /// than `act::FighterAction`.
+    pub continuous_control: Vec<usize>,
     pub physics_debug_render: bool,
     pub log_diagnostics: bool,
     pub disable_bloom: bool,
+    /// Keyboard and gamepad bindings of local human players.
+    pub input: InputConfig,
+    /// Play against humans on other machines through a relay server.
+    pub network: Option<NetworkSettings>,
+    /// Address on which to accept connections from `Controller::Bot` agents.
+    pub bot_server: Option<String>,
+    /// Frames between power-up spawns, 0 disables power-ups.
+    pub powerup_interval: u32,
+    pub max_powerups: u32,
+    /// Frames after which an uncollected power-up disappears.
+    pub powerup_lifetime: u32,
+    /// Hull points of player 0's fighters.
+    pub hull: f32,
+    /// Hull points of all other fighters.
+    pub opponent_hull: f32,
+    /// Rewards each player for its own kills, assists and asteroids rather
+    /// than for all destroyed opponents, allies and asteroids. Off by default,
+    /// since the configurations in `train.ron` and `pve.ron` were tuned for
+    /// the original rewards.
+    pub kill_rewards: bool,
+    /// Hull damage per unit of impulse when ramming an asteroid, see
+    /// `RAM_DAMAGE_PER_IMPULSE`.
+    pub ram_damage_per_impulse: f32,
+    /// Width and height of the arena, which is centered on the origin.
+    pub arena_size: Vec2,
+    /// Start with the overlay showing what agents observe and decide, which
+    /// can also be toggled with F3.
+    pub agent_debug_render: bool,
+    /// Initial mode of the camera, which can be changed while playing.
+    pub camera_mode: camera::CameraMode,
+    /// Distance up to which fighters observe other entities, unlimited if
+    /// `None`.
+    pub sensor_range: Option<f32>,
+    /// Number of past states of each fighter included in observations, see
+    /// `history`.
+    pub observation_history: usize,
+    /// Agents observe weapons, abilities and hull points and choose weapons
+    /// and abilities. Off by default, since the bundled policies were trained
+    /// on `entity::classic` and `act::classic`.
+    pub agent_loadout: bool,
+    /// Agents observe the ID of every entity as its first feature, see
+    /// `entity::Identified`. Off by default for the same reason as
+    /// `agent_loadout`.
+    pub observe_ids: bool,
+    /// Render every frame on the CPU with this width in pixels, see `raster`.
+    pub software_render_width: Option<u32>,
+    /// Directory to which software rendered frames are written as PNGs.
+    pub record_frames: Option<String>,
+    /// File to which the first game is written as a `replay::Replay`.
+    pub record_replay: Option<String>,
+    /// File to which metrics of every game are appended, see `metrics`.
+    pub metrics_log: Option<String>,
+    /// File to which actions of local humans are written along with their
+    /// observations, see `demonstrations`.
+    pub record_demonstrations: Option<String>,
+    /// Whether the minimap only shows what player 0's fighters sense rather
+    /// than the whole arena.
+    pub minimap_sensor_range: bool,
+}
+
+/// Determines who chooses the actions of a player's fighters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Controller {
+    /// Local humans, one fighter for each binding set index into
+    /// `Settings.input`.
+    Human(Vec<usize>),
+    /// RogueNet policy loaded from `assets/policies/<name>.roguenet`.
+    Policy(String),
+    /// Agent checkpoint loaded from a local path.
+    Checkpoint(String),
+    Random,
+    /// Hand-written heuristic that chases and shoots the nearest enemy.
+    Scripted,
+    /// Fighters take no actions.
+    Idle,
+    /// Human on another machine whose actions are received over the network,
+    /// see `Settings.network`.
+    Remote,
+    /// External process connected to the bot server, see
+    /// `Settings.bot_server`.
+    Bot,
+}
+
+impl FromStr for Controller {
+    type Err = String;
+
+    /// Parses `human:0,1`, `policy:<name>`, `checkpoint:<path>`, `random`,
+    /// `scripted`, `idle`, `remote` or `bot`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        match (kind, arg) {
+            ("human", None) => Ok(Controller::Human(vec![0])),
+            ("human", Some(bindings)) => bindings
+                .split(',')
+                .map(|b| {
+                    b.trim()
+                        .parse()
+                        .map_err(|_| format!("invalid binding set: {}", b))
+                })
+                .collect::<Result<Vec<usize>, _>>()
+                .map(Controller::Human),
+            ("policy", Some(name)) => Ok(Controller::Policy(name.to_string())),
+            ("checkpoint", Some(path)) => {
+                Ok(Controller::Checkpoint(path.to_string()))
+            }
+            ("random", None) => Ok(Controller::Random),
+            ("scripted", None) => Ok(Controller::Scripted),
+            ("idle", None) => Ok(Controller::Idle),
+            ("remote", None) => Ok(Controller::Remote),
+            ("bot", None) => Ok(Controller::Bot),
+            _ => Err(format!("invalid controller: {}", s)),
+        }
+    }
 }
 
 #[derive(Component)]
@@ -105,6 +338,65 @@ impl Settings {
         1.0 / self.frame_rate * self.frameskip as f32
     }
 
+    /// Whether the game opens with `menu::GameState::Menu` rather than
+    /// starting right away.
+    fn starts_in_menu(&self) -> bool {
+        !self.headless && self.network.is_none()
+    }
+
+    /// Whether every update advances the simulation by exactly one fixed
+    /// timestep, which networked games and replays rely on to be
+    /// reproducible.
+    fn lockstep(&self) -> bool {
+        self.network.is_some() || self.record_replay.is_some()
+    }
+
+    /// Whether power-ups spawn and are part of observations.
+    fn powerups_enabled(&self) -> bool {
+        self.powerup_interval > 0
+    }
+
+    /// Smallest contact force of fighters that is reported, which deals
+    /// `RAM_DAMAGE_RESOLUTION` hull points when sustained for a step.
+    fn ram_force_threshold(&self) -> f32 {
+        RAM_DAMAGE_RESOLUTION
+            / (self.ram_damage_per_impulse * self.timestep_secs())
+    }
+
+    /// Distance from the center of the arena to its right and top edges.
+    fn arena_half_size(&self) -> Vec2 {
+        self.arena_size / 2.0
+    }
+
+    /// Number of fighters of each AI opponent after `timesteps`.
+    fn difficulty(&self, timesteps: usize) -> usize {
+        1 + timesteps / self.difficulty_ramp as usize
+    }
+
+    /// Resolves the controller of every player, falling back to the legacy
+    /// flags when `controllers` is empty.
+    fn player_controllers(&self) -> Vec<Controller> {
+        let player_count =
+            (self.players as usize).max(self.controllers.len()).max(1);
+        let opponent = match (&self.agent_path, &self.opponent_policy) {
+            (Some(path), _) => Controller::Checkpoint(path.clone()),
+            (None, Some(name)) => Controller::Policy(name.clone()),
+            (None, None) if self.random_ai => Controller::Random,
+            (None, None) => Controller::Idle,
+        };
+        (0..player_count)
+            .map(|i| match self.controllers.get(i) {
+                Some(controller) => controller.clone(),
+                None if i == 0
+                    && (self.human_player || opponent == Controller::Idle) =>
+                {
+                    Controller::Human(vec![0])
+                }
+                None => opponent.clone(),
+            })
+            .collect()
+    }
+
     fn ccd(&self) -> Ccd {
         if self.continuous_collision_detection {
             Ccd::enabled()
@@ -120,31 +412,91 @@ pub fn base_app(
 ) -> App {
     let mut main_system = SystemSet::new()
         .with_system(ai)
+        .with_system(scripted_ai)
         .with_system(check_boundary_collision)
         .with_system(spawn_asteroids)
+        .with_system(weapon::steer_missiles)
+        .with_system(weapon::arm_mines)
+        .with_system(powerup::spawn_powerups.after(cooldowns))
+        .with_system(powerup::expire_powerups.after(detect_collisions))
         .with_system(detect_collisions)
-        .with_system(expire_bullets)
-        .with_system(fighter_actions.after(ai).after(keyboard_events))
+        .with_system(expire_bullets.after(detect_collisions))
+        .with_system(
+            fighter_actions
+                .after(ai)
+                .after(scripted_ai)
+                .after(input::human_input),
+        )
         .with_system(cooldowns.after(fighter_actions))
+        .with_system(
+            targeting::select_targets.after(ai).before(fighter_actions),
+        )
+        .with_system(
+            targeting::auto_aim
+                .after(fighter_actions)
+                .after(targeting::select_targets),
+        )
+        .with_system(ability::tick_abilities.after(fighter_actions))
+        .with_system(
+            ability::emp.after(fighter_actions).after(detect_collisions),
+        )
+        .with_system(
+            event::update_stats
+                .after(detect_collisions)
+                .after(fighter_actions)
+                .before(reset),
+        )
         .with_system(respawn.after(cooldowns))
         .with_system(reset.after(respawn));
-    if settings.fixed_timestep {
-        main_system = main_system.with_run_criteria(FixedTimestep::step(
-            settings.timestep_secs() as f64,
-        ));
+    if settings.observation_history > 0 {
+        main_system = main_system.with_system(
+            history::record_history
+                .after(fighter_actions)
+                .before(cooldowns),
+        );
     }
-    let timestep_mode = if settings.frameskip > 1 || settings.headless {
-        TimestepMode::Fixed {
-            dt: 1.0 * settings.frameskip as f32 / settings.frame_rate as f32,
-            substeps: 1,
-        }
+    #[cfg(not(target_arch = "wasm32"))]
+    if settings.record_demonstrations.is_some() {
+        main_system = main_system.with_system(
+            demonstrations::record_demonstrations
+                .after(input::human_input)
+                .before(fighter_actions),
+        );
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if settings.record_replay.is_some() {
+        main_system = main_system.with_system(
+            replay::record_actions
+                .after(fighter_actions)
+                .before(cooldowns),
+        );
+    }
+    if settings.fixed_timestep && settings.lockstep() {
+        log::warn!("Ignoring fixed_timestep in networked game or replay");
+    }
+    main_system = if settings.fixed_timestep && !settings.lockstep() {
+        main_system.with_run_criteria(
+            FixedTimestep::step(settings.timestep_secs() as f64)
+                .pipe(simulation_gate),
+        )
     } else {
-        TimestepMode::Variable {
-            max_dt: 1.0 / settings.frame_rate,
-            time_scale: 1.0,
-            substeps: 1,
-        }
+        main_system.with_run_criteria(every_frame.pipe(simulation_gate))
     };
+    let timestep_mode =
+        if settings.frameskip > 1 || settings.headless || settings.lockstep() {
+            TimestepMode::Fixed {
+                dt: 1.0 * settings.frameskip as f32
+                    / settings.frame_rate as f32,
+                substeps: 1,
+            }
+        } else {
+            TimestepMode::Variable {
+                max_dt: 1.0 / settings.frame_rate,
+                time_scale: 1.0,
+                substeps: 1,
+            }
+        };
+    let controllers = settings.player_controllers();
     let mut app = App::new();
     app.add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0))
         .insert_resource(RapierConfiguration {
@@ -152,46 +504,107 @@ pub fn base_app(
             timestep_mode,
             ..default()
         })
-        .insert_resource(OpponentHandle(None))
+        .insert_resource(PolicyHandles(vec![]))
         .insert_resource(RngState(SmallRng::seed_from_u64(settings.seed)))
+        .insert_resource(FireRng(SmallRng::seed_from_u64(
+            settings.seed ^ FIRE_SEED_SALT,
+        )))
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
         .insert_resource(RemainingTime(settings.max_game_length as i32))
-        .insert_resource(Stats::default())
+        .insert_resource(Stats::new(settings))
+        .init_resource::<CollisionQueue>()
+        .init_resource::<SimulationGate>()
+        .init_resource::<history::ObservationHistory>()
+        .init_resource::<ids::EntityIds>()
         .insert_resource(settings.clone())
         .insert_non_send_resource(Players(
             agents
                 .into_iter()
-                .map(|a| Player {
-                    agent: a,
-                    ids: vec![],
-                    respawns: vec![],
+                .enumerate()
+                .map(|(i, agent)| {
+                    Player::new(
+                        agent,
+                        controllers.get(i).unwrap_or(&Controller::Idle),
+                    )
                 })
                 .collect(),
         ))
         .add_event::<GameOver>()
+        .add_event::<GameSummary>()
         .add_event::<(act::FighterAction, Entity)>()
-        .add_system_set(main_system);
+        .add_event::<(act::ContinuousAction, Entity)>()
+        .add_event::<(act::Target, Entity)>()
+        .add_event::<ability::EmpBlast>()
+        .add_event::<event::BulletFired>()
+        .add_event::<event::FighterHit>()
+        .add_event::<event::ShieldBroken>()
+        .add_event::<event::FighterDestroyed>()
+        .add_event::<event::AsteroidHit>()
+        .add_event::<event::AsteroidDestroyed>()
+        .add_event::<event::PlayerRespawned>()
+        .add_event::<particles::Effect>()
+        .add_system_set(main_system)
+        .add_system_to_stage(CoreStage::PreUpdate, ids::assign_ids)
+        .add_system(apply_simulation_gate);
+    #[cfg(not(target_arch = "wasm32"))]
+    if settings.record_replay.is_some() {
+        app.init_resource::<replay::Recorder>()
+            .add_system(replay::save_replay.after(reset));
+    }
     app
 }
 
+fn every_frame() -> ShouldRun {
+    ShouldRun::Yes
+}
+
+/// Stops the simulation while the `SimulationGate` is closed, piped after the
+/// run criteria of the main system set.
+fn simulation_gate(
+    In(should_run): In<ShouldRun>,
+    gate: Res<SimulationGate>,
+) -> ShouldRun {
+    if gate.is_open() {
+        should_run
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn apply_simulation_gate(
+    gate: Res<SimulationGate>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    if gate.is_changed() {
+        rapier_config.physics_pipeline_active = gate.is_open();
+    }
+}
+
+/// Builds the game, panicking where `try_app` returns an error.
 pub fn app(settings: Settings, agents: Vec<Box<dyn Agent>>) -> App {
+    try_app(settings, agents).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Builds the game, or returns an error if the arena is too small, the relay
+/// of a networked game can't be reached or the bot server can't be bound.
+pub fn try_app(
+    settings: Settings,
+    agents: Vec<Box<dyn Agent>>,
+) -> Result<App, String> {
+    if settings.arena_size.cmple(Vec2::splat(MIN_ARENA_SIZE)).any() {
+        return Err(format!(
+            "arena of {}x{} is too small, both sides must exceed {}",
+            settings.arena_size.x, settings.arena_size.y, MIN_ARENA_SIZE
+        ));
+    }
     let mut agents: Vec<Option<Box<dyn Agent>>> =
         agents.into_iter().map(Some).collect();
-    if settings.human_player {
-        agents.push(None);
-    }
-    while agents.len() < settings.players as usize {
-        let agent = match &settings.agent_path {
-            Some(path) => Some(agent::load(path)),
-            None => {
-                if settings.random_ai {
-                    Some(agent::random())
-                } else {
-                    None
-                }
-            }
-        };
-        agents.push(agent);
+    for controller in settings.player_controllers().iter().skip(agents.len()) {
+        agents.push(match controller {
+            Controller::Checkpoint(path) => Some(agent::load(path)),
+            Controller::Random => Some(agent::random()),
+            _ => None,
+        });
     }
     let mut app = base_app(&settings, agents);
     if settings.headless {
@@ -219,12 +632,45 @@ pub fn app(settings: Settings, agents: Vec<Box<dyn Agent>>) -> App {
             },
             ..default()
         }))
-        .add_system(keyboard_events)
+        .add_system(input::select_weapons.before(input::human_input))
+        .add_system(input::human_input)
+        .add_system(targeting::human_targets)
+        .add_plugin(camera::CameraPlugin)
+        .add_plugin(particles::ParticlePlugin)
+        .add_plugin(hud::HudPlugin)
+        .add_plugin(minimap::MinimapPlugin)
+        .add_plugin(agent_debug::AgentDebugPlugin)
+        .add_plugin(menu::MenuPlugin)
+        .add_plugin(storage::StoragePlugin)
         .add_startup_system(setup);
         if settings.physics_debug_render {
             app.add_plugin(RapierDebugRenderPlugin::default());
         }
     }
+    if let Some(network) = &settings.network {
+        let transport =
+            net::Transport::connect(&network.relay).map_err(|e| {
+                format!("failed to connect to relay {}: {}", network.relay, e)
+            })?;
+        app.insert_non_send_resource(transport)
+            .add_plugin(net::NetPlugin);
+    }
+    if settings.software_render_width.is_some() {
+        app.add_plugin(raster::RasterPlugin);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(address) = &settings.bot_server {
+        app.insert_non_send_resource(remote::RemoteAgentServer::bind(address)?)
+            .add_plugin(remote::RemoteAgentPlugin);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if settings.metrics_log.is_some() {
+        app.add_plugin(metrics::MetricsPlugin);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if settings.record_demonstrations.is_some() {
+        app.add_plugin(demonstrations::DemonstrationsPlugin);
+    }
     if settings.log_diagnostics {
         app.add_plugin(LogDiagnosticsPlugin::default())
             .add_plugin(FrameTimeDiagnosticsPlugin::default());
@@ -236,31 +682,53 @@ pub fn app(settings: Settings, agents: Vec<Box<dyn Agent>>) -> App {
         .add_system(update_score)
         .add_startup_system(load_opponent_policy)
         .add_startup_system(spawn_highscore_text);
-    app
+    Ok(app)
 }
 
+/// Settings of the training environment with index `seed`.
 #[cfg(feature = "python")]
-pub fn run_training(
-    config: Config,
-    agents: Vec<entity_gym_rs::agent::TrainAgent>,
-    seed: u64,
-) {
-    let settings = Settings {
+fn training_settings(config: &Config, seed: u64) -> Settings {
+    Settings {
         seed,
         frameskip: config.frameskip,
         action_interval: config.act_interval,
         headless: true,
         continuous_collision_detection: config.ccd,
+        sensor_range: config.sensor_range,
+        observation_history: config.history,
+        agent_loadout: config.loadout,
+        observe_ids: config.ids,
+        kill_rewards: config.kill_rewards,
+        powerup_interval: config.powerup_interval,
+        software_render_width: config.render_width,
         ..Settings::default()
-    };
-    app(
-        settings,
+    }
+}
+
+#[cfg(feature = "python")]
+pub fn run_training(
+    config: Config,
+    agents: Vec<entity_gym_rs::agent::TrainAgent>,
+    seed: u64,
+) {
+    let mut app = app(
+        training_settings(&config, seed),
         agents
             .into_iter()
             .map(|x| Box::new(x) as Box<dyn Agent>)
             .collect(),
-    )
-    .run();
+    );
+    if let (Some(frames), Some(_)) = (config.frames, config.render_width) {
+        app.insert_resource(python::FrameSink {
+            frames,
+            env_index: seed,
+        })
+        .add_system_to_stage(
+            CoreStage::Last,
+            python::publish_frame.after(raster::rasterize),
+        );
+    }
+    app.run();
 }
 
 #[cfg(feature = "python")]
@@ -294,50 +762,23 @@ fn reset(
     mut fighter: Query<Entity, With<Fighter>>,
     mut jets: Query<Entity, With<Jet>>,
     mut bullets: Query<Entity, With<Bullet>>,
+    mut powerups: Query<Entity, With<powerup::PowerUp>>,
     mut remaining_time: ResMut<RemainingTime>,
     mut players: NonSendMut<Players>,
+    mut summaries: EventWriter<GameSummary>,
 ) {
     if let Some(GameOver) = game_over.iter().next() {
-        for (
-            i,
-            Player {
-                agent,
-                ids,
-                respawns,
-            },
-        ) in players.0.iter_mut().enumerate()
-        {
-            let score = if i == 0 {
-                stats.player0_score()
-            } else {
-                stats.player1_score()
-            };
-            if let Some(p) = agent {
-                p.game_over(
-                    &Obs::new(score)
-                        .metric("bullets_fired", stats.bullets_fired as f32)
-                        .metric("timesteps", stats.timesteps as f32)
-                        .metric("bullet_hits", stats.bullet_hits as f32)
-                        .metric(
-                            "destroyed_asteroids",
-                            stats.destroyed_asteroids as f32,
-                        )
-                        .metric(
-                            "destroyed_opponents",
-                            stats.destroyed_opponents as f32,
-                        )
-                        .metric(
-                            "destroyed_allies",
-                            stats.destroyed_allies as f32,
-                        )
-                        .metric(&format!("player_{}_score", i), score),
-                );
+        for (i, player) in players.0.iter_mut().enumerate() {
+            if let Some(agent) = &mut player.agent {
+                agent.game_over(i, &stats);
             }
-            ids.clear();
-            respawns.clear();
+            player.clear();
         }
         log::info!("Game Over! Stats: {:?}", stats);
-        *stats = Stats::default();
+        summaries.send(GameSummary(std::mem::replace(
+            &mut *stats,
+            Stats::new(&settings),
+        )));
         // Despawn all entities
         for entity in query.iter_mut() {
             cmd.entity(entity).despawn_recursive();
@@ -351,6 +792,9 @@ fn reset(
         for entity in bullets.iter_mut() {
             cmd.entity(entity).despawn_recursive();
         }
+        for entity in powerups.iter_mut() {
+            cmd.entity(entity).despawn_recursive();
+        }
         remaining_time.0 = settings.max_game_length as i32;
         spawn_players(
             &settings,
@@ -369,13 +813,16 @@ fn setup(
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut players: NonSendMut<Players>,
 ) {
-    spawn_players(
-        &settings,
-        &mut cmd,
-        &mut meshes,
-        &mut materials,
-        &mut players,
-    );
+    // Otherwise the players are spawned once the game is started from the menu
+    if !settings.starts_in_menu() {
+        spawn_players(
+            &settings,
+            &mut cmd,
+            &mut meshes,
+            &mut materials,
+            &mut players,
+        );
+    }
     if settings.physics_debug_render || settings.disable_bloom {
         cmd.spawn(Camera2dBundle::default());
     } else {
@@ -394,7 +841,7 @@ fn setup(
         ));
     }
     // Spawn rectangular bounds
-    let bounds = Quad::new(Vec2::new(2000.0, 1000.0));
+    let bounds = Quad::new(settings.arena_size);
     let handle = meshes.add(bounds.into());
     cmd.spawn(ColorMesh2dBundle {
         mesh: handle.into(),
@@ -412,15 +859,19 @@ fn spawn_players(
     materials: &mut ResMut<Assets<ColorMaterial>>,
     players: &mut NonSendMut<Players>,
 ) {
-    spawn_fighter(
-        &mut players.0[0],
-        0,
-        settings,
-        cmd,
-        meshes,
-        materials,
-        Vec3::new(0.0, 0.0, 0.5),
-    )
+    let count = players.0[0].target_fighter_count(0, settings, 0);
+    for i in 0..count {
+        let x = 150.0 * (i as f32 - (count - 1) as f32 / 2.0);
+        spawn_fighter(
+            &mut players.0[0],
+            0,
+            settings,
+            cmd,
+            meshes,
+            materials,
+            Vec3::new(x, 0.0, 0.5),
+        );
+    }
 }
 
 fn spawn_fighter(
@@ -450,16 +901,21 @@ fn spawn_fighter(
             Vect::new(0.3, -0.3),
         ])
     };
+    let max_health = if player_id == 0 {
+        settings.hull
+    } else {
+        settings.opponent_hull
+    };
     // Account for larger mass
     let acceleration_multiplier = if player_id == 0 { 5.0 } else { 1.0 };
     let deceleration_multiplier = if player_id == 0 { 3.0 } else { 1.0 };
     let entity = cmd
         .spawn(Fighter {
             max_velocity: 1000.0 * stats_multiplier,
-            acceleration: 1000000.0
+            acceleration: BASE_THRUST
                 * stats_multiplier
                 * acceleration_multiplier,
-            deceleration: 1000000.0
+            deceleration: BASE_THRUST
                 * stats_multiplier
                 * deceleration_multiplier,
             drag_exp: 1.5,
@@ -470,20 +926,26 @@ fn spawn_fighter(
             bullet_lifetime: if player_id == 0 { 24 } else { 150 },
             bullet_cooldown: if player_id == 0 { 24 } else { 72 },
             remaining_bullet_cooldown: 0,
+            laser_charge: 0,
+            rapid_fire_time: 0,
+            speed_boost_time: 0,
             is_turning: false,
+            target: None,
             player_id,
-            act_interval: match player.agent {
-                Some(_) => settings
-                    .ai_action_interval
-                    .unwrap_or(settings.action_interval),
-                None => settings.action_interval,
-            },
+            number: player.spawned,
+            act_interval: player.fighter_act_interval(settings),
             shield_active: player_id == 0,
             shield_cooldown: 0,
             shield_recharge_period: 300,
+            health: max_health,
+            max_health,
         })
+        .record_spawn_order()
         .insert(RigidBody::Dynamic)
-        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(
+            ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS,
+        )
+        .insert(ContactForceEventThreshold(settings.ram_force_threshold()))
         .insert(collider.unwrap())
         .insert(Velocity {
             linvel: Vec2::new(0.0, 0.0),
@@ -498,6 +960,7 @@ fn spawn_fighter(
             torque_impulse: 0.0,
         })
         .insert(CollisionType::Fighter)
+        .insert(ability::Abilities::new())
         .insert(MaterialMesh2dBundle {
             mesh: meshes
                 .add(if player_id == 0 {
@@ -509,7 +972,8 @@ fn spawn_fighter(
             transform: Transform::default()
                 .with_scale(Vec3::splat(50.0))
                 .with_translation(Vec3::new(position.x, position.y, 1.0)),
-            material: materials.add(FIGHTER_COLORS[player_id].into()),
+            material: materials
+                .add(FIGHTER_COLORS[player_id % FIGHTER_COLORS.len()].into()),
             ..default()
         })
         .with_children(|parent| {
@@ -539,11 +1003,32 @@ fn spawn_fighter(
                     })
                     .insert(Shield);
             }
+            // Spawned last, the jet and shield are looked up by index
+            parent
+                .spawn(ColorMesh2dBundle {
+                    mesh: meshes
+                        .add(Quad::new(Vec2::new(1.6, 0.15)).into())
+                        .into(),
+                    material: materials.add(ColorMaterial::from(Color::hsla(
+                        190.0, 1.0, 0.6, 1.0,
+                    ))),
+                    transform: Transform::default()
+                        .with_translation(Vec3::new(0.0, 0.75, 0.00001)),
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(ability::FrontShield);
         })
         .id();
     player.ids.push(entity);
+    player.spawned += 1;
+    if let Some(pilot) = player.humans.iter_mut().find(|p| p.fighter.is_none())
+    {
+        pilot.fighter = Some(entity);
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn respawn(
     settings: Res<Settings>,
     stats: Res<Stats>,
@@ -552,24 +1037,23 @@ fn respawn(
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut players: NonSendMut<Players>,
     mut rng: ResMut<RngState>,
+    mut respawned: EventWriter<event::PlayerRespawned>,
 ) {
     for (i, player) in players.0.iter_mut().enumerate() {
-        let target_count = if i == 0 {
-            1
-        } else {
-            1 + stats.timesteps / settings.difficulty_ramp as usize
-        };
+        let target_count =
+            player.target_fighter_count(i, &settings, stats.timesteps);
         if player.ids.len() + player.respawns.len() < target_count {
             player.respawns.push(settings.respawn_time as i32);
         }
         for j in 0..player.respawns.len() {
             player.respawns[j] -= settings.frameskip as i32;
             if player.respawns[j] <= 0 {
+                let Vec2 { x: w, y: h } = settings.arena_half_size();
                 let spawn_pos = match rng.gen_range(0..4) {
-                    0 => Vec3::new(-1000.0, rng.gen_range(-500.0..500.0), 0.5),
-                    1 => Vec3::new(1000.0, rng.gen_range(-500.0..500.0), 0.5),
-                    2 => Vec3::new(rng.gen_range(-1000.0..1000.0), -500.0, 0.5),
-                    3 => Vec3::new(rng.gen_range(-1000.0..1000.0), 500.0, 0.5),
+                    0 => Vec3::new(-w, rng.gen_range(-h..h), 0.5),
+                    1 => Vec3::new(w, rng.gen_range(-h..h), 0.5),
+                    2 => Vec3::new(rng.gen_range(-w..w), -h, 0.5),
+                    3 => Vec3::new(rng.gen_range(-w..w), h, 0.5),
                     _ => unreachable!(),
                 };
                 spawn_fighter(
@@ -581,6 +1065,7 @@ fn respawn(
                     &mut materials,
                     spawn_pos,
                 );
+                respawned.send(event::PlayerRespawned);
                 player.respawns.remove(j);
                 break; // loop counter is incorrect now, need to break
             }
@@ -588,198 +1073,351 @@ fn respawn(
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn spawn_bullet(
-    settings: &Settings,
-    cmd: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-    position: Vec3,
-    velocity: Vec2,
-    lifetime: u32,
-    player_id: usize,
-) {
-    let (mesh, collider) = if player_id == 0 {
-        (
-            meshes.add(create_projectile_mesh()).into(),
-            Collider::convex_hull(&[
-                Vect::new(-8.0, 30.0),
-                Vect::new(-8.0, -30.0),
-                Vect::new(8.0, 0.0),
-            ])
-            .unwrap(),
-        )
-    } else {
-        let radius = 3.0;
-        let circle = Circle::new(radius);
-        (meshes.add(circle.into()).into(), Collider::ball(3.0))
-    };
-
-    cmd.spawn(Bullet {
-        remaining_lifetime: lifetime as i32,
-        player_id,
-    })
-    .insert(RigidBody::Dynamic)
-    .insert(collider)
-    .insert(Velocity {
-        linvel: velocity,
-        angvel: 0.0,
-    })
-    .insert(LockedAxes::ROTATION_LOCKED)
-    .insert(CollisionType::Bullet)
-    .insert(ActiveEvents::COLLISION_EVENTS)
-    .insert(settings.ccd())
-    .insert(MaterialMesh2dBundle {
-        mesh,
-        transform: Transform::default()
-            .with_scale(Vec3::splat(1.0))
-            .with_rotation(Quat::from_rotation_z(
-                Vec2::new(1.0, 0.0).angle_between(velocity),
-            ))
-            .with_translation(position),
-        material: materials.add(ColorMaterial::from(
-            BULLET_COLORS[player_id % BULLET_COLORS.len()],
-        )),
-        ..default()
-    });
-}
-
 #[allow(clippy::too_many_arguments)]
 fn detect_collisions(
     mut cmd: Commands,
     mut events: EventReader<CollisionEvent>,
+    mut contact_forces: EventReader<ContactForceEvent>,
+    mut queue: ResMut<CollisionQueue>,
     collision_type: Query<&CollisionType>,
-    mut game_over: EventWriter<GameOver>,
     mut asteroids: Query<(&mut Asteroid, &mut Handle<ColorMaterial>)>,
     mut fighters: Query<(&mut Fighter, &Children)>,
     bullets: Query<&Bullet>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut players: NonSendMut<Players>,
-    mut stats: ResMut<Stats>,
+    mut game_events: event::GameEvents,
     mut shield: Query<&mut Visibility, With<Shield>>,
+    mut lasers: Query<(Entity, &mut weapon::Laser)>,
+    front_shields: Query<(&Transform, Option<&ability::Abilities>)>,
+    powerups: Query<&powerup::PowerUp>,
+    settings: Res<Settings>,
 ) {
-    //log::info!("{}", stats.timesteps);
+    queue.clear();
+    let dt = settings.frameskip as f32 / settings.frame_rate;
+    for event in contact_forces.iter() {
+        queue.record_impulse(
+            event.collider1,
+            event.collider2,
+            event.total_force_magnitude * dt,
+        );
+    }
     for event in events.iter() {
         if let CollisionEvent::Started(data1, data2, _) = *event {
-            // log::info!(
-            //     "{:?} <-> {:?} collision ({:?}, {:?})",
-            //     collision_type.get(data1.rigid_body_entity()).unwrap(),
-            //     collision_type.get(data2.rigid_body_entity()).unwrap(),
-            //     data1.rigid_body_entity(),
-            //     data2.rigid_body_entity()
-            // );
-            match (
-                collision_type.get(data1).unwrap(),
-                collision_type.get(data2).unwrap(),
-            ) {
-                (CollisionType::Fighter, CollisionType::Asteroid) => {
-                    take_hit(
-                        &mut cmd,
-                        &mut stats,
-                        &mut game_over,
-                        &mut fighters,
-                        &mut shield,
-                        &mut players,
-                        data1,
-                    );
-                    cmd.entity(data2).despawn();
-                }
-                (CollisionType::Asteroid, CollisionType::Fighter) => {
-                    take_hit(
-                        &mut cmd,
-                        &mut stats,
-                        &mut game_over,
-                        &mut fighters,
-                        &mut shield,
-                        &mut players,
-                        data2,
-                    );
-                    cmd.entity(data1).despawn();
-                }
-                (CollisionType::Bullet, CollisionType::Asteroid) => {
-                    handle_bullet_asteroid_collision(
-                        &mut cmd,
-                        &mut asteroids,
-                        &mut materials,
-                        &mut stats,
-                        data2,
-                        data1,
-                    );
-                }
-                (CollisionType::Asteroid, CollisionType::Bullet) => {
-                    handle_bullet_asteroid_collision(
-                        &mut cmd,
-                        &mut asteroids,
-                        &mut materials,
-                        &mut stats,
+            if !queue.push(data1, data2) {
+                log::debug!(
+                    "Dropping duplicate collision {:?} <-> {:?}",
+                    data1,
+                    data2
+                );
+            }
+        }
+    }
+    for (beam, mut laser) in lasers.iter_mut() {
+        if let Some(target) = laser.target.take() {
+            queue.push(beam, target);
+        }
+    }
+    for (data1, data2) in queue.take_pending() {
+        if queue.is_consumed(data1) || queue.is_consumed(data2) {
+            log::debug!(
+                "Dropping collision {:?} <-> {:?} with entity already \
+                 resolved this frame",
+                data1,
+                data2
+            );
+            continue;
+        }
+        let (type1, type2) =
+            match (collision_type.get(data1), collision_type.get(data2)) {
+                (Ok(type1), Ok(type2)) => (type1, type2),
+                _ => {
+                    log::warn!(
+                        "Dropping collision {:?} <-> {:?} with despawned \
+                         entity",
                         data1,
-                        data2,
+                        data2
                     );
+                    continue;
                 }
-                (CollisionType::Fighter, CollisionType::Bullet) => {
-                    if fighters.get(data1).unwrap().0.player_id
-                        != bullets.get(data2).unwrap().player_id
-                    {
-                        take_hit(
-                            &mut cmd,
-                            &mut stats,
-                            &mut game_over,
-                            &mut fighters,
-                            &mut shield,
-                            &mut players,
-                            data1,
-                        );
-                        cmd.entity(data2).despawn();
-                    }
-                }
-                (CollisionType::Bullet, CollisionType::Fighter) => {
-                    if fighters.get(data2).unwrap().0.player_id
-                        != bullets.get(data1).unwrap().player_id
+            };
+        match (type1, type2) {
+            (CollisionType::Fighter, CollisionType::Asteroid) => {
+                let damage = ram_damage(&queue, &settings, data1, data2);
+                let rammer = fighters.get(data1).ok().map(|(f, _)| f.player_id);
+                take_hit(
+                    &mut cmd,
+                    &mut queue,
+                    &mut game_events,
+                    &mut fighters,
+                    &mut shield,
+                    &mut players,
+                    data1,
+                    damage,
+                    None,
+                );
+                game_events
+                    .asteroid_destroyed
+                    .send(event::AsteroidDestroyed {
+                        asteroid: data2,
+                        attacker: rammer,
+                        rammed: true,
+                    });
+                queue.despawn(&mut cmd, data2);
+            }
+            (CollisionType::Asteroid, CollisionType::Fighter) => {
+                let damage = ram_damage(&queue, &settings, data2, data1);
+                let rammer = fighters.get(data2).ok().map(|(f, _)| f.player_id);
+                take_hit(
+                    &mut cmd,
+                    &mut queue,
+                    &mut game_events,
+                    &mut fighters,
+                    &mut shield,
+                    &mut players,
+                    data2,
+                    damage,
+                    None,
+                );
+                game_events
+                    .asteroid_destroyed
+                    .send(event::AsteroidDestroyed {
+                        asteroid: data1,
+                        attacker: rammer,
+                        rammed: true,
+                    });
+                queue.despawn(&mut cmd, data1);
+            }
+            (CollisionType::Bullet, CollisionType::Asteroid) => {
+                handle_bullet_asteroid_collision(
+                    &mut cmd,
+                    &mut queue,
+                    &mut asteroids,
+                    &bullets,
+                    &mut materials,
+                    &mut game_events,
+                    data2,
+                    data1,
+                );
+            }
+            (CollisionType::Asteroid, CollisionType::Bullet) => {
+                handle_bullet_asteroid_collision(
+                    &mut cmd,
+                    &mut queue,
+                    &mut asteroids,
+                    &bullets,
+                    &mut materials,
+                    &mut game_events,
+                    data1,
+                    data2,
+                );
+            }
+            (CollisionType::Fighter, CollisionType::Bullet) => {
+                handle_bullet_fighter_collision(
+                    &mut cmd,
+                    &mut queue,
+                    &mut game_events,
+                    &mut fighters,
+                    &bullets,
+                    &front_shields,
+                    &mut shield,
+                    &mut players,
+                    data1,
+                    data2,
+                );
+            }
+            (CollisionType::Bullet, CollisionType::Fighter) => {
+                handle_bullet_fighter_collision(
+                    &mut cmd,
+                    &mut queue,
+                    &mut game_events,
+                    &mut fighters,
+                    &bullets,
+                    &front_shields,
+                    &mut shield,
+                    &mut players,
+                    data2,
+                    data1,
+                );
+            }
+            (CollisionType::Fighter, CollisionType::PowerUp) => {
+                handle_powerup_collision(
+                    &mut cmd,
+                    &mut queue,
+                    &mut fighters,
+                    &powerups,
+                    &mut shield,
+                    &mut players,
+                    data1,
+                    data2,
+                );
+            }
+            (CollisionType::PowerUp, CollisionType::Fighter) => {
+                handle_powerup_collision(
+                    &mut cmd,
+                    &mut queue,
+                    &mut fighters,
+                    &powerups,
+                    &mut shield,
+                    &mut players,
+                    data2,
+                    data1,
+                );
+            }
+            (CollisionType::Bullet, CollisionType::Bullet) => {
+                match (bullets.get(data1), bullets.get(data2)) {
+                    // Projectiles passing through a mine's trigger radius
+                    (Ok(bullet1), Ok(bullet2))
+                        if bullet1.weapon == act::Weapon::Mine
+                            || bullet2.weapon == act::Weapon::Mine => {}
+                    (Ok(bullet1), Ok(bullet2))
+                        if (bullet1.weapon == act::Weapon::Missile
+                            || bullet2.weapon == act::Weapon::Missile)
+                            && bullet1.player_id != bullet2.player_id =>
                     {
-                        take_hit(
-                            &mut cmd,
-                            &mut stats,
-                            &mut game_over,
-                            &mut fighters,
-                            &mut shield,
-                            &mut players,
-                            data2,
-                        );
-                        cmd.entity(data1).despawn();
-                    }
-                }
-                (CollisionType::Bullet, CollisionType::Bullet) => {
-                    let bullet1 = bullets.get(data1).unwrap();
-                    let bullet2 = bullets.get(data2).unwrap();
-                    if bullet1.player_id == 0 {
-                        cmd.entity(data2).despawn();
+                        game_events
+                            .effects
+                            .send(particles::Effect::Impact(data1));
+                        queue.despawn(&mut cmd, data1);
+                        queue.despawn(&mut cmd, data2);
                     }
-                    if bullet2.player_id == 0 {
-                        cmd.entity(data1).despawn();
+                    (Ok(bullet1), Ok(bullet2)) => {
+                        let (player1, player2) =
+                            (bullet1.player_id, bullet2.player_id);
+                        if player1 == 0 {
+                            queue.despawn(&mut cmd, data2);
+                        }
+                        if player2 == 0 {
+                            queue.despawn(&mut cmd, data1);
+                        }
                     }
+                    _ => log::warn!(
+                        "Bullet collision {:?} <-> {:?} without Bullet \
+                         component",
+                        data1,
+                        data2
+                    ),
                 }
-                _ => {}
             }
+            _ => {}
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_bullet_fighter_collision(
+    cmd: &mut Commands,
+    queue: &mut CollisionQueue,
+    game_events: &mut event::GameEvents,
+    fighters: &mut Query<(&mut Fighter, &Children)>,
+    bullets: &Query<&Bullet>,
+    front_shields: &Query<(&Transform, Option<&ability::Abilities>)>,
+    shield: &mut Query<&mut Visibility, With<Shield>>,
+    players: &mut NonSendMut<Players>,
+    fighter: Entity,
+    bullet: Entity,
+) {
+    let (fighter_player, bullet_player, weapon, damage) =
+        match (fighters.get(fighter), bullets.get(bullet)) {
+            (Ok((f, _)), Ok(b)) => {
+                (f.player_id, b.player_id, b.weapon, b.damage)
+            }
+            _ => {
+                log::warn!(
+                    "Bullet {:?} hit fighter {:?} which no longer exists",
+                    bullet,
+                    fighter
+                );
+                return;
+            }
+        };
+    if fighter_player == bullet_player {
+        return;
+    }
+    let blocked = match (front_shields.get(fighter), front_shields.get(bullet))
+    {
+        (Ok((transform, Some(abilities))), Ok((bullet_transform, _))) => {
+            abilities.blocks(transform, bullet_transform.translation)
         }
+        _ => false,
+    };
+    // Laser beams stay visible until they expire
+    if weapon == act::Weapon::Laser {
+        game_events.effects.send(particles::Effect::Impact(fighter));
+    } else {
+        game_events.effects.send(particles::Effect::Impact(bullet));
+        queue.despawn(cmd, bullet);
+    }
+    if !blocked {
+        take_hit(
+            cmd,
+            queue,
+            game_events,
+            fighters,
+            shield,
+            players,
+            fighter,
+            damage,
+            Some(bullet_player),
+        );
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_bullet_asteroid_collision(
     cmd: &mut Commands,
+    queue: &mut CollisionQueue,
     asteroids: &mut Query<(&mut Asteroid, &mut Handle<ColorMaterial>)>,
+    bullets: &Query<&Bullet>,
     materials: &mut ResMut<Assets<ColorMaterial>>,
-    stats: &mut ResMut<Stats>,
+    game_events: &mut event::GameEvents,
     asteroid_entity: Entity,
     bullet: Entity,
 ) {
-    cmd.entity(bullet).despawn();
-    let (mut asteroid, mut material) =
-        asteroids.get_mut(asteroid_entity).unwrap();
-    asteroid.health -= 1.0;
-    stats.bullet_hits += 1;
+    let (attacker, weapon, damage) = match bullets.get(bullet) {
+        Ok(b) => (b.player_id, b.weapon, b.damage),
+        Err(_) => {
+            log::warn!(
+                "Asteroid {:?} hit by bullet {:?} which no longer exists",
+                asteroid_entity,
+                bullet
+            );
+            return;
+        }
+    };
+    match weapon {
+        // Mines are only triggered by enemy fighters
+        act::Weapon::Mine => return,
+        act::Weapon::Laser => game_events
+            .effects
+            .send(particles::Effect::Impact(asteroid_entity)),
+        _ => {
+            game_events.effects.send(particles::Effect::Impact(bullet));
+            queue.despawn(cmd, bullet);
+        }
+    }
+    let (mut asteroid, mut material) = match asteroids.get_mut(asteroid_entity)
+    {
+        Ok(asteroid) => asteroid,
+        Err(_) => {
+            log::warn!(
+                "Bullet {:?} hit asteroid {:?} which no longer exists",
+                bullet,
+                asteroid_entity
+            );
+            return;
+        }
+    };
+    asteroid.health -= damage;
+    game_events.asteroid_hit.send(event::AsteroidHit);
     if asteroid.health <= 0.0 {
-        cmd.entity(asteroid_entity).despawn();
-        stats.destroyed_asteroids += 1;
+        game_events
+            .asteroid_destroyed
+            .send(event::AsteroidDestroyed {
+                asteroid: asteroid_entity,
+                attacker: Some(attacker),
+                rammed: false,
+            });
+        queue.despawn(cmd, asteroid_entity);
     } else {
         *material = materials.add(ColorMaterial::from(Color::rgb(
             0.6 - 0.1 * asteroid.health,
@@ -789,62 +1427,158 @@ fn handle_bullet_asteroid_collision(
     }
 }
 
-fn take_hit(
-    cmd: &mut Commands,
-    stats: &mut ResMut<Stats>,
-    game_over: &mut EventWriter<GameOver>,
-    fighters: &mut Query<(&mut Fighter, &Children)>,
+/// Hull damage dealt to `fighter` by ramming `asteroid`, at least that of a
+/// bullet.
+fn ram_damage(
+    queue: &CollisionQueue,
+    settings: &Settings,
+    fighter: Entity,
+    asteroid: Entity,
+) -> f32 {
+    (queue.impulse(fighter, asteroid) * settings.ram_damage_per_impulse)
+        .max(1.0)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_powerup_collision(
+    cmd: &mut Commands,
+    queue: &mut CollisionQueue,
+    fighters: &mut Query<(&mut Fighter, &Children)>,
+    powerups: &Query<&powerup::PowerUp>,
+    shield: &mut Query<&mut Visibility, With<Shield>>,
+    players: &mut NonSendMut<Players>,
+    fighter: Entity,
+    powerup: Entity,
+) {
+    let (kind, (mut f, children)) =
+        match (powerups.get(powerup), fighters.get_mut(fighter)) {
+            (Ok(p), Ok(f)) => (p.kind, f),
+            _ => {
+                log::warn!(
+                    "Power-up {:?} collected by fighter {:?} which no longer \
+                     exists",
+                    powerup,
+                    fighter
+                );
+                return;
+            }
+        };
+    match kind {
+        powerup::PowerUpKind::Restore => {
+            f.health = f.max_health;
+            // Only player 0 has a passive shield to restore
+            if let Some(child) =
+                children.iter().find(|child| shield.contains(**child))
+            {
+                f.shield_active = true;
+                f.shield_cooldown = 0;
+                shield.get_mut(*child).unwrap().is_visible = true;
+            }
+        }
+        powerup::PowerUpKind::RapidFire => {
+            f.rapid_fire_time = powerup::RAPID_FIRE_DURATION;
+        }
+        powerup::PowerUpKind::SpeedBoost => {
+            f.speed_boost_time = powerup::SPEED_BOOST_DURATION;
+        }
+        powerup::PowerUpKind::ExtraLife => {
+            players.0[f.player_id].extra_lives += 1;
+        }
+    }
+    queue.despawn(cmd, powerup);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn take_hit(
+    cmd: &mut Commands,
+    queue: &mut CollisionQueue,
+    game_events: &mut event::GameEvents,
+    fighters: &mut Query<(&mut Fighter, &Children)>,
     shield: &mut Query<&mut Visibility, With<Shield>>,
     players: &mut NonSendMut<Players>,
     fighter: Entity,
+    damage: f32,
+    attacker: Option<usize>,
 ) {
     let mut already_destroyed = true;
-    let (mut f, children) = fighters.get_mut(fighter).unwrap();
+    let mut remaining_fighters = 0;
+    let mut extra_life = false;
+    let (mut f, children) = match fighters.get_mut(fighter) {
+        Ok(f) => f,
+        Err(_) => {
+            log::warn!("Hit on fighter {:?} which no longer exists", fighter);
+            return;
+        }
+    };
     if f.shield_active {
         f.shield_active = false;
         f.shield_cooldown = f.shield_recharge_period;
-        shield
-            .get_mut(*children.iter().nth(1).unwrap())
-            .unwrap()
-            .is_visible = false;
+        if let Some(mut visibility) = children
+            .get(1)
+            .and_then(|child| shield.get_mut(*child).ok())
+        {
+            visibility.is_visible = false;
+        }
+        game_events
+            .shield_broken
+            .send(event::ShieldBroken { fighter, attacker });
+        return;
+    }
+    f.health -= damage;
+    if f.health > 0.0 {
+        game_events
+            .fighter_hit
+            .send(event::FighterHit { fighter, attacker });
         return;
     }
     for player in players.0.iter_mut() {
-        if let Some(index) = player.ids.iter().position(|id| *id == fighter) {
-            player.ids.remove(index);
+        if player.remove_fighter(fighter) {
+            remaining_fighters = player.ids.len();
             already_destroyed = false;
+            // The last fighter is respawned instead of ending the game
+            if remaining_fighters == 0 && player.extra_lives > 0 {
+                player.extra_lives -= 1;
+                extra_life = true;
+            }
             break;
         }
     }
     if !already_destroyed {
-        if let Ok(f) = fighters.get_mut(fighter) {
-            stats.bullet_hits += 1;
-            if f.0.player_id == 0 {
-                game_over.send(GameOver);
-                stats.destroyed_allies += 1;
-            } else {
-                stats.destroyed_opponents += 1;
-            }
+        // With several humans on player 0, the game continues while any of
+        // them is still alive
+        if f.player_id == 0 && remaining_fighters == 0 && !extra_life {
+            game_events.game_over.send(GameOver);
         }
-        cmd.entity(fighter).despawn_recursive();
+        game_events.fighter_destroyed.send(event::FighterDestroyed {
+            victim: fighter,
+            victim_player: f.player_id,
+            killer: attacker,
+        });
+        queue.despawn_recursive(cmd, fighter);
     }
 }
 
 fn check_boundary_collision(
     settings: Res<Settings>,
-    mut fighter: Query<(&mut Velocity, &Transform, &mut Fighter)>,
+    mut fighter: Query<(
+        &mut Velocity,
+        &Transform,
+        &mut Fighter,
+        &ability::Abilities,
+    )>,
 ) {
-    for (mut velocity, transform, fighter) in fighter.iter_mut() {
+    for (mut velocity, transform, fighter, abilities) in fighter.iter_mut() {
         let x = transform.translation.x;
         let y = transform.translation.y;
-        if x > 1000.0 {
+        let bounds = settings.arena_half_size();
+        if x > bounds.x {
             velocity.linvel.x = -velocity.linvel.x.abs();
-        } else if x < -1000.0 {
+        } else if x < -bounds.x {
             velocity.linvel.x = velocity.linvel.x.abs();
         }
-        if y > 500.0 {
+        if y > bounds.y {
             velocity.linvel.y = -velocity.linvel.y.abs();
-        } else if y < -500.0 {
+        } else if y < -bounds.y {
             velocity.linvel.y = velocity.linvel.y.abs();
         }
         if !fighter.is_turning && velocity.angvel != 0.0 {
@@ -853,10 +1587,12 @@ fn check_boundary_collision(
                 * settings.frameskip as f32;
         }
         // Clamp velocity
+        let max_velocity = fighter.max_velocity
+            * fighter.speed_multiplier()
+            * abilities.speed_multiplier();
         let speed = velocity.linvel.length();
-        if speed > fighter.max_velocity {
-            velocity.linvel =
-                velocity.linvel.normalize() * fighter.max_velocity;
+        if speed > max_velocity {
+            velocity.linvel = velocity.linvel.normalize() * max_velocity;
         }
     }
 }
@@ -869,13 +1605,15 @@ fn spawn_asteroids(
     mut asteroids: Query<(Entity, &mut Asteroid, &mut Transform)>,
     mut rng: ResMut<RngState>,
 ) {
+    // Asteroids spawn and disappear outside of the arena
+    let spawn_bounds = settings.arena_half_size() * 1.5;
     let mut count = 0;
     for asteroid in asteroids.iter_mut() {
         // Delete asteroid if it is out of bounds
-        if asteroid.2.translation.x > 1500.0
-            || asteroid.2.translation.x < -1500.0
-            || asteroid.2.translation.y > 750.0
-            || asteroid.2.translation.y < -750.0
+        if asteroid.2.translation.x > spawn_bounds.x
+            || asteroid.2.translation.x < -spawn_bounds.x
+            || asteroid.2.translation.y > spawn_bounds.y
+            || asteroid.2.translation.y < -spawn_bounds.y
         {
             cmd.entity(asteroid.0).despawn();
         } else {
@@ -893,6 +1631,7 @@ fn spawn_asteroids(
             health: 2.0,
             radius: size.sqrt(),
         })
+        .record_spawn_order()
         .insert(RigidBody::Dynamic)
         .insert(LockedAxes::ROTATION_LOCKED)
         .insert(Collider::ball(size.sqrt()))
@@ -906,8 +1645,8 @@ fn spawn_asteroids(
             transform: Transform::default()
                 .with_scale(Vec3::splat(1.0))
                 .with_translation(Vec3::new(
-                    1500.0 * spawn_angle.cos(),
-                    750.0 * spawn_angle.sin(),
+                    spawn_bounds.x * spawn_angle.cos(),
+                    spawn_bounds.y * spawn_angle.sin(),
                     1.0,
                 )),
             material: materials
@@ -918,52 +1657,6 @@ fn spawn_asteroids(
     }
 }
 
-fn keyboard_events(
-    mut action_events: EventWriter<(act::FighterAction, Entity)>,
-    remaining_time: Res<RemainingTime>,
-    settings: Res<Settings>,
-    keys: Res<Input<KeyCode>>,
-    players: NonSend<Players>,
-) {
-    if remaining_time.0 as u32 % settings.action_interval != 0 {
-        return;
-    }
-    let thrust = if keys.pressed(KeyCode::Up) || keys.pressed(KeyCode::W) {
-        act::Thrust::On
-    } else if keys.pressed(KeyCode::Down) || keys.pressed(KeyCode::S) {
-        act::Thrust::Stop
-    } else {
-        act::Thrust::Off
-    };
-    let turn = if keys.pressed(KeyCode::Left) || keys.pressed(KeyCode::A) {
-        act::Turn::Left
-    } else if keys.pressed(KeyCode::Right) || keys.pressed(KeyCode::D) {
-        act::Turn::Right
-    } else {
-        act::Turn::None
-    };
-    let shoot = if keys.pressed(KeyCode::Space) {
-        act::Shoot::On
-    } else {
-        act::Shoot::Off
-    };
-    if (thrust != act::Thrust::Off
-        || turn != act::Turn::None
-        || shoot != act::Shoot::Off
-        || players.0[0].agent.is_none())
-        && !players.0[0].ids.is_empty()
-    {
-        action_events.send((
-            act::FighterAction {
-                thrust,
-                turn,
-                shoot,
-            },
-            players.0[0].ids[0],
-        ));
-    }
-}
-
 fn create_fighter_mesh() -> Mesh {
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
@@ -996,17 +1689,6 @@ fn create_jet_mesh() -> Mesh {
     mesh
 }
 
-fn create_projectile_mesh() -> Mesh {
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-
-    mesh.insert_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        vec![[-8.0, 30.0, 0.0], [-8.0, -30.0, 0.0], [8.0, 0.0, 0.0]],
-    );
-    mesh.set_indices(Some(Indices::U32(vec![0, 1, 2])));
-    mesh
-}
-
 fn create_fighter_mesh2() -> Mesh {
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
 
@@ -1027,12 +1709,13 @@ fn create_fighter_mesh2() -> Mesh {
 fn expire_bullets(
     settings: Res<Settings>,
     mut cmd: Commands,
+    mut queue: ResMut<CollisionQueue>,
     mut bullets: Query<(Entity, &mut Bullet)>,
 ) {
     for (entity, mut bullet) in &mut bullets.iter_mut() {
         bullet.remaining_lifetime -= settings.frameskip as i32;
         if bullet.remaining_lifetime <= 0 {
-            cmd.entity(entity).despawn();
+            queue.despawn(&mut cmd, entity);
         }
     }
 }
@@ -1048,6 +1731,8 @@ fn cooldowns(
     stats.timesteps += settings.frameskip as usize;
     for (mut fighter, children) in &mut fighter.iter_mut() {
         fighter.remaining_bullet_cooldown -= settings.frameskip as i32;
+        fighter.rapid_fire_time -= settings.frameskip as i32;
+        fighter.speed_boost_time -= settings.frameskip as i32;
         if !fighter.shield_active && fighter.player_id == 0 {
             fighter.shield_cooldown -= settings.frameskip as i32;
             if fighter.shield_cooldown <= 0 {
@@ -1065,55 +1750,204 @@ fn cooldowns(
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn ai(
-    mut action_events: EventWriter<(act::FighterAction, Entity)>,
-    mut players: NonSendMut<Players>,
-    fighter: Query<(&mut Fighter, &Transform, &Velocity)>,
-    mut exit: EventWriter<AppExit>,
-    asteroids: Query<(&Asteroid, &Transform, &Velocity), Without<Fighter>>,
-    bullets: Query<(&Bullet, &Transform, &Velocity), Without<Fighter>>,
-    remaining_time: Res<RemainingTime>,
-    stats: Res<Stats>,
-    settings: Res<Settings>,
-) {
-    let action_interval = settings
-        .ai_action_interval
-        .unwrap_or(settings.action_interval);
-    if remaining_time.0 as u32 % action_interval != 0 {
-        return;
+/// Entities observed by a player, featurized as for agents.
+#[derive(Clone, Serialize)]
+pub struct Observation {
+    pub score: f32,
+    pub fighters: Vec<entity::Identified<entity::Fighter>>,
+    pub enemy_fighters: Vec<entity::Identified<entity::EnemyFighter>>,
+    pub asteroids: Vec<entity::Identified<entity::Asteroid>>,
+    pub bullets: Vec<entity::Identified<entity::Bullet>>,
+    pub powerups: Vec<entity::Identified<entity::PowerUp>>,
+    /// Only present with `Settings.observation_history`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub past_fighters: Vec<entity::Identified<entity::PastFighter>>,
+}
+
+impl Observation {
+    /// The entities in the observation space of `entity::classic`.
+    fn classic(&self) -> entity::classic::Entities {
+        fn convert<'a, E, C>(
+            entities: &'a [entity::Identified<E>],
+        ) -> Vec<entity::Identified<C>>
+        where
+            C: From<&'a E>,
+        {
+            entities
+                .iter()
+                .map(|e| entity::Identified {
+                    id: e.id,
+                    entity: (&e.entity).into(),
+                })
+                .collect()
+        }
+        entity::classic::Entities {
+            fighters: convert(&self.fighters),
+            enemy_fighters: convert(&self.enemy_fighters),
+            asteroids: convert(&self.asteroids),
+            bullets: convert(&self.bullets),
+        }
     }
-    let mut actions = vec![];
-    let num_players = players.0.len();
-    for (i, agent, ids) in players.0.iter_mut().enumerate().filter_map(
-        |(i, Player { agent, ids, .. })| agent.as_mut().map(|a| (i, a, ids)),
-    ) {
-        if num_players == 1 && ids.is_empty() {
-            return;
+
+    fn into_obs(self, settings: &Settings) -> Obs {
+        let score = self.score;
+        self.observe_into(Obs::new(score), settings, settings.observe_ids)
+    }
+
+    /// Adds the entities in the observation space chosen by the settings to
+    /// `obs`, with their IDs if `ids` is set.
+    fn observe_into<O: Observer>(
+        self,
+        mut obs: O,
+        settings: &Settings,
+        ids: bool,
+    ) -> O {
+        if settings.agent_loadout {
+            obs = entity::observe_actors(obs, self.fighters, ids);
+            obs = entity::observe(obs, self.enemy_fighters, ids);
+            obs = entity::observe(obs, self.asteroids, ids);
+            obs = entity::observe(obs, self.bullets, ids);
+        } else {
+            let classic = self.classic();
+            obs = entity::observe_actors(obs, classic.fighters, ids);
+            obs = entity::observe(obs, classic.enemy_fighters, ids);
+            obs = entity::observe(obs, classic.asteroids, ids);
+            obs = entity::observe(obs, classic.bullets, ids);
+        }
+        // Optional entity types are only part of the observation space when
+        // enabled, even if there are none of them at the moment
+        if settings.powerups_enabled() {
+            obs = entity::observe(obs, self.powerups, ids);
         }
+        if settings.observation_history > 0 {
+            obs = entity::observe(obs, self.past_fighters, ids);
+        }
+        obs
+    }
+}
+
+/// Collects the entities of an `Observation` as seen by agents, see
+/// `Observation::observe_into`.
+trait Observer {
+    fn actors<E: Featurizable + Serialize>(self, actors: Vec<E>) -> Self;
+    fn entities<E: Featurizable + Serialize>(self, entities: Vec<E>) -> Self;
+}
+
+impl Observer for Obs {
+    fn actors<E: Featurizable + Serialize>(self, actors: Vec<E>) -> Self {
+        Obs::actors(self, actors)
+    }
+
+    fn entities<E: Featurizable + Serialize>(self, entities: Vec<E>) -> Self {
+        Obs::entities(self, entities)
+    }
+}
+
+/// Queries needed to build the `Observation` of a player.
+#[derive(SystemParam)]
+struct ObservationQueries<'w, 's> {
+    fighters: Query<
+        'w,
+        's,
+        (
+            &'static Fighter,
+            &'static Transform,
+            &'static Velocity,
+            Entity,
+        ),
+    >,
+    abilities: Query<'w, 's, &'static ability::Abilities>,
+    asteroids: Query<
+        'w,
+        's,
+        (
+            &'static Asteroid,
+            &'static Transform,
+            &'static Velocity,
+            Entity,
+        ),
+        Without<Fighter>,
+    >,
+    bullets: Query<
+        'w,
+        's,
+        (
+            &'static Bullet,
+            &'static Transform,
+            &'static Velocity,
+            Entity,
+        ),
+        Without<Fighter>,
+    >,
+    powerups:
+        Query<'w, 's, (&'static powerup::PowerUp, &'static Transform, Entity)>,
+    remaining_time: Res<'w, RemainingTime>,
+    stats: Res<'w, Stats>,
+    settings: Res<'w, Settings>,
+    history: Res<'w, history::ObservationHistory>,
+    entity_ids: Res<'w, ids::EntityIds>,
+}
+
+impl<'w, 's> ObservationQueries<'w, 's> {
+    /// Whether `position` is within the sensor range of any of the fighters
+    /// `ids`, always true without a `Settings.sensor_range`.
+    fn senses(&self, ids: &[Entity], position: Vec3) -> bool {
+        let range = match self.settings.sensor_range {
+            Some(range) => range,
+            None => return true,
+        };
+        ids.iter().filter_map(|id| self.fighters.get(*id).ok()).any(
+            |(_, transform, _, _)| {
+                transform
+                    .translation
+                    .truncate()
+                    .distance_squared(position.truncate())
+                    <= range * range
+            },
+        )
+    }
+
+    /// Builds the observation of player `i` controlling the fighters `ids`.
+    fn observe(&self, i: usize, ids: &[Entity]) -> Observation {
         let mut actor_entities = vec![];
         let mut xdir = 0.0;
         let mut ydir = 0.0;
-        for id in &*ids {
-            if let Ok((fighter, transform, velocity)) = fighter.get(*id) {
+        for id in ids {
+            if let (Ok((fighter, transform, velocity, _)), Ok(abilities)) =
+                (self.fighters.get(*id), self.abilities.get(*id))
+            {
                 let pos = transform.translation;
                 let vel = velocity.linvel;
                 let (direction_x, direction_y) =
                     transform_to_direction(transform);
-                actor_entities.push(entity::Fighter {
-                    x: pos.x,
-                    y: pos.y,
-                    dx: vel.x,
-                    dy: vel.y,
-                    direction_x,
-                    direction_y,
-                    remaining_time: remaining_time.0,
-                    gun_cooldown: fighter.remaining_bullet_cooldown.max(0)
-                        as u32,
-                    player: i as u32,
-                    shield_active: fighter.shield_active,
-                    shield_cooldown: fighter.shield_cooldown as f32
-                        / fighter.shield_recharge_period as f32,
+                actor_entities.push(entity::Identified {
+                    id: self.entity_ids.id(*id),
+                    entity: entity::Fighter {
+                        x: pos.x,
+                        y: pos.y,
+                        dx: vel.x,
+                        dy: vel.y,
+                        direction_x,
+                        direction_y,
+                        remaining_time: self.remaining_time.0,
+                        gun_cooldown: fighter.remaining_bullet_cooldown.max(0)
+                            as u32,
+                        player: i as u32,
+                        shield_active: fighter.shield_active,
+                        shield_cooldown: fighter.shield_cooldown as f32
+                            / fighter.shield_recharge_period as f32,
+                        laser_charge: fighter.laser_charge as f32
+                            / weapon::LASER_CHARGE_TIME as f32,
+                        energy: abilities.energy(),
+                        front_shield_active: abilities.shield_active(),
+                        front_shield_cooldown: abilities
+                            .cooldown(act::Ability::Shield),
+                        boost_active: abilities.boost_active(),
+                        boost_cooldown: abilities.cooldown(act::Ability::Boost),
+                        blink_cooldown: abilities.cooldown(act::Ability::Blink),
+                        emp_cooldown: abilities.cooldown(act::Ability::Emp),
+                        health: fighter.health / fighter.max_health,
+                    },
                 });
                 xdir = direction_x;
                 ydir = direction_y;
@@ -1122,77 +1956,288 @@ fn ai(
         // Rotates position/direction to align with direction of player 0
         let rotate_x = |x: f32, y: f32| xdir * x + ydir * y;
         let rotate_y = |x: f32, y: f32| -ydir * x + xdir * y;
-        let score = if i == 0 {
-            stats.player0_score()
-        } else {
-            stats.player1_score()
-        };
-        let obs = Obs::new(score)
-            .actors(actor_entities)
-            .entities(fighter.iter().filter(|(f, _, _)| f.player_id != i).map(
-                |(fighter, transform, velocity)| {
+        Observation {
+            score: self.stats.score(i),
+            fighters: actor_entities,
+            enemy_fighters: self
+                .fighters
+                .iter()
+                .filter(|(f, t, _, _)| {
+                    f.player_id != i && self.senses(ids, t.translation)
+                })
+                .map(|(fighter, transform, velocity, entity)| {
                     let pos = transform.translation;
                     let vel = velocity.linvel;
                     let (direction_x, direction_y) =
                         transform_to_direction(transform);
-                    entity::EnemyFighter {
-                        x: pos.x,
-                        y: pos.y,
-                        dx: vel.x,
-                        dy: vel.y,
-                        direction_x,
-                        direction_y,
-                        gun_cooldown: fighter.remaining_bullet_cooldown.max(0)
-                            as u32,
-                        player: i as u32,
+                    entity::Identified {
+                        id: self.entity_ids.id(entity),
+                        entity: entity::EnemyFighter {
+                            x: pos.x,
+                            y: pos.y,
+                            dx: vel.x,
+                            dy: vel.y,
+                            direction_x,
+                            direction_y,
+                            gun_cooldown: fighter
+                                .remaining_bullet_cooldown
+                                .max(0)
+                                as u32,
+                            player: i as u32,
+                            health: fighter.health / fighter.max_health,
 
-                        reldx: rotate_x(vel.x, vel.y),
-                        reldy: rotate_y(vel.x, vel.y),
-                        reldirection_x: rotate_x(direction_x, direction_y),
-                        reldirection_y: rotate_y(direction_x, direction_y),
+                            reldx: rotate_x(vel.x, vel.y),
+                            reldy: rotate_y(vel.x, vel.y),
+                            reldirection_x: rotate_x(direction_x, direction_y),
+                            reldirection_y: rotate_y(direction_x, direction_y),
+                        },
                     }
-                },
-            ))
-            .entities(asteroids.iter().map(
-                |(asteroid, transform, velocity)| {
+                })
+                .collect(),
+            asteroids: self
+                .asteroids
+                .iter()
+                .filter(|(_, t, _, _)| self.senses(ids, t.translation))
+                .map(|(asteroid, transform, velocity, entity)| {
                     let pos = transform.translation;
                     let vel = velocity.linvel;
-                    entity::Asteroid {
-                        health: asteroid.health,
-                        radius: asteroid.radius,
-                        x: pos.x,
-                        y: pos.y,
-                        dx: vel.x,
-                        dy: vel.y,
+                    entity::Identified {
+                        id: self.entity_ids.id(entity),
+                        entity: entity::Asteroid {
+                            health: asteroid.health,
+                            radius: asteroid.radius,
+                            x: pos.x,
+                            y: pos.y,
+                            dx: vel.x,
+                            dy: vel.y,
+                        },
                     }
-                },
-            ))
-            .entities(bullets.iter().map(|(bullet, transform, velocity)| {
-                let pos = transform.translation;
-                let vel = velocity.linvel;
-                entity::Bullet {
-                    x: pos.x,
-                    y: pos.y,
-                    dx: vel.x,
-                    dy: vel.y,
-                    lifetime: bullet.remaining_lifetime,
-                    player: bullet.player_id as u32,
-                    reldx: rotate_x(vel.x, vel.y),
-                    reldy: rotate_y(vel.x, vel.y),
-                }
-            }));
-        let action = agent.act_async::<act::FighterAction>(&obs);
-        actions.push((action, ids.clone()));
-    }
-    for (action, ids) in actions {
-        let action = action.rcv();
-        match action {
-            Some(actions) => {
-                for (action, id) in actions.into_iter().zip(ids) {
-                    action_events.send((action, id));
-                }
+                })
+                .collect(),
+            bullets: self
+                .bullets
+                .iter()
+                .filter(|(_, t, _, _)| self.senses(ids, t.translation))
+                .map(|(bullet, transform, velocity, entity)| {
+                    let pos = transform.translation;
+                    let vel = velocity.linvel;
+                    entity::Identified {
+                        id: self.entity_ids.id(entity),
+                        entity: entity::Bullet {
+                            x: pos.x,
+                            y: pos.y,
+                            dx: vel.x,
+                            dy: vel.y,
+                            lifetime: bullet.remaining_lifetime,
+                            player: bullet.player_id as u32,
+                            weapon: bullet.weapon as u32,
+                            reldx: rotate_x(vel.x, vel.y),
+                            reldy: rotate_y(vel.x, vel.y),
+                        },
+                    }
+                })
+                .collect(),
+            powerups: self
+                .powerups
+                .iter()
+                .filter(|(_, t, _)| self.senses(ids, t.translation))
+                .map(|(powerup, transform, entity)| entity::Identified {
+                    id: self.entity_ids.id(entity),
+                    entity: entity::PowerUp {
+                        x: transform.translation.x,
+                        y: transform.translation.y,
+                        kind: powerup::PowerUpKind::ALL
+                            .iter()
+                            .position(|k| *k == powerup.kind)
+                            .unwrap() as u32,
+                        lifetime: powerup.remaining_lifetime,
+                    },
+                })
+                .collect(),
+            past_fighters: self
+                .history
+                .snapshots
+                .iter()
+                .flat_map(|snapshot| {
+                    let age = snapshot.remaining_time - self.remaining_time.0;
+                    snapshot.fighters.iter().filter_map(move |(id, f)| {
+                        // Only fighters that are still around, and sensed now
+                        let (_, transform, _, _) =
+                            self.fighters.get(*id).ok()?;
+                        if !self.senses(ids, transform.translation) {
+                            return None;
+                        }
+                        Some(entity::Identified {
+                            id: self.entity_ids.id(*id),
+                            entity: entity::PastFighter {
+                                x: f.position.x,
+                                y: f.position.y,
+                                dx: f.velocity.x,
+                                dy: f.velocity.y,
+                                direction_x: f.direction.x,
+                                direction_y: f.direction.y,
+                                player: f.player_id as u32,
+                                age,
+                                reldx: rotate_x(f.velocity.x, f.velocity.y),
+                                reldy: rotate_y(f.velocity.x, f.velocity.y),
+                                reldirection_x: rotate_x(
+                                    f.direction.x,
+                                    f.direction.y,
+                                ),
+                                reldirection_y: rotate_y(
+                                    f.direction.x,
+                                    f.direction.y,
+                                ),
+                            },
+                        })
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ai(
+    mut action_events: EventWriter<(act::FighterAction, Entity)>,
+    mut continuous_events: EventWriter<(act::ContinuousAction, Entity)>,
+    mut target_events: EventWriter<(act::Target, Entity)>,
+    mut players: NonSendMut<Players>,
+    mut exit: EventWriter<AppExit>,
+    observations: ObservationQueries,
+    settings: Res<Settings>,
+    mut debug: Option<ResMut<agent_debug::AgentDebug>>,
+) {
+    let action_interval = settings
+        .ai_action_interval
+        .unwrap_or(settings.action_interval);
+    let observe = observations.remaining_time.0 as u32 % action_interval == 0;
+    // Only record observations and actions while the overlay is shown
+    let mut debug = debug.as_mut().filter(|debug| debug.enabled);
+    let mut pending = vec![];
+    let num_players = players.0.len();
+    for (i, player) in players.0.iter_mut().enumerate() {
+        let agent = match &mut player.agent {
+            Some(agent) => agent,
+            None => continue,
+        };
+        let decisions: PendingDecisions = if observe {
+            if num_players == 1 && player.ids.is_empty() {
+                return;
+            }
+            let observation = observations.observe(i, &player.ids);
+            if let Some(debug) = &mut debug {
+                debug.observations.insert(i, observation.clone());
+            }
+            // Agents of all players act concurrently, their decisions are
+            // awaited below
+            agent.act(observation, player.ids.clone(), &settings)
+        } else {
+            // Replies of bots may arrive on any step
+            Box::new(move || agent.poll())
+        };
+        pending.push((i, decisions));
+    }
+    let mut stopped = vec![];
+    for (i, decisions) in pending {
+        let decisions = match decisions() {
+            Some(decisions) => decisions,
+            None => {
+                stopped.push(i);
+                continue;
+            }
+        };
+        for (action, id) in decisions.actions {
+            if let Some(debug) = &mut debug {
+                debug.actions.insert(id, action);
             }
-            None => exit.send(AppExit),
+            action_events.send((action, id));
+        }
+        for action in decisions.continuous {
+            continuous_events.send(action);
+        }
+        for target in decisions.targets {
+            target_events.send(target);
+        }
+    }
+    for i in stopped {
+        // Bots may reconnect, other agents only stop once training is over
+        if players.0[i].bot {
+            players.0[i].agent = None;
+        } else {
+            exit.send(AppExit);
+        }
+    }
+}
+
+/// Heuristic for players with `Controller::Scripted`: turns towards the
+/// nearest enemy fighter, closes in and fires once roughly aligned.
+fn scripted_ai(
+    mut action_events: EventWriter<(act::FighterAction, Entity)>,
+    players: NonSend<Players>,
+    fighters: Query<(&Fighter, &Transform)>,
+    remaining_time: Res<RemainingTime>,
+    settings: Res<Settings>,
+) {
+    let action_interval = settings
+        .ai_action_interval
+        .unwrap_or(settings.action_interval);
+    if remaining_time.0 as u32 % action_interval != 0 {
+        return;
+    }
+    for (i, player) in players.0.iter().enumerate().filter(|(_, p)| p.scripted)
+    {
+        for id in &player.ids {
+            let transform = match fighters.get(*id) {
+                Ok((_, transform)) => transform,
+                Err(_) => continue,
+            };
+            let pos = transform.translation.truncate();
+            let target = fighters
+                .iter()
+                .filter(|(f, _)| f.player_id != i)
+                .map(|(_, t)| t.translation.truncate())
+                .min_by(|a, b| {
+                    a.distance_squared(pos).total_cmp(&b.distance_squared(pos))
+                });
+            let offset = match target {
+                Some(target) => target - pos,
+                None => continue,
+            };
+            let (direction_x, direction_y) = transform_to_direction(transform);
+            let angle =
+                Vec2::new(direction_x, direction_y).angle_between(offset);
+            let turn = if angle > 0.5 {
+                act::Turn::Left
+            } else if angle > 0.1 {
+                act::Turn::QuarterLeft
+            } else if angle < -0.5 {
+                act::Turn::Right
+            } else if angle < -0.1 {
+                act::Turn::QuarterRight
+            } else {
+                act::Turn::None
+            };
+            let thrust = if offset.length() > 400.0 {
+                act::Thrust::On
+            } else {
+                act::Thrust::Off
+            };
+            let shoot = if angle.abs() < 0.2 {
+                act::Shoot::On
+            } else {
+                act::Shoot::Off
+            };
+            action_events.send((
+                act::FighterAction {
+                    thrust,
+                    turn,
+                    shoot,
+                    weapon: act::Weapon::Gun,
+                    ability: act::Ability::None,
+                },
+                *id,
+            ));
         }
     }
 }
@@ -1200,36 +2245,53 @@ fn ai(
 #[allow(clippy::too_many_arguments)]
 fn fighter_actions(
     mut action_events: EventReader<(act::FighterAction, Entity)>,
-    mut cmd: Commands,
+    mut continuous_events: EventReader<(act::ContinuousAction, Entity)>,
+    mut fire_rng: ResMut<FireRng>,
+    mut emp_blasts: EventWriter<ability::EmpBlast>,
+    mut effects: EventWriter<particles::Effect>,
+    mut armory: weapon::Armory,
     mut fighter: Query<(
         &mut Fighter,
-        &Transform,
+        &mut Transform,
         &mut Velocity,
         &mut ExternalImpulse,
         &mut ExternalForce,
         &Children,
+        &mut ability::Abilities,
     )>,
     mut jet: Query<&mut Visibility, With<Jet>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut stats: ResMut<Stats>,
+    mut bullet_fired: EventWriter<event::BulletFired>,
     settings: Res<Settings>,
 ) {
-    for (action, id) in action_events.iter() {
+    // Discrete actions are applied as their continuous equivalent
+    let actions = action_events
+        .iter()
+        .map(|(action, id)| (act::ContinuousAction::from(*action), *id))
+        .chain(continuous_events.iter().copied())
+        .collect::<Vec<_>>();
+    for (action, id) in actions.iter() {
+        // Bots may send any value
+        let clamp = |x: f32| if x.is_nan() { 0.0 } else { x.clamp(-1.0, 1.0) };
+        let thrust = clamp(action.thrust);
+        let turn = clamp(action.turn);
+        // Only sample when needed, so discrete actions don't consume the RNG
+        let shoot = action.fire >= 1.0
+            || (action.fire > 0.0 && fire_rng.0.gen::<f32>() < action.fire);
         if let Ok((
             mut fighter,
-            transform,
+            mut transform,
             mut vel,
             mut imp,
             mut force,
             children,
+            mut abilities,
         )) = fighter.get_mut(*id)
         {
             // Reset rotation and acceleration
             imp.impulse = Vec2::new(0.0, 0.0);
             force.force = Vec2::new(0.0, 0.0);
             force.torque = 0.0;
-            fighter.is_turning = action.turn != act::Turn::None;
+            fighter.is_turning = turn != 0.0;
 
             let rot = transform.rotation.xyz();
             let mut angle = transform.rotation.to_axis_angle().1;
@@ -1238,100 +2300,113 @@ fn fighter_actions(
             }
             let angle2 = angle + std::f32::consts::PI / 2.0;
 
-            vel.angvel += match action.turn {
-                act::Turn::Left => fighter.turn_acceleration,
-                act::Turn::QuarterLeft => fighter.turn_acceleration * 0.25,
-                act::Turn::Right => -fighter.turn_acceleration,
-                act::Turn::QuarterRight => -fighter.turn_acceleration * 0.25,
-                act::Turn::None => 0.0,
-            } * settings.frameskip as f32;
+            vel.angvel +=
+                turn * fighter.turn_acceleration * settings.frameskip as f32;
             vel.angvel = vel
                 .angvel
                 .clamp(-fighter.max_turn_speed, fighter.max_turn_speed);
             let mut jet_visible = fighter.player_id == 0;
+            let mut throttle = 0.0;
             let speed = vel.linvel.length();
-            match action.thrust {
-                act::Thrust::On => {
-                    let thrust = Vec2::new(angle2.cos(), angle2.sin())
-                        * fighter.acceleration;
-                    force.force = thrust;
-                }
-                act::Thrust::Off => {
-                    // Should integrate here rather than just multiplying by interval, whatever
-                    vel.linvel *= 1.0
-                        - fighter.drag_coef
-                            * (speed / fighter.max_velocity)
-                                .powf(fighter.drag_exp)
-                            * fighter.act_interval as f32;
-                    jet_visible = false;
-                }
-                act::Thrust::Stop => {
-                    if speed < 1.0 {
-                        vel.linvel = Vec2::ZERO;
-                    } else {
-                        force.force =
-                            -vel.linvel.normalize() * fighter.deceleration;
-                    }
-                }
+            if thrust >= 0.0 {
+                // Drag fades out as the throttle opens, so that barely
+                // thrusting doesn't switch it off.
+                // Should integrate here rather than just multiplying by interval, whatever
+                vel.linvel *= 1.0
+                    - (1.0 - thrust)
+                        * fighter.drag_coef
+                        * (speed / fighter.max_velocity).powf(fighter.drag_exp)
+                        * fighter.act_interval as f32;
+            }
+            if thrust > 0.0 {
+                throttle = fighter.speed_multiplier()
+                    * abilities.thrust_multiplier()
+                    * thrust;
+                force.force = Vec2::new(angle2.cos(), angle2.sin())
+                    * fighter.acceleration
+                    * throttle;
+            } else if thrust == 0.0 {
+                jet_visible = false;
+            } else if speed < 1.0 {
+                vel.linvel = Vec2::ZERO;
+            } else {
+                force.force =
+                    -vel.linvel.normalize() * fighter.deceleration * -thrust;
             }
             jet.get_mut(*children.first().unwrap()).unwrap().is_visible =
                 jet_visible;
+            effects.send(particles::Effect::Thrust(*id, throttle));
 
-            if let act::Shoot::On = action.shoot {
-                if fighter.remaining_bullet_cooldown <= 0 {
-                    stats.bullets_fired += 1;
-                    spawn_bullet(
-                        &settings,
-                        &mut cmd,
-                        &mut meshes,
-                        &mut materials,
-                        transform.translation
-                            + 24.0
-                                * Vec3::new(angle2.cos(), angle2.sin(), 0.0)
-                                * if fighter.player_id == 0 {
-                                    -0.0
-                                } else {
-                                    1.0
-                                },
-                        vel.linvel
-                            + Vec2::new(angle2.cos(), angle2.sin())
-                                * fighter.bullet_speed,
-                        fighter.bullet_lifetime,
-                        fighter.player_id,
-                    );
-                    fighter.remaining_bullet_cooldown =
-                        fighter.bullet_cooldown as i32;
+            if shoot
+                && armory.fire(
+                    action.weapon,
+                    *id,
+                    &mut fighter,
+                    &transform,
+                    vel.linvel,
+                )
+            {
+                bullet_fired.send(event::BulletFired);
+            }
+            if abilities.try_activate(action.ability) {
+                let direction = Vec2::new(angle2.cos(), angle2.sin());
+                match action.ability {
+                    act::Ability::Blink => {
+                        transform.translation = ability::blink_destination(
+                            transform.translation,
+                            direction,
+                            settings.arena_half_size(),
+                        );
+                    }
+                    act::Ability::Emp => emp_blasts.send(ability::EmpBlast {
+                        position: transform.translation.truncate(),
+                        player_id: fighter.player_id,
+                    }),
+                    _ => {}
                 }
             }
+            // The laser only charges while the trigger is held
+            if !shoot || action.weapon != act::Weapon::Laser {
+                fighter.laser_charge = 0;
+            }
         }
     }
 }
 
 fn load_opponent_policy(
-    settings: Res<Settings>,
-    mut opponent_handles: ResMut<OpponentHandle>,
+    mut policy_handles: ResMut<PolicyHandles>,
+    players: NonSend<Players>,
     server: Res<AssetServer>,
 ) {
-    opponent_handles.0 = settings
-        .opponent_policy
-        .as_ref()
-        .map(|name| server.load(&format!("policies/{}.roguenet", name)));
+    policy_handles.0 = load_policies(&players, &server);
+}
+
+fn load_policies(
+    players: &Players,
+    server: &AssetServer,
+) -> Vec<Option<Handle<RogueNetAsset>>> {
+    players
+        .0
+        .iter()
+        .map(|player| {
+            player
+                .policy
+                .as_ref()
+                .map(|name| server.load(&format!("policies/{}.roguenet", name)))
+        })
+        .collect()
 }
 
 fn apply_policy_asset(
-    settings: Res<Settings>,
     mut players: NonSendMut<Players>,
-    opponent_handle: Res<OpponentHandle>,
+    policy_handles: Res<PolicyHandles>,
     assets: Res<Assets<RogueNetAsset>>,
 ) {
-    if players.0.last().unwrap().agent.is_none() {
-        if let Some(asset) =
-            opponent_handle.0.as_ref().and_then(|h| assets.get(h))
-        {
-            for (i, player) in players.0.iter_mut().enumerate() {
-                if i > 0 || !settings.human_player {
-                    player.agent = Some(Box::new(asset.agent.clone()));
-                }
+    for (player, handle) in players.0.iter_mut().zip(&policy_handles.0) {
+        if player.agent.is_none() {
+            if let Some(asset) = handle.as_ref().and_then(|h| assets.get(h)) {
+                player.agent =
+                    Some(PlayerAgent::Agent(Box::new(asset.agent.clone())));
             }
         }
     }
@@ -1340,6 +2415,7 @@ fn apply_policy_asset(
 fn spawn_highscore_text(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     let text_style = TextStyle {
@@ -1353,11 +2429,14 @@ fn spawn_highscore_text(
     };
     commands
         .spawn(Text2dBundle {
-            text: Text::from_section("Score: 0\nBest: 0", text_style)
-                .with_alignment(text_alignment),
-            transform: Transform::from_translation(Vec3::new(
-                -1000.0, 500.0, 0.3,
-            )),
+            text: Text::from_section(
+                "Score: 0\nBest: 0\nK/D/A: 0/0/0",
+                text_style,
+            )
+            .with_alignment(text_alignment),
+            transform: Transform::from_translation(
+                (settings.arena_half_size() * Vec2::new(-1.0, 1.0)).extend(0.3),
+            ),
             ..default()
         })
         .insert(HighscoreText { best: 0 });
@@ -1369,9 +2448,12 @@ fn update_score(
 ) {
     if let Some((mut highscore, mut text)) = highscore_text.iter_mut().next() {
         let score = stats.player0_score() as u32;
+        let player = stats.player(0);
         highscore.best = highscore.best.max(score);
-        text.sections[0].value =
-            format!("Score: {}\nBest: {}", score, highscore.best);
+        text.sections[0].value = format!(
+            "Score: {}\nBest: {}\nK/D/A: {}/{}/{}",
+            score, highscore.best, player.kills, player.deaths, player.assists
+        );
     }
 }
 
@@ -1388,12 +2470,35 @@ struct Fighter {
     bullet_speed: f32,
     bullet_lifetime: u32,
     remaining_bullet_cooldown: i32,
+    /// Frames for which the laser has been charged.
+    laser_charge: u32,
+    /// Remaining frames of the rapid fire power-up.
+    rapid_fire_time: i32,
+    /// Remaining frames of the speed boost power-up.
+    speed_boost_time: i32,
     player_id: usize,
+    /// Number of fighters the player spawned before this one in the current
+    /// game, which identifies the fighter in replays.
+    number: usize,
     act_interval: u32,
     is_turning: bool,
+    /// Entity the fighter turns towards while not turning, see `targeting`.
+    target: Option<Entity>,
     shield_active: bool,
     shield_cooldown: i32,
     shield_recharge_period: i32,
+    health: f32,
+    max_health: f32,
+}
+
+impl Fighter {
+    fn speed_multiplier(&self) -> f32 {
+        if self.speed_boost_time > 0 {
+            1.5
+        } else {
+            1.0
+        }
+    }
 }
 
 #[derive(Component)]
@@ -1406,6 +2511,8 @@ struct Shield;
 struct Bullet {
     remaining_lifetime: i32,
     player_id: usize,
+    weapon: act::Weapon,
+    damage: f32,
 }
 
 #[derive(Component)]
@@ -1419,9 +2526,108 @@ enum CollisionType {
     Asteroid,
     Fighter,
     Bullet,
+    PowerUp,
 }
 
-struct GameOver;
+/// Collisions reported by Rapier during the current frame.
+///
+/// Rapier may report several contacts for the same bullet or asteroid within a
+/// single frame, and an entity may already have been despawned by an earlier
+/// collision or by `expire_bullets`. Pairs are deduplicated and each entity is
+/// despawned at most once per frame; later collisions involving it are dropped.
+#[derive(Default, Resource)]
+struct CollisionQueue {
+    pending: Vec<(Entity, Entity)>,
+    queued: HashSet<(Entity, Entity)>,
+    consumed: HashSet<Entity>,
+    /// Impulse of the contact between each pair of colliders.
+    impulses: HashMap<(Entity, Entity), f32>,
+}
+
+fn pair_key(a: Entity, b: Entity) -> (Entity, Entity) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl CollisionQueue {
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.queued.clear();
+        self.consumed.clear();
+        self.impulses.clear();
+    }
+
+    fn record_impulse(&mut self, a: Entity, b: Entity, impulse: f32) {
+        *self.impulses.entry(pair_key(a, b)).or_default() += impulse;
+    }
+
+    fn impulse(&self, a: Entity, b: Entity) -> f32 {
+        self.impulses.get(&pair_key(a, b)).copied().unwrap_or(0.0)
+    }
+
+    /// Enqueues a collision, returns `false` if the pair is already queued.
+    fn push(&mut self, a: Entity, b: Entity) -> bool {
+        if !self.queued.insert(pair_key(a, b)) {
+            return false;
+        }
+        self.pending.push((a, b));
+        true
+    }
+
+    fn take_pending(&mut self) -> Vec<(Entity, Entity)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn is_consumed(&self, entity: Entity) -> bool {
+        self.consumed.contains(&entity)
+    }
+
+    /// Marks `entity` as consumed, returns `false` if it already was.
+    fn consume(&mut self, entity: Entity) -> bool {
+        self.consumed.insert(entity)
+    }
+
+    fn despawn(&mut self, cmd: &mut Commands, entity: Entity) {
+        if self.consume(entity) {
+            match cmd.get_entity(entity) {
+                Some(mut e) => e.despawn(),
+                None => log::warn!("Entity {:?} despawned twice", entity),
+            }
+        }
+    }
+
+    fn despawn_recursive(&mut self, cmd: &mut Commands, entity: Entity) {
+        if self.consume(entity) {
+            match cmd.get_entity(entity) {
+                Some(e) => e.despawn_recursive(),
+                None => log::warn!("Entity {:?} despawned twice", entity),
+            }
+        }
+    }
+}
+
+struct GameOver;
+
+/// Final stats of a game, sent by `reset` before starting the next one.
+struct GameSummary(Stats);
+
+/// Reasons for which the simulation is currently halted.
+#[derive(Default, Resource)]
+struct SimulationGate {
+    /// Waiting for the inputs of remote players.
+    network_stall: bool,
+    /// In a menu, paused or showing the game-over screen.
+    paused: bool,
+}
+
+impl SimulationGate {
+    fn is_open(&self) -> bool {
+        !self.network_stall && !self.paused
+    }
+}
 
 #[derive(Resource)]
 struct RemainingTime(i32);
@@ -1429,28 +2635,352 @@ struct RemainingTime(i32);
 #[derive(Resource)]
 struct RngState(SmallRng);
 
+/// Samples the shots of continuous actions, kept apart from `RngState` so
+/// that continuous control doesn't change asteroid spawns and the other
+/// randomness of the game.
+#[derive(Resource)]
+struct FireRng(SmallRng);
+
 #[derive(Debug)]
 struct Players(Vec<Player>);
 
 struct Player {
-    agent: Option<Box<dyn Agent>>,
+    agent: Option<PlayerAgent>,
+    /// Local humans piloting this player's fighters.
+    humans: Vec<HumanPilot>,
+    /// Controlled by a remote human through `net::NetPlugin`.
+    remote: bool,
+    /// Controlled by an external agent through `remote::RemoteAgentPlugin`,
+    /// which becomes the `agent` once connected.
+    bot: bool,
+    scripted: bool,
+    /// Name of the policy asset assigned to `agent` once loaded.
+    policy: Option<String>,
     ids: Vec<Entity>,
     respawns: Vec<i32>,
+    /// Collected `PowerUpKind::ExtraLife` power-ups.
+    extra_lives: u32,
+    /// Fighters spawned in the current game.
+    spawned: usize,
+    /// Action interval of new fighters, overriding the one derived from the
+    /// settings.
+    act_interval: Option<u32>,
+}
+
+/// Chooses the actions of a player's fighters.
+enum PlayerAgent {
+    /// entity-gym agent, such as a policy or an agent being trained.
+    Agent(Box<dyn Agent>),
+    /// Agent in another process, which can't implement `Agent` since
+    /// entity-gym-rs neither exposes the features of an `Obs` nor lets other
+    /// crates create the `ActionReceiver`s returned by `Agent::act_async_dyn`.
+    #[cfg(not(target_arch = "wasm32"))]
+    Remote(remote::RemoteAgent),
+}
+
+/// Decisions of an agent for its player's fighters, `None` once the agent
+/// stopped.
+type PendingDecisions<'a> = Box<dyn FnOnce() -> Option<Decisions> + 'a>;
+
+/// Actions, continuous actions and targets chosen by an agent.
+#[derive(Default)]
+struct Decisions {
+    actions: Vec<(act::FighterAction, Entity)>,
+    continuous: Vec<(act::ContinuousAction, Entity)>,
+    targets: Vec<(act::Target, Entity)>,
+}
+
+impl PlayerAgent {
+    /// Starts choosing actions for the fighters `ids` of the player that made
+    /// the `observation`.
+    fn act(
+        &mut self,
+        observation: Observation,
+        ids: Vec<Entity>,
+        settings: &Settings,
+    ) -> PendingDecisions<'_> {
+        let agent = match self {
+            PlayerAgent::Agent(agent) => agent,
+            #[cfg(not(target_arch = "wasm32"))]
+            PlayerAgent::Remote(agent) => {
+                return agent.act(observation, ids, settings)
+            }
+        };
+        let obs = observation.into_obs(settings);
+        if settings.agent_loadout {
+            let action = agent.act_async::<act::FighterAction>(&obs);
+            Box::new(move || {
+                let actions = action.rcv()?.into_iter().zip(ids).collect();
+                Some(Decisions {
+                    actions,
+                    ..default()
+                })
+            })
+        } else {
+            let action = agent.act_async::<act::classic::FighterAction>(&obs);
+            Box::new(move || {
+                let actions = action
+                    .rcv()?
+                    .into_iter()
+                    .map(Into::into)
+                    .zip(ids)
+                    .collect();
+                Some(Decisions {
+                    actions,
+                    ..default()
+                })
+            })
+        }
+    }
+
+    /// Decisions that arrived since the last step without an observation.
+    fn poll(&mut self) -> Option<Decisions> {
+        match self {
+            PlayerAgent::Agent(_) => Some(Decisions::default()),
+            #[cfg(not(target_arch = "wasm32"))]
+            PlayerAgent::Remote(agent) => agent.poll(),
+        }
+    }
+
+    /// Reports the end of the game to the agent of `player`.
+    fn game_over(&mut self, player: usize, stats: &Stats) {
+        let agent = match self {
+            PlayerAgent::Agent(agent) => agent,
+            #[cfg(not(target_arch = "wasm32"))]
+            PlayerAgent::Remote(agent) => {
+                return agent.game_over(stats.score(player), stats)
+            }
+        };
+        let score = stats.score(player);
+        let player_stats = stats.player(player);
+        agent.game_over(
+            &Obs::new(score)
+                .metric("bullets_fired", stats.bullets_fired as f32)
+                .metric("timesteps", stats.timesteps as f32)
+                .metric("bullet_hits", stats.bullet_hits as f32)
+                .metric("destroyed_asteroids", stats.destroyed_asteroids as f32)
+                .metric("destroyed_opponents", stats.destroyed_opponents as f32)
+                .metric("destroyed_allies", stats.destroyed_allies as f32)
+                .metric("kills", player_stats.kills as f32)
+                .metric("deaths", player_stats.deaths as f32)
+                .metric("assists", player_stats.assists as f32)
+                .metric(&format!("player_{}_score", player), score),
+        );
+    }
+}
+
+struct HumanPilot {
+    /// Index of the binding set in `Settings.input`.
+    bindings: usize,
+    fighter: Option<Entity>,
+    weapon: act::Weapon,
+}
+
+impl Player {
+    fn new(agent: Option<Box<dyn Agent>>, controller: &Controller) -> Player {
+        // Agents passed in by the caller take precedence over the controller
+        let controller = if agent.is_some() {
+            &Controller::Idle
+        } else {
+            controller
+        };
+        Player {
+            agent: agent.map(PlayerAgent::Agent),
+            humans: match controller {
+                Controller::Human(bindings) => bindings
+                    .iter()
+                    .map(|&bindings| HumanPilot {
+                        bindings,
+                        fighter: None,
+                        weapon: act::Weapon::Gun,
+                    })
+                    .collect(),
+                _ => vec![],
+            },
+            remote: *controller == Controller::Remote,
+            bot: *controller == Controller::Bot,
+            scripted: *controller == Controller::Scripted,
+            policy: match controller {
+                Controller::Policy(name) => Some(name.clone()),
+                _ => None,
+            },
+            ids: vec![],
+            respawns: vec![],
+            extra_lives: 0,
+            spawned: 0,
+            act_interval: None,
+        }
+    }
+
+    /// Action interval of the player's next fighter.
+    fn fighter_act_interval(&self, settings: &Settings) -> u32 {
+        match (self.act_interval, &self.agent) {
+            (Some(interval), _) => interval,
+            (None, Some(_)) => settings
+                .ai_action_interval
+                .unwrap_or(settings.action_interval),
+            (None, None) => settings.action_interval,
+        }
+    }
+
+    /// Short description of the player's controller.
+    fn kind(&self) -> &'static str {
+        if !self.humans.is_empty() {
+            "human"
+        } else if self.remote {
+            "remote"
+        } else if self.bot {
+            "bot"
+        } else if self.scripted {
+            "scripted"
+        } else if self.policy.is_some() {
+            "policy"
+        } else if self.agent.is_some() {
+            "agent"
+        } else {
+            "idle"
+        }
+    }
+
+    /// Number of fighters the player fields after the given number of
+    /// timesteps.
+    fn target_fighter_count(
+        &self,
+        player_id: usize,
+        settings: &Settings,
+        timesteps: usize,
+    ) -> usize {
+        if !self.humans.is_empty() {
+            self.humans.len()
+        } else if self.remote || player_id == 0 {
+            1
+        } else {
+            settings.difficulty(timesteps)
+        }
+    }
+
+    fn remove_fighter(&mut self, fighter: Entity) -> bool {
+        match self.ids.iter().position(|id| *id == fighter) {
+            Some(index) => {
+                self.ids.remove(index);
+                for pilot in &mut self.humans {
+                    if pilot.fighter == Some(fighter) {
+                        pilot.fighter = None;
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+        self.respawns.clear();
+        self.extra_lives = 0;
+        self.spawned = 0;
+        for pilot in &mut self.humans {
+            pilot.fighter = None;
+        }
+    }
 }
 
 impl std::fmt::Debug for Player {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Player")
+            .field("humans", &self.humans.len())
+            .field("remote", &self.remote)
+            .field("bot", &self.bot)
+            .field("scripted", &self.scripted)
+            .field("policy", &self.policy)
             .field("ids", &self.ids)
             .field("respawns", &self.respawns)
+            .field("extra_lives", &self.extra_lives)
             .finish()
     }
 }
 
 pub mod entity {
+    use std::ops::Deref;
+
     use entity_gym_rs::agent::Featurizable;
+    use serde::Serialize;
+
+    use crate::Observer;
+
+    /// Observed entity with the ID that stays the same for as long as it
+    /// exists, see `ids`. Bots always receive the ID along with the features
+    /// to select targets, policies only observe it as the first feature of
+    /// the entity type with `Settings.observe_ids`.
+    #[derive(Clone, Serialize)]
+    pub struct Identified<E> {
+        pub id: u32,
+        #[serde(flatten)]
+        pub entity: E,
+    }
+
+    impl<E> Deref for Identified<E> {
+        type Target = E;
+
+        fn deref(&self) -> &E {
+            &self.entity
+        }
+    }
+
+    impl<E: Featurizable> Featurizable for Identified<E> {
+        fn num_feats() -> usize {
+            1 + E::num_feats()
+        }
+
+        fn feature_names() -> Vec<String> {
+            let mut names = vec!["id".to_string()];
+            names.extend(E::feature_names());
+            names
+        }
+
+        fn featurize(&self) -> Vec<f32> {
+            let mut features = vec![self.id as f32];
+            features.extend(self.entity.featurize());
+            features
+        }
+
+        fn name() -> &'static str {
+            E::name()
+        }
+    }
+
+    /// The entities without their IDs.
+    pub fn without_ids<E>(entities: Vec<Identified<E>>) -> Vec<E> {
+        entities.into_iter().map(|e| e.entity).collect()
+    }
+
+    /// Adds `entities` to `obs`, with their IDs if `ids` is set.
+    pub(crate) fn observe<O: Observer, E: Featurizable + Serialize>(
+        obs: O,
+        entities: Vec<Identified<E>>,
+        ids: bool,
+    ) -> O {
+        if ids {
+            obs.entities(entities)
+        } else {
+            obs.entities(without_ids(entities))
+        }
+    }
+
+    /// Adds `actors` to `obs`, with their IDs if `ids` is set.
+    pub(crate) fn observe_actors<O: Observer, E: Featurizable + Serialize>(
+        obs: O,
+        actors: Vec<Identified<E>>,
+        ids: bool,
+    ) -> O {
+        if ids {
+            obs.actors(actors)
+        } else {
+            obs.actors(without_ids(actors))
+        }
+    }
 
-    #[derive(Featurizable)]
+    #[derive(Clone, Featurizable, Serialize)]
     pub struct Asteroid {
         pub health: f32,
         pub radius: f32,
@@ -1460,7 +2990,7 @@ pub mod entity {
         pub dy: f32,
     }
 
-    #[derive(Featurizable)]
+    #[derive(Clone, Featurizable, Serialize)]
     pub struct Fighter {
         pub x: f32,
         pub y: f32,
@@ -1473,9 +3003,22 @@ pub mod entity {
         pub player: u32,
         pub shield_active: bool,
         pub shield_cooldown: f32,
+        /// Fraction of the charge required to fire the laser.
+        pub laser_charge: f32,
+        /// Fraction of the maximum ability energy.
+        pub energy: f32,
+        pub front_shield_active: bool,
+        /// Remaining fraction of each ability's cooldown.
+        pub front_shield_cooldown: f32,
+        pub boost_active: bool,
+        pub boost_cooldown: f32,
+        pub blink_cooldown: f32,
+        pub emp_cooldown: f32,
+        /// Fraction of the maximum hull points that remain.
+        pub health: f32,
     }
 
-    #[derive(Featurizable)]
+    #[derive(Clone, Featurizable, Serialize)]
     pub struct EnemyFighter {
         pub x: f32,
         pub y: f32,
@@ -1485,6 +3028,7 @@ pub mod entity {
         pub direction_y: f32,
         pub gun_cooldown: u32,
         pub player: u32,
+        pub health: f32,
 
         pub reldx: f32,
         pub reldy: f32,
@@ -1492,7 +3036,7 @@ pub mod entity {
         pub reldirection_y: f32,
     }
 
-    #[derive(Featurizable)]
+    #[derive(Clone, Featurizable, Serialize)]
     pub struct Bullet {
         pub x: f32,
         pub y: f32,
@@ -1500,30 +3044,207 @@ pub mod entity {
         pub dy: f32,
         pub lifetime: i32,
         pub player: u32,
+        /// Index of the `act::Weapon` that fired the projectile.
+        pub weapon: u32,
 
         pub reldx: f32,
         pub reldy: f32,
     }
+
+    /// State of a fighter when agents acted `age` frames ago, see `history`.
+    #[derive(Clone, Featurizable, Serialize)]
+    pub struct PastFighter {
+        pub x: f32,
+        pub y: f32,
+        pub dx: f32,
+        pub dy: f32,
+        pub direction_x: f32,
+        pub direction_y: f32,
+        pub player: u32,
+        pub age: i32,
+
+        pub reldx: f32,
+        pub reldy: f32,
+        pub reldirection_x: f32,
+        pub reldirection_y: f32,
+    }
+
+    #[derive(Clone, Featurizable, Serialize)]
+    pub struct PowerUp {
+        pub x: f32,
+        pub y: f32,
+        /// Index of the kind of power-up: restore, rapid fire, speed boost or
+        /// extra life.
+        pub kind: u32,
+        pub lifetime: i32,
+    }
+
+    /// Entities with the features that agents observed before weapons,
+    /// abilities and hull points, which is what agents observe
+    /// unless `Settings.agent_loadout` is set.
+    pub mod classic {
+        use entity_gym_rs::agent::Featurizable;
+        use serde::Serialize;
+
+        use super::Identified;
+
+        /// Entities of one `Observation`.
+        pub struct Entities {
+            pub fighters: Vec<Identified<Fighter>>,
+            pub enemy_fighters: Vec<Identified<EnemyFighter>>,
+            pub asteroids: Vec<Identified<Asteroid>>,
+            pub bullets: Vec<Identified<Bullet>>,
+        }
+
+        #[derive(Featurizable, Serialize)]
+        pub struct Asteroid {
+            pub health: f32,
+            pub radius: f32,
+            pub x: f32,
+            pub y: f32,
+            pub dx: f32,
+            pub dy: f32,
+        }
+
+        impl From<&super::Asteroid> for Asteroid {
+            fn from(asteroid: &super::Asteroid) -> Self {
+                Asteroid {
+                    health: asteroid.health,
+                    radius: asteroid.radius,
+                    x: asteroid.x,
+                    y: asteroid.y,
+                    dx: asteroid.dx,
+                    dy: asteroid.dy,
+                }
+            }
+        }
+
+        #[derive(Featurizable, Serialize)]
+        pub struct Fighter {
+            pub x: f32,
+            pub y: f32,
+            pub dx: f32,
+            pub dy: f32,
+            pub direction_x: f32,
+            pub direction_y: f32,
+            pub remaining_time: i32,
+            pub gun_cooldown: u32,
+            pub player: u32,
+            pub shield_active: bool,
+            pub shield_cooldown: f32,
+        }
+
+        impl From<&super::Fighter> for Fighter {
+            fn from(fighter: &super::Fighter) -> Self {
+                Fighter {
+                    x: fighter.x,
+                    y: fighter.y,
+                    dx: fighter.dx,
+                    dy: fighter.dy,
+                    direction_x: fighter.direction_x,
+                    direction_y: fighter.direction_y,
+                    remaining_time: fighter.remaining_time,
+                    gun_cooldown: fighter.gun_cooldown,
+                    player: fighter.player,
+                    shield_active: fighter.shield_active,
+                    shield_cooldown: fighter.shield_cooldown,
+                }
+            }
+        }
+
+        #[derive(Featurizable, Serialize)]
+        pub struct EnemyFighter {
+            pub x: f32,
+            pub y: f32,
+            pub dx: f32,
+            pub dy: f32,
+            pub direction_x: f32,
+            pub direction_y: f32,
+            pub gun_cooldown: u32,
+            pub player: u32,
+
+            pub reldx: f32,
+            pub reldy: f32,
+            pub reldirection_x: f32,
+            pub reldirection_y: f32,
+        }
+
+        impl From<&super::EnemyFighter> for EnemyFighter {
+            fn from(fighter: &super::EnemyFighter) -> Self {
+                EnemyFighter {
+                    x: fighter.x,
+                    y: fighter.y,
+                    dx: fighter.dx,
+                    dy: fighter.dy,
+                    direction_x: fighter.direction_x,
+                    direction_y: fighter.direction_y,
+                    gun_cooldown: fighter.gun_cooldown,
+                    player: fighter.player,
+                    reldx: fighter.reldx,
+                    reldy: fighter.reldy,
+                    reldirection_x: fighter.reldirection_x,
+                    reldirection_y: fighter.reldirection_y,
+                }
+            }
+        }
+
+        #[derive(Featurizable, Serialize)]
+        pub struct Bullet {
+            pub x: f32,
+            pub y: f32,
+            pub dx: f32,
+            pub dy: f32,
+            pub lifetime: i32,
+            pub player: u32,
+
+            pub reldx: f32,
+            pub reldy: f32,
+        }
+
+        impl From<&super::Bullet> for Bullet {
+            fn from(bullet: &super::Bullet) -> Self {
+                Bullet {
+                    x: bullet.x,
+                    y: bullet.y,
+                    dx: bullet.dx,
+                    dy: bullet.dy,
+                    lifetime: bullet.lifetime,
+                    player: bullet.player,
+                    reldx: bullet.reldx,
+                    reldy: bullet.reldy,
+                }
+            }
+        }
+    }
 }
 
 pub mod act {
     use entity_gym_rs::agent::Action;
+    use serde::{Deserialize, Serialize};
 
-    #[derive(Action, Clone, Copy, Debug)]
+    #[derive(Action, Clone, Copy, Debug, Serialize, Deserialize)]
     pub struct FighterAction {
         pub thrust: Thrust,
         pub shoot: Shoot,
         pub turn: Turn,
+        #[serde(default)]
+        pub weapon: Weapon,
+        #[serde(default)]
+        pub ability: Ability,
     }
 
-    #[derive(Action, Clone, Copy, Debug, PartialEq, Eq)]
+    #[derive(
+        Action, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize,
+    )]
     pub enum Thrust {
         On,
         Off,
         Stop,
     }
 
-    #[derive(Action, Clone, Copy, Debug, PartialEq, Eq)]
+    #[derive(
+        Action, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize,
+    )]
     pub enum Turn {
         Left,
         QuarterLeft,
@@ -1532,11 +3253,158 @@ pub mod act {
         None,
     }
 
-    #[derive(Action, Clone, Copy, Debug, PartialEq, Eq)]
+    #[derive(
+        Action, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize,
+    )]
     pub enum Shoot {
         On,
         Off,
     }
+
+    /// Weapon fired by `Shoot::On`.
+    #[derive(
+        Action, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize,
+    )]
+    pub enum Weapon {
+        Gun,
+        Spread,
+        Laser,
+        Missile,
+        Mine,
+    }
+
+    impl Weapon {
+        pub const ALL: [Weapon; 5] = [
+            Weapon::Gun,
+            Weapon::Spread,
+            Weapon::Laser,
+            Weapon::Missile,
+            Weapon::Mine,
+        ];
+    }
+
+    impl Default for Weapon {
+        fn default() -> Self {
+            Weapon::Gun
+        }
+    }
+
+    /// Ability triggered along with the rest of the action.
+    #[derive(
+        Action, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize,
+    )]
+    pub enum Ability {
+        None,
+        Shield,
+        Boost,
+        Blink,
+        Emp,
+    }
+
+    impl Ability {
+        pub const ALL: [Ability; 5] = [
+            Ability::None,
+            Ability::Shield,
+            Ability::Boost,
+            Ability::Blink,
+            Ability::Emp,
+        ];
+    }
+
+    impl Default for Ability {
+        fn default() -> Self {
+            Ability::None
+        }
+    }
+
+    /// `FighterAction`s that always fire the gun and use no ability, which is
+    /// what agents choose unless `Settings.agent_loadout` is set.
+    pub mod classic {
+        use entity_gym_rs::agent::Action;
+        use serde::Deserialize;
+
+        use super::{Ability, Shoot, Thrust, Turn, Weapon};
+
+        #[derive(Action, Clone, Copy, Debug, Deserialize)]
+        pub struct FighterAction {
+            pub thrust: Thrust,
+            pub shoot: Shoot,
+            pub turn: Turn,
+        }
+
+        impl From<FighterAction> for super::FighterAction {
+            fn from(action: FighterAction) -> Self {
+                super::FighterAction {
+                    thrust: action.thrust,
+                    shoot: action.shoot,
+                    turn: action.turn,
+                    weapon: Weapon::Gun,
+                    ability: Ability::None,
+                }
+            }
+        }
+
+        impl From<super::FighterAction> for FighterAction {
+            fn from(action: super::FighterAction) -> Self {
+                FighterAction {
+                    thrust: action.thrust,
+                    shoot: action.shoot,
+                    turn: action.turn,
+                }
+            }
+        }
+    }
+
+    /// Alternative to `FighterAction` with continuous thrust and turn, for
+    /// analog gamepads and continuous control, see
+    /// `Settings.continuous_control`.
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+    pub struct ContinuousAction {
+        /// Forward thrust if positive, braking if negative, in [-1, 1]. Drag
+        /// slows the fighter in proportion to `1 - thrust` unless braking.
+        pub thrust: f32,
+        /// Turn torque in [-1, 1], positive to the left.
+        pub turn: f32,
+        /// Probability of firing in [0, 1].
+        pub fire: f32,
+        #[serde(default)]
+        pub weapon: Weapon,
+        #[serde(default)]
+        pub ability: Ability,
+    }
+
+    /// The continuous action with the same effect.
+    impl From<FighterAction> for ContinuousAction {
+        fn from(action: FighterAction) -> Self {
+            ContinuousAction {
+                thrust: match action.thrust {
+                    Thrust::On => 1.0,
+                    Thrust::Off => 0.0,
+                    Thrust::Stop => -1.0,
+                },
+                turn: match action.turn {
+                    Turn::Left => 1.0,
+                    Turn::QuarterLeft => 0.25,
+                    Turn::Right => -1.0,
+                    Turn::QuarterRight => -0.25,
+                    Turn::None => 0.0,
+                },
+                fire: match action.shoot {
+                    Shoot::On => 1.0,
+                    Shoot::Off => 0.0,
+                },
+                weapon: action.weapon,
+                ability: action.ability,
+            }
+        }
+    }
+
+    /// Entity-reference action selecting the observed entity with the given
+    /// `id` as the target of a fighter's auto-aim, or clearing it.
+    #[derive(
+        Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+    )]
+    pub struct Target(pub Option<u32>);
 }
 
 fn transform_to_direction(transform: &Transform) -> (f32, f32) {
@@ -1574,6 +3442,31 @@ impl Default for Settings {
             physics_debug_render: false,
             log_diagnostics: false,
             disable_bloom: false,
+            input: InputConfig::default(),
+            controllers: vec![],
+            continuous_control: vec![],
+            network: None,
+            bot_server: None,
+            powerup_interval: 0,
+            max_powerups: 3,
+            powerup_lifetime: 900,
+            hull: 1.0,
+            opponent_hull: 1.0,
+            ram_damage_per_impulse: RAM_DAMAGE_PER_IMPULSE,
+            arena_size: Vec2::new(2000.0, 1000.0),
+            agent_debug_render: false,
+            camera_mode: camera::CameraMode::Fixed,
+            sensor_range: None,
+            observation_history: 0,
+            agent_loadout: false,
+            observe_ids: false,
+            kill_rewards: false,
+            minimap_sensor_range: false,
+            software_render_width: None,
+            record_frames: None,
+            record_replay: None,
+            metrics_log: None,
+            record_demonstrations: None,
         }
     }
 }
@@ -1591,3 +3484,843 @@ impl DerefMut for RngState {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+    use bevy::ecs::system::SystemState;
+    use bevy_rapier2d::rapier::geometry::CollisionEventFlags;
+
+    use super::*;
+
+    pub(crate) fn fighter(player_id: usize) -> Fighter {
+        Fighter {
+            max_velocity: 1000.0,
+            acceleration: 1000000.0,
+            deceleration: 1000000.0,
+            drag_exp: 1.5,
+            drag_coef: 0.02,
+            turn_acceleration: 0.5,
+            max_turn_speed: 8.0,
+            bullet_cooldown: 24,
+            bullet_speed: 2500.0,
+            bullet_lifetime: 24,
+            remaining_bullet_cooldown: 0,
+            laser_charge: 0,
+            rapid_fire_time: 0,
+            speed_boost_time: 0,
+            player_id,
+            number: 0,
+            act_interval: 1,
+            is_turning: false,
+            target: None,
+            shield_active: true,
+            shield_cooldown: 0,
+            shield_recharge_period: 300,
+            health: 3.0,
+            max_health: 3.0,
+        }
+    }
+
+    fn collision_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<ColorMaterial>()
+            .insert_resource(Settings::default())
+            .init_resource::<CollisionQueue>()
+            .insert_non_send_resource(Players(vec![]))
+            .add_event::<CollisionEvent>()
+            .add_event::<ContactForceEvent>()
+            .add_event::<GameOver>()
+            .add_event::<particles::Effect>()
+            .add_event::<event::FighterHit>()
+            .add_event::<event::ShieldBroken>()
+            .add_event::<event::FighterDestroyed>()
+            .add_event::<event::AsteroidHit>()
+            .add_event::<event::AsteroidDestroyed>()
+            .add_system(detect_collisions);
+        app
+    }
+
+    #[test]
+    fn bullet_hits_once_per_frame() {
+        let mut app = collision_app();
+        let fighters = [1, 2].map(|player_id| {
+            app.world
+                .spawn((
+                    fighter(player_id),
+                    CollisionType::Fighter,
+                    Transform::default(),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(Jet);
+                })
+                .id()
+        });
+        let bullet = app
+            .world
+            .spawn((
+                Bullet {
+                    remaining_lifetime: 24,
+                    player_id: 0,
+                    weapon: act::Weapon::Gun,
+                    damage: 1.0,
+                },
+                CollisionType::Bullet,
+                Transform::default(),
+            ))
+            .id();
+        // Rapier reports the same contact in both orders, and the bullet
+        // touches both fighters in the same step
+        let flags = CollisionEventFlags::empty();
+        let mut events = app.world.resource_mut::<Events<CollisionEvent>>();
+        events.send(CollisionEvent::Started(fighters[0], bullet, flags));
+        events.send(CollisionEvent::Started(bullet, fighters[0], flags));
+        events.send(CollisionEvent::Started(fighters[1], bullet, flags));
+
+        app.update();
+
+        // Only the shield of the first fighter absorbed the bullet
+        let shielded = fighters
+            .iter()
+            .filter(|f| app.world.get::<Fighter>(**f).unwrap().shield_active)
+            .count();
+        assert_eq!(shielded, 1);
+        assert!(app.world.get_entity(bullet).is_none());
+        let broken = app.world.resource::<Events<event::ShieldBroken>>();
+        assert_eq!(broken.get_reader().iter(broken).count(), 1);
+    }
+
+    /// Fires `kind` from `shooter`, which faces up from the origin.
+    fn fire(app: &mut App, shooter: Entity, kind: act::Weapon) -> bool {
+        let mut fighter =
+            app.world.entity_mut(shooter).remove::<Fighter>().unwrap();
+        let mut armory = SystemState::<weapon::Armory>::new(&mut app.world);
+        let fired = armory.get_mut(&mut app.world).fire(
+            kind,
+            shooter,
+            &mut fighter,
+            &Transform::default(),
+            Vec2::ZERO,
+        );
+        armory.apply(&mut app.world);
+        app.world.entity_mut(shooter).insert(fighter);
+        fired
+    }
+
+    fn spawn_asteroid(app: &mut App, position: Vec2) -> Entity {
+        let transform = Transform::from_translation(position.extend(0.0));
+        app.world
+            .spawn((
+                Asteroid {
+                    health: 3.0,
+                    radius: 50.0,
+                },
+                Handle::<ColorMaterial>::default(),
+                CollisionType::Asteroid,
+                Collider::ball(50.0),
+                transform,
+                GlobalTransform::from(transform),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn laser_hits_first_target_along_the_ray() {
+        let mut app = collision_app();
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(
+            1.0,
+        ))
+        .insert_resource(RapierConfiguration {
+            gravity: Vect::ZERO,
+            timestep_mode: TimestepMode::Fixed {
+                dt: 1.0 / 90.0,
+                substeps: 1,
+            },
+            ..default()
+        })
+        .add_asset::<Mesh>();
+        let mut charged = fighter(1);
+        charged.laser_charge = weapon::LASER_CHARGE_TIME - 1;
+        let shooter = app
+            .world
+            .spawn((
+                charged,
+                CollisionType::Fighter,
+                Collider::ball(20.0),
+                Transform::default(),
+                GlobalTransform::default(),
+            ))
+            .with_children(|parent| {
+                parent.spawn(Jet);
+            })
+            .id();
+        // The beam passes through the trigger of a mine in front of the
+        // shooter and stops at the nearer asteroid
+        app.world.spawn((
+            Bullet {
+                remaining_lifetime: 1800,
+                player_id: 2,
+                weapon: act::Weapon::Mine,
+                damage: 3.0,
+            },
+            CollisionType::Bullet,
+            Collider::ball(60.0),
+            Sensor,
+            Transform::from_xyz(0.0, 150.0, 0.0),
+            GlobalTransform::from_xyz(0.0, 150.0, 0.0),
+        ));
+        let asteroids =
+            [300.0, 600.0].map(|y| spawn_asteroid(&mut app, Vec2::new(0.0, y)));
+        // Add the colliders to the query pipeline
+        app.update();
+
+        assert!(fire(&mut app, shooter, act::Weapon::Laser));
+
+        let mut lasers = app.world.query::<&weapon::Laser>();
+        let laser = lasers.single(&app.world);
+        assert_eq!(laser.target, Some(asteroids[0]));
+        assert!((laser.length - 250.0).abs() < 0.1);
+        for _ in 0..2 {
+            app.update();
+        }
+        // The beam outlives the hit, but only damages the asteroid once
+        assert_eq!(lasers.iter(&app.world).count(), 1);
+        let health = |app: &App, asteroid| {
+            app.world.get::<Asteroid>(asteroid).unwrap().health
+        };
+        assert_eq!(health(&app, asteroids[0]), 1.0);
+        assert_eq!(health(&app, asteroids[1]), 3.0);
+    }
+
+    #[test]
+    fn missile_turns_towards_nearest_enemy() {
+        let mut app = collision_app();
+        app.init_resource::<RapierContext>()
+            .add_asset::<Mesh>()
+            .add_system(weapon::steer_missiles);
+        let shooter = app.world.spawn((fighter(1), Transform::default())).id();
+        // Teammates are ignored, and the enemy straight ahead is farther
+        // than the one to the right
+        app.world
+            .spawn((fighter(1), Transform::from_xyz(-100.0, 0.0, 0.0)));
+        app.world
+            .spawn((fighter(2), Transform::from_xyz(500.0, 0.0, 0.0)));
+        app.world
+            .spawn((fighter(3), Transform::from_xyz(0.0, 2000.0, 0.0)));
+        assert!(fire(&mut app, shooter, act::Weapon::Missile));
+
+        app.update();
+
+        let mut missiles = app
+            .world
+            .query_filtered::<&Velocity, With<weapon::Missile>>();
+        let velocity = missiles.single(&app.world).linvel;
+        let settings = app.world.resource::<Settings>();
+        let max_turn = weapon::MISSILE_TURN_RATE * settings.frameskip as f32;
+        // Fired straight up, the missile turns right as fast as it can
+        assert!((Vec2::Y.angle_between(velocity) + max_turn).abs() < 1e-4);
+        assert!((velocity.length() - 2500.0 * 0.4).abs() < 1e-2);
+    }
+
+    #[test]
+    fn mine_arms_and_only_triggers_on_enemy_fighters() {
+        let mut app = collision_app();
+        app.init_resource::<RapierContext>()
+            .add_asset::<Mesh>()
+            .add_system(weapon::arm_mines);
+        let [shooter, enemy] = [1, 2].map(|player_id| {
+            app.world
+                .spawn((
+                    fighter(player_id),
+                    CollisionType::Fighter,
+                    Transform::default(),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(Jet);
+                })
+                .id()
+        });
+        assert!(fire(&mut app, shooter, act::Weapon::Mine));
+        let mine = app
+            .world
+            .query_filtered::<Entity, With<weapon::Mine>>()
+            .single(&app.world);
+
+        for _ in 1..weapon::MINE_ARM_TIME {
+            app.update();
+        }
+        assert!(app.world.get::<Collider>(mine).is_none());
+        app.update();
+        let trigger = app.world.get::<Collider>(mine).unwrap();
+        assert_eq!(
+            trigger.as_ball().unwrap().radius(),
+            weapon::MINE_TRIGGER_RADIUS
+        );
+        assert!(app.world.get::<Sensor>(mine).is_some());
+
+        let asteroid = spawn_asteroid(&mut app, Vec2::ZERO);
+        let bullet = app
+            .world
+            .spawn((
+                Bullet {
+                    remaining_lifetime: 24,
+                    player_id: 2,
+                    weapon: act::Weapon::Gun,
+                    damage: 1.0,
+                },
+                CollisionType::Bullet,
+            ))
+            .id();
+        let flags = CollisionEventFlags::empty();
+        let mut events = app.world.resource_mut::<Events<CollisionEvent>>();
+        events.send(CollisionEvent::Started(mine, shooter, flags));
+        events.send(CollisionEvent::Started(asteroid, mine, flags));
+        events.send(CollisionEvent::Started(bullet, mine, flags));
+        app.update();
+
+        assert!(app.world.get_entity(mine).is_some());
+        assert!(app.world.get_entity(bullet).is_some());
+        assert_eq!(app.world.get::<Asteroid>(asteroid).unwrap().health, 3.0);
+        assert!(app.world.get::<Fighter>(shooter).unwrap().shield_active);
+
+        let mut events = app.world.resource_mut::<Events<CollisionEvent>>();
+        events.send(CollisionEvent::Started(enemy, mine, flags));
+        app.update();
+
+        assert!(app.world.get_entity(mine).is_none());
+        assert!(!app.world.get::<Fighter>(enemy).unwrap().shield_active);
+    }
+
+    #[test]
+    fn projectiles_deal_their_damage_to_the_hull() {
+        let mut app = collision_app();
+        app.init_resource::<RapierContext>().add_asset::<Mesh>();
+        let shooter = app.world.spawn((fighter(1), Transform::default())).id();
+        let mut sturdy = fighter(2);
+        sturdy.shield_active = false;
+        sturdy.health = 10.0;
+        sturdy.max_health = 10.0;
+        let target = app
+            .world
+            .spawn((sturdy, CollisionType::Fighter, Transform::default()))
+            .with_children(|parent| {
+                parent.spawn(Jet);
+            })
+            .id();
+        let mut bullets = app.world.query_filtered::<Entity, With<Bullet>>();
+        let hits = [
+            (act::Weapon::Gun, 1.0),
+            (act::Weapon::Spread, 0.5),
+            (act::Weapon::Missile, 3.0),
+            (act::Weapon::Mine, 3.0),
+        ];
+        for (kind, damage) in hits {
+            let mut armed = app.world.get_mut::<Fighter>(shooter).unwrap();
+            armed.remaining_bullet_cooldown = 0;
+            assert!(fire(&mut app, shooter, kind));
+            let fired = bullets.iter(&app.world).collect::<Vec<_>>();
+            let health = app.world.get::<Fighter>(target).unwrap().health;
+            app.world.send_event(CollisionEvent::Started(
+                fired[0],
+                target,
+                CollisionEventFlags::empty(),
+            ));
+
+            app.update();
+
+            let hit = app.world.get::<Fighter>(target).unwrap();
+            assert_eq!(health - hit.health, damage, "{:?}", kind);
+            assert_eq!(hit.max_health, 10.0);
+            for bullet in fired {
+                app.world.despawn(bullet);
+            }
+        }
+    }
+
+    #[test]
+    fn shield_absorbs_a_hit_before_the_hull() {
+        let mut app = collision_app();
+        let target = app
+            .world
+            .spawn((fighter(2), CollisionType::Fighter, Transform::default()))
+            .with_children(|parent| {
+                parent.spawn(Jet);
+            })
+            .id();
+        let shoot = |app: &mut App| {
+            let bullet = app
+                .world
+                .spawn((
+                    Bullet {
+                        remaining_lifetime: 24,
+                        player_id: 1,
+                        weapon: act::Weapon::Gun,
+                        damage: 1.0,
+                    },
+                    CollisionType::Bullet,
+                    Transform::default(),
+                ))
+                .id();
+            app.world.send_event(CollisionEvent::Started(
+                bullet,
+                target,
+                CollisionEventFlags::empty(),
+            ));
+            app.update();
+            let hit = app.world.resource::<Events<event::FighterHit>>();
+            let broken = app.world.resource::<Events<event::ShieldBroken>>();
+            (
+                hit.get_reader().iter(hit).count(),
+                broken.get_reader().iter(broken).count(),
+            )
+        };
+
+        assert_eq!(shoot(&mut app), (0, 1));
+        let shielded = app.world.get::<Fighter>(target).unwrap();
+        assert_eq!(shielded.health, shielded.max_health);
+        assert!(!shielded.shield_active);
+        assert_eq!(shielded.shield_cooldown, shielded.shield_recharge_period);
+        // Events are kept for two frames
+        assert_eq!(shoot(&mut app), (1, 1));
+        assert_eq!(app.world.get::<Fighter>(target).unwrap().health, 2.0);
+    }
+
+    #[test]
+    fn ram_damage_scales_with_impulse() {
+        let settings = Settings::default();
+        let (fighter, asteroid) = (Entity::from_raw(1), Entity::from_raw(2));
+        let mut queue = CollisionQueue::default();
+        let damage = |queue: &CollisionQueue| {
+            ram_damage(queue, &settings, fighter, asteroid)
+        };
+        // Grazing an asteroid deals as much damage as a bullet
+        assert_eq!(damage(&queue), 1.0);
+        let per_hull_point = 1.0 / settings.ram_damage_per_impulse;
+        queue.record_impulse(asteroid, fighter, 0.5 * per_hull_point);
+        assert_eq!(damage(&queue), 1.0);
+        queue.record_impulse(fighter, asteroid, 2.0 * per_hull_point);
+        assert!((damage(&queue) - 2.5).abs() < 1e-3);
+        // Contact forces below the threshold would add less than the
+        // resolution in a step
+        let impulse = settings.ram_force_threshold() * settings.timestep_secs();
+        assert!(
+            (impulse * settings.ram_damage_per_impulse - RAM_DAMAGE_RESOLUTION)
+                .abs()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn opponent_hull_survives_a_bullet() {
+        let mut app = collision_app();
+        app.add_asset::<Mesh>();
+        let mut settings = app.world.resource::<Settings>().clone();
+        settings.opponent_hull = 2.0;
+        app.insert_resource(settings.clone());
+        let mut player = Player::new(None, &Controller::Idle);
+        let mut spawner = SystemState::<(
+            Commands,
+            ResMut<Assets<Mesh>>,
+            ResMut<Assets<ColorMaterial>>,
+        )>::new(&mut app.world);
+        let (mut cmd, mut meshes, mut materials) =
+            spawner.get_mut(&mut app.world);
+        spawn_fighter(
+            &mut player,
+            1,
+            &settings,
+            &mut cmd,
+            &mut meshes,
+            &mut materials,
+            Vec3::ZERO,
+        );
+        spawner.apply(&mut app.world);
+        let target = player.ids[0];
+        app.insert_non_send_resource(Players(vec![player]));
+        let shoot = |app: &mut App| {
+            let bullet = app
+                .world
+                .spawn((
+                    Bullet {
+                        remaining_lifetime: 24,
+                        player_id: 0,
+                        weapon: act::Weapon::Gun,
+                        damage: 1.0,
+                    },
+                    CollisionType::Bullet,
+                    Transform::default(),
+                ))
+                .id();
+            app.world.send_event(CollisionEvent::Started(
+                target,
+                bullet,
+                CollisionEventFlags::empty(),
+            ));
+            app.update();
+            let destroyed =
+                app.world.resource::<Events<event::FighterDestroyed>>();
+            destroyed.get_reader().iter(destroyed).count()
+        };
+
+        // Unlike those of player 0, other fighters have no passive shield
+        assert_eq!(shoot(&mut app), 0);
+        let hit = app.world.get::<Fighter>(target).unwrap();
+        assert_eq!((hit.health, hit.max_health), (1.0, 2.0));
+        assert_eq!(shoot(&mut app), 1);
+        assert!(app.world.get_entity(target).is_none());
+    }
+
+    #[test]
+    fn restore_repairs_hull_and_only_existing_shields() {
+        let mut app = collision_app();
+        // Like fighters of player 0, only the first one has a passive shield
+        let fighters = [0, 1].map(|player_id| {
+            let mut damaged = fighter(player_id);
+            damaged.health = 1.0;
+            damaged.shield_active = false;
+            damaged.shield_cooldown = 100;
+            app.world
+                .spawn((damaged, CollisionType::Fighter, Transform::default()))
+                .with_children(|parent| {
+                    parent.spawn(Jet);
+                    if player_id == 0 {
+                        parent
+                            .spawn((Shield, Visibility { is_visible: false }));
+                    }
+                })
+                .id()
+        });
+        let powerups = fighters.map(|_| {
+            app.world
+                .spawn((
+                    powerup::PowerUp {
+                        kind: powerup::PowerUpKind::Restore,
+                        remaining_lifetime: 900,
+                    },
+                    CollisionType::PowerUp,
+                ))
+                .id()
+        });
+        let flags = CollisionEventFlags::empty();
+        let mut events = app.world.resource_mut::<Events<CollisionEvent>>();
+        events.send(CollisionEvent::Started(fighters[0], powerups[0], flags));
+        events.send(CollisionEvent::Started(powerups[1], fighters[1], flags));
+
+        app.update();
+
+        let restored = app.world.get::<Fighter>(fighters[0]).unwrap();
+        assert_eq!(restored.health, restored.max_health);
+        assert!(restored.shield_active);
+        assert_eq!(restored.shield_cooldown, 0);
+        let shield = app.world.get::<Children>(fighters[0]).unwrap()[1];
+        assert!(app.world.get::<Visibility>(shield).unwrap().is_visible);
+        let opponent = app.world.get::<Fighter>(fighters[1]).unwrap();
+        assert_eq!(opponent.health, opponent.max_health);
+        assert!(!opponent.shield_active);
+        for powerup in powerups {
+            assert!(app.world.get_entity(powerup).is_none());
+        }
+    }
+
+    /// App applying actions to a fighter of player 1 flying up at 500.
+    fn action_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Mesh>()
+            .add_asset::<ColorMaterial>()
+            .insert_resource(Settings::default())
+            .insert_resource(FireRng(SmallRng::seed_from_u64(7)))
+            .init_resource::<RapierContext>()
+            .add_event::<(act::FighterAction, Entity)>()
+            .add_event::<(act::ContinuousAction, Entity)>()
+            .add_event::<ability::EmpBlast>()
+            .add_event::<particles::Effect>()
+            .add_event::<event::BulletFired>()
+            .add_system(fighter_actions);
+        let id = app
+            .world
+            .spawn((
+                fighter(1),
+                Transform::default(),
+                Velocity {
+                    linvel: Vec2::new(0.0, 500.0),
+                    angvel: 0.0,
+                },
+                ExternalImpulse {
+                    impulse: Vec2::ZERO,
+                    torque_impulse: 0.0,
+                },
+                ExternalForce {
+                    force: Vec2::ZERO,
+                    torque: 0.0,
+                },
+                ability::Abilities::new(),
+            ))
+            .with_children(|parent| {
+                parent.spawn((Jet, Visibility::default()));
+            })
+            .id();
+        (app, id)
+    }
+
+    fn continuous(thrust: f32, turn: f32, fire: f32) -> act::ContinuousAction {
+        act::ContinuousAction {
+            thrust,
+            turn,
+            fire,
+            weapon: act::Weapon::Gun,
+            ability: act::Ability::None,
+        }
+    }
+
+    #[test]
+    fn discrete_actions_as_continuous() {
+        let action = act::ContinuousAction::from(act::FighterAction {
+            thrust: act::Thrust::Stop,
+            shoot: act::Shoot::On,
+            turn: act::Turn::QuarterRight,
+            weapon: act::Weapon::Missile,
+            ability: act::Ability::Blink,
+        });
+        assert_eq!(
+            (action.thrust, action.turn, action.fire),
+            (-1.0, -0.25, 1.0)
+        );
+        assert_eq!(action.weapon, act::Weapon::Missile);
+        assert_eq!(action.ability, act::Ability::Blink);
+        let action = act::ContinuousAction::from(act::FighterAction {
+            thrust: act::Thrust::On,
+            shoot: act::Shoot::Off,
+            turn: act::Turn::Left,
+            weapon: act::Weapon::Gun,
+            ability: act::Ability::None,
+        });
+        assert_eq!((action.thrust, action.turn, action.fire), (1.0, 1.0, 0.0));
+        let action = act::ContinuousAction::from(act::FighterAction {
+            thrust: act::Thrust::Off,
+            shoot: act::Shoot::Off,
+            turn: act::Turn::None,
+            weapon: act::Weapon::Gun,
+            ability: act::Ability::None,
+        });
+        assert_eq!((action.thrust, action.turn), (0.0, 0.0));
+    }
+
+    #[test]
+    fn drag_fades_out_with_thrust() {
+        let full_drag = 500.0 * 0.02 * 0.5f32.powf(1.5);
+        for (thrust, drag) in [(0.0, 1.0), (1e-6, 1.0), (0.5, 0.5), (1.0, 0.0)]
+        {
+            let (mut app, id) = action_app();
+            app.world.send_event((continuous(thrust, 0.0, 0.0), id));
+            app.update();
+            let speed = app.world.get::<Velocity>(id).unwrap().linvel.y;
+            assert!(
+                (500.0 - speed - drag * full_drag).abs() < 1e-3,
+                "thrust {} slowed the fighter to {}",
+                thrust,
+                speed
+            );
+        }
+    }
+
+    #[test]
+    fn continuous_actions_are_clamped() {
+        let cases = [
+            // Treated like no thrust or turn at all
+            (f32::NAN, f32::NAN, 0.0, 0.0),
+            (5.0, 3.0, 1.0, 0.5),
+            (-5.0, -3.0, -1.0, -0.5),
+        ];
+        for (thrust, turn, force, angvel) in cases {
+            let (mut app, id) = action_app();
+            app.world.send_event((continuous(thrust, turn, 0.0), id));
+            app.update();
+            // Braking pushes against the velocity with the deceleration
+            let applied = app.world.get::<ExternalForce>(id).unwrap().force;
+            assert!((applied.y - force * 1000000.0).abs() < 1.0);
+            let velocity = app.world.get::<Velocity>(id).unwrap();
+            assert_eq!(velocity.angvel, angvel);
+        }
+    }
+
+    #[test]
+    fn only_uncertain_shots_consume_fire_rng() {
+        let next =
+            |app: &App| app.world.resource::<FireRng>().0.clone().gen::<u64>();
+        for (fire, shots) in
+            [(0.0, 0), (f32::NAN, 0), (-1.0, 0), (1.0, 1), (2.0, 1)]
+        {
+            let (mut app, id) = action_app();
+            let before = next(&app);
+            app.world.send_event((continuous(0.0, 0.0, fire), id));
+            app.update();
+            let fired = app.world.resource::<Events<event::BulletFired>>();
+            assert_eq!(fired.get_reader().iter(fired).count(), shots);
+            assert_eq!(next(&app), before, "fire {} sampled", fire);
+        }
+        let (mut app, id) = action_app();
+        let before = next(&app);
+        app.world.send_event((continuous(0.0, 0.0, 0.5), id));
+        app.update();
+        assert_ne!(next(&app), before);
+    }
+
+    #[test]
+    fn identified_entities_observe_the_id_first() {
+        use entity_gym_rs::agent::Featurizable;
+
+        let asteroid = entity::Identified {
+            id: 7,
+            entity: entity::Asteroid {
+                health: 1.0,
+                radius: 2.0,
+                x: 3.0,
+                y: 4.0,
+                dx: 5.0,
+                dy: 6.0,
+            },
+        };
+        assert_eq!(
+            entity::Identified::<entity::Asteroid>::num_feats(),
+            1 + entity::Asteroid::num_feats()
+        );
+        assert_eq!(
+            entity::Identified::<entity::Asteroid>::feature_names()[0],
+            "id"
+        );
+        assert_eq!(
+            entity::Identified::<entity::Asteroid>::name(),
+            entity::Asteroid::name()
+        );
+        assert_eq!(
+            asteroid.featurize(),
+            vec![7.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        );
+    }
+
+    #[test]
+    fn parses_controllers() {
+        let parse = |s: &str| s.parse::<Controller>();
+        assert_eq!(parse("human"), Ok(Controller::Human(vec![0])));
+        assert_eq!(parse("human:0,1"), Ok(Controller::Human(vec![0, 1])));
+        assert_eq!(parse("human: 2"), Ok(Controller::Human(vec![2])));
+        assert_eq!(
+            parse("policy:150m"),
+            Ok(Controller::Policy("150m".to_string()))
+        );
+        assert_eq!(
+            parse("checkpoint:a:b"),
+            Ok(Controller::Checkpoint("a:b".to_string()))
+        );
+        assert_eq!(parse("random"), Ok(Controller::Random));
+        assert_eq!(parse("scripted"), Ok(Controller::Scripted));
+        assert_eq!(parse("idle"), Ok(Controller::Idle));
+        assert_eq!(parse("remote"), Ok(Controller::Remote));
+        assert_eq!(parse("bot"), Ok(Controller::Bot));
+        assert_eq!(
+            parse("human:0,x"),
+            Err("invalid binding set: x".to_string())
+        );
+        assert!(parse("human:").is_err());
+        assert!(parse("human:-1").is_err());
+        assert!(parse("policy").is_err());
+        assert!(parse("random:1").is_err());
+        assert!(parse("robot").is_err());
+    }
+
+    #[test]
+    fn resolves_legacy_controller_flags() {
+        let controllers = |settings: Settings| settings.player_controllers();
+        // An idle opponent would leave nobody playing, so player 0 is human
+        assert_eq!(
+            controllers(Settings {
+                players: 2,
+                ..default()
+            }),
+            vec![Controller::Human(vec![0]), Controller::Idle]
+        );
+        assert_eq!(
+            controllers(Settings {
+                players: 2,
+                random_ai: true,
+                ..default()
+            }),
+            vec![Controller::Random, Controller::Random]
+        );
+        assert_eq!(
+            controllers(Settings {
+                players: 2,
+                random_ai: true,
+                human_player: true,
+                ..default()
+            }),
+            vec![Controller::Human(vec![0]), Controller::Random]
+        );
+        // Checkpoints take precedence over policies and random actions
+        assert_eq!(
+            controllers(Settings {
+                players: 2,
+                human_player: true,
+                agent_path: Some("agent.ckpt".to_string()),
+                opponent_policy: Some("150m".to_string()),
+                random_ai: true,
+                ..default()
+            }),
+            vec![
+                Controller::Human(vec![0]),
+                Controller::Checkpoint("agent.ckpt".to_string())
+            ]
+        );
+        assert_eq!(
+            controllers(Settings {
+                players: 2,
+                opponent_policy: Some("150m".to_string()),
+                random_ai: true,
+                ..default()
+            }),
+            vec![
+                Controller::Policy("150m".to_string()),
+                Controller::Policy("150m".to_string())
+            ]
+        );
+        // Explicit controllers win, the legacy flags fill in missing players
+        assert_eq!(
+            controllers(Settings {
+                players: 3,
+                controllers: vec![Controller::Scripted, Controller::Bot],
+                random_ai: true,
+                ..default()
+            }),
+            vec![Controller::Scripted, Controller::Bot, Controller::Random]
+        );
+        assert_eq!(
+            controllers(Settings {
+                players: 1,
+                controllers: vec![Controller::Idle, Controller::Remote],
+                ..default()
+            }),
+            vec![Controller::Idle, Controller::Remote]
+        );
+    }
+
+    #[test]
+    fn agents_override_controllers() {
+        let human = Controller::Human(vec![0, 1]);
+        let player = Player::new(None, &human);
+        assert_eq!(player.humans.len(), 2);
+        assert_eq!(player.kind(), "human");
+
+        let player = Player::new(Some(agent::random()), &human);
+        assert!(player.humans.is_empty());
+        assert!(player.agent.is_some());
+        let player = Player::new(Some(agent::random()), &Controller::Bot);
+        assert!(!player.bot);
+        let policy = Controller::Policy("150m".to_string());
+        let player = Player::new(Some(agent::random()), &policy);
+        assert_eq!(player.policy, None);
+    }
+}
@@ -0,0 +1,514 @@
+//! Main menu, pause and game-over screens.
+//!
+//! The game moves through the `GameState`s Menu, Playing, Paused and
+//! GameOver. Outside of Playing, the `SimulationGate` stays closed, which
+//! freezes the main system set and Rapier. The plugin is not added in headless
+//! mode, so training runs never see a menu, and networked games skip the main
+//! menu since all clients have to start with the same settings.
+
+use bevy::prelude::*;
+
+use crate::input::InputConfig;
+use crate::{
+    Controller, GameSummary, Player, PlayerStats, Players, PolicyHandles,
+    Settings, SimulationGate,
+};
+
+const FONT: &str = "fonts/FiraSans-Bold.ttf";
+/// Seconds after which the game-over screen continues on its own.
+const GAME_OVER_SCREEN_SECS: f32 = 10.0;
+/// Seconds during which the game-over screen ignores `CONFIRM_KEYS`, most of
+/// which also fire weapons and are likely still being pressed.
+const GAME_OVER_GRACE_SECS: f32 = 1.5;
+const PAUSE_KEYS: [KeyCode; 2] = [KeyCode::Escape, KeyCode::P];
+const CONFIRM_KEYS: [KeyCode; 3] =
+    [KeyCode::Return, KeyCode::Space, KeyCode::NumpadEnter];
+const SELECTED_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum GameState {
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+pub(crate) struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = app.world.resource::<Settings>();
+        let initial_state = if settings.starts_in_menu() {
+            GameState::Menu
+        } else {
+            GameState::Playing
+        };
+        // Peers can't wait for each other, so networked games neither pause
+        // nor stop at the game-over screen
+        let local = settings.network.is_none();
+        let menu = Menu::new(settings);
+        app.add_state(initial_state)
+            .insert_resource(menu)
+            .add_system(close_gate)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu).with_system(spawn_menu),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(navigate_menu),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Menu)
+                    .with_system(despawn_screen)
+                    .with_system(start_game),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused)
+                    .with_system(spawn_pause_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused).with_system(pause),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused)
+                    .with_system(despawn_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(dismiss_game_over),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver)
+                    .with_system(despawn_screen),
+            );
+        if local {
+            app.add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(pause)
+                    .with_system(show_game_over.after(crate::reset)),
+            );
+        }
+    }
+}
+
+/// Root node of the screen shown in the current state.
+#[derive(Component)]
+struct Screen;
+
+#[derive(Component)]
+struct MenuText;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Keep the controllers set up by the launcher.
+    Configured,
+    /// Player 0 against the selected opponent.
+    VersusAi,
+    /// Two humans sharing the keyboard.
+    Duel,
+    /// Player 0 controlled by the selected opponent as well.
+    Watch,
+}
+
+impl Mode {
+    const ALL: [Mode; 4] =
+        [Mode::Configured, Mode::VersusAi, Mode::Duel, Mode::Watch];
+
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Configured => "As configured",
+            Mode::VersusAi => "Versus AI",
+            Mode::Duel => "Duel",
+            Mode::Watch => "Watch AI",
+        }
+    }
+}
+
+/// Names and multipliers of opponent stats and difficulty ramp relative to
+/// the configured settings.
+const DIFFICULTIES: [(&str, f32, f32); 3] =
+    [("Normal", 1.0, 1.0), ("Easy", 0.7, 2.0), ("Hard", 1.5, 0.5)];
+
+/// Options chosen in the main menu.
+#[derive(Resource)]
+struct Menu {
+    /// Row with the cursor: mode, opponent, difficulty or start.
+    row: usize,
+    mode: usize,
+    /// Policy names, the last option is the scripted AI.
+    opponents: Vec<String>,
+    opponent: usize,
+    difficulty: usize,
+}
+
+const ROWS: usize = 4;
+
+impl Menu {
+    fn new(settings: &Settings) -> Menu {
+        let opponents = available_policies(settings);
+        let opponent = settings
+            .opponent_policy
+            .as_ref()
+            .and_then(|name| opponents.iter().position(|p| p == name))
+            .unwrap_or(0);
+        Menu {
+            row: ROWS - 1,
+            mode: 0,
+            opponents,
+            opponent,
+            difficulty: 0,
+        }
+    }
+
+    fn opponent_controller(&self) -> Controller {
+        match self.opponents.get(self.opponent) {
+            Some(name) => Controller::Policy(name.clone()),
+            None => Controller::Scripted,
+        }
+    }
+
+    fn opponent_name(&self) -> &str {
+        self.opponents
+            .get(self.opponent)
+            .map_or("Scripted", |name| name.as_str())
+    }
+
+    /// Cycles the option in the current row.
+    fn change(&mut self, step: isize) {
+        let (value, len) = match self.row {
+            0 => (&mut self.mode, Mode::ALL.len()),
+            1 => (&mut self.opponent, self.opponents.len() + 1),
+            2 => (&mut self.difficulty, DIFFICULTIES.len()),
+            _ => return,
+        };
+        *value = (*value as isize + step).rem_euclid(len as isize) as usize;
+    }
+
+    fn sections(&self, style: &TextStyle) -> Vec<TextSection> {
+        let rows = [
+            format!("Mode: {}", Mode::ALL[self.mode].name()),
+            format!("Opponent: {}", self.opponent_name()),
+            format!("Difficulty: {}", DIFFICULTIES[self.difficulty].0),
+            "Start".to_string(),
+        ];
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut style = style.clone();
+                if i == self.row {
+                    style.color = SELECTED_COLOR;
+                }
+                TextSection::new(format!("{}\n", row), style)
+            })
+            .collect()
+    }
+}
+
+/// Policy assets to choose opponents from, the web build can only offer the
+/// configured one.
+fn available_policies(settings: &Settings) -> Vec<String> {
+    let mut policies: Vec<String> =
+        settings.opponent_policy.iter().cloned().collect();
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(entries) = std::fs::read_dir("assets/policies") {
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().map_or(false, |ext| ext == "roguenet") {
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    policies.push(name.to_string());
+                }
+            }
+        }
+    }
+    policies.sort();
+    policies.dedup();
+    policies
+}
+
+fn close_gate(state: Res<State<GameState>>, mut gate: ResMut<SimulationGate>) {
+    let paused = *state.current() != GameState::Playing;
+    if gate.paused != paused {
+        gate.paused = paused;
+    }
+}
+
+/// Spawns a full screen overlay with the given text sections.
+fn spawn_screen(cmd: &mut Commands, sections: Vec<TextSection>) -> Entity {
+    let mut text = None;
+    cmd.spawn(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            position_type: PositionType::Absolute,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+        ..default()
+    })
+    .insert(Screen)
+    .with_children(|parent| {
+        text = Some(
+            parent
+                .spawn(
+                    TextBundle::from_sections(sections)
+                        .with_text_alignment(TextAlignment::CENTER),
+                )
+                .id(),
+        );
+    });
+    text.unwrap()
+}
+
+fn title_style(asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font: asset_server.load(FONT),
+        font_size: 60.0,
+        color: Color::WHITE,
+    }
+}
+
+fn text_style(asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font: asset_server.load(FONT),
+        font_size: 32.0,
+        color: Color::WHITE,
+    }
+}
+
+fn spawn_menu(
+    mut cmd: Commands,
+    asset_server: Res<AssetServer>,
+    menu: Res<Menu>,
+) {
+    let text =
+        spawn_screen(&mut cmd, menu.sections(&text_style(&asset_server)));
+    cmd.entity(text).insert(MenuText);
+}
+
+fn navigate_menu(
+    keys: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    mut menu: ResMut<Menu>,
+    mut state: ResMut<State<GameState>>,
+    mut text: Query<&mut Text, With<MenuText>>,
+) {
+    if keys.any_just_pressed([KeyCode::Up, KeyCode::W]) {
+        menu.row = (menu.row + ROWS - 1) % ROWS;
+    }
+    if keys.any_just_pressed([KeyCode::Down, KeyCode::S]) {
+        menu.row = (menu.row + 1) % ROWS;
+    }
+    if keys.any_just_pressed([KeyCode::Left, KeyCode::A]) {
+        menu.change(-1);
+    }
+    if keys.any_just_pressed([KeyCode::Right, KeyCode::D]) {
+        menu.change(1);
+    }
+    if keys.any_just_pressed(CONFIRM_KEYS) {
+        if menu.row == ROWS - 1 {
+            let _ = state.set(GameState::Playing);
+        } else {
+            menu.change(1);
+        }
+    }
+    if menu.is_changed() {
+        if let Ok(mut text) = text.get_single_mut() {
+            text.sections = menu.sections(&text_style(&asset_server));
+        }
+    }
+}
+
+/// Applies the options chosen in the menu and spawns the first fighters.
+#[allow(clippy::too_many_arguments)]
+fn start_game(
+    menu: Res<Menu>,
+    mut settings: ResMut<Settings>,
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut players: NonSendMut<Players>,
+    mut policy_handles: ResMut<PolicyHandles>,
+    server: Res<AssetServer>,
+) {
+    let (_, stats_multiplier, ramp_multiplier) = DIFFICULTIES[menu.difficulty];
+    settings.opponent_stats_multiplier *= stats_multiplier;
+    settings.difficulty_ramp =
+        ((settings.difficulty_ramp as f32 * ramp_multiplier) as u32).max(1);
+    let mode = Mode::ALL[menu.mode];
+    if mode == Mode::Duel && settings.input.players.len() < 2 {
+        settings.input = InputConfig::split_keyboard();
+    }
+    if mode != Mode::Configured {
+        let opponent = menu.opponent_controller();
+        for (i, player) in players.0.iter_mut().enumerate() {
+            let controller = match (mode, i) {
+                (Mode::VersusAi, 0) => Controller::Human(vec![0]),
+                (Mode::Duel, 0) => Controller::Human(vec![0]),
+                (Mode::Duel, 1) => Controller::Human(vec![1]),
+                _ => opponent.clone(),
+            };
+            *player = Player::new(None, &controller);
+        }
+        policy_handles.0 = crate::load_policies(&players, &server);
+    }
+    crate::spawn_players(
+        &settings,
+        &mut cmd,
+        &mut meshes,
+        &mut materials,
+        &mut players,
+    );
+}
+
+fn despawn_screen(mut cmd: Commands, screens: Query<Entity, With<Screen>>) {
+    for screen in screens.iter() {
+        cmd.entity(screen).despawn_recursive();
+    }
+}
+
+fn pause(
+    keys: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let pressed = keys.any_just_pressed(PAUSE_KEYS)
+        || buttons
+            .get_just_pressed()
+            .any(|button| button.button_type == GamepadButtonType::Start);
+    if !pressed {
+        return;
+    }
+    let next = match state.current() {
+        GameState::Playing => GameState::Paused,
+        _ => GameState::Playing,
+    };
+    // Fails if the game ended this frame, which takes precedence
+    let _ = state.set(next);
+}
+
+fn spawn_pause_screen(mut cmd: Commands, asset_server: Res<AssetServer>) {
+    spawn_screen(
+        &mut cmd,
+        vec![
+            TextSection::new("Paused\n", title_style(&asset_server)),
+            TextSection::new(
+                "Press Escape or P to continue",
+                text_style(&asset_server),
+            ),
+        ],
+    );
+}
+
+/// Shows the stats of a finished game, the next one has already been set up
+/// by `reset` and starts once the screen is dismissed.
+fn show_game_over(
+    mut cmd: Commands,
+    mut summaries: EventReader<GameSummary>,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let stats = match summaries.iter().last() {
+        Some(GameSummary(stats)) => stats,
+        None => return,
+    };
+    let mut summary = format!(
+        "Score: {}\nTime: {:.0}s\nBullets fired: {}\nHits: {}\n\
+         Asteroids destroyed: {}\nOpponents destroyed: {}\n\
+         Allies destroyed: {}\n",
+        stats.player0_score(),
+        stats.timesteps as f32 / settings.frame_rate,
+        stats.bullets_fired,
+        stats.bullet_hits,
+        stats.destroyed_asteroids,
+        stats.destroyed_opponents,
+        stats.destroyed_allies,
+    );
+    for (i, player) in stats.players.iter().enumerate() {
+        let PlayerStats {
+            kills,
+            deaths,
+            assists,
+            ..
+        } = player;
+        summary.push_str(&format!(
+            "Player {} K/D/A: {}/{}/{}\n",
+            i, kills, deaths, assists
+        ));
+    }
+    spawn_screen(
+        &mut cmd,
+        vec![
+            TextSection::new("Game Over\n", title_style(&asset_server)),
+            TextSection::new(summary, text_style(&asset_server)),
+            TextSection::new(
+                "\nPress Enter to play again",
+                text_style(&asset_server),
+            ),
+        ],
+    );
+    cmd.insert_resource(GameOverTimer(Timer::from_seconds(
+        GAME_OVER_SCREEN_SECS,
+        TimerMode::Once,
+    )));
+    let _ = state.overwrite_set(GameState::GameOver);
+}
+
+#[derive(Resource)]
+struct GameOverTimer(Timer);
+
+fn dismiss_game_over(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mut timer: ResMut<GameOverTimer>,
+    mut state: ResMut<State<GameState>>,
+) {
+    timer.0.tick(time.delta());
+    let confirmed = timer.0.elapsed_secs() >= GAME_OVER_GRACE_SECS
+        && keys.any_just_pressed(CONFIRM_KEYS);
+    if timer.0.finished() || confirmed {
+        let _ = state.set(GameState::Playing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn game_over_screen_ignores_fire_buttons_at_first() {
+        let start = Instant::now();
+        let mut time = Time::default();
+        time.update_with_instant(start);
+        // Return also fires for the second player on a split keyboard
+        let mut keys = Input::<KeyCode>::default();
+        keys.press(KeyCode::Return);
+        let mut app = App::new();
+        app.insert_resource(time)
+            .insert_resource(keys)
+            .insert_resource(GameOverTimer(Timer::from_seconds(
+                GAME_OVER_SCREEN_SECS,
+                TimerMode::Once,
+            )))
+            .add_state(GameState::GameOver)
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(dismiss_game_over),
+            );
+        let run_until = |app: &mut App, secs: f32| {
+            app.world
+                .resource_mut::<Time>()
+                .update_with_instant(start + Duration::from_secs_f32(secs));
+            app.update();
+            *app.world.resource::<State<GameState>>().current()
+        };
+
+        assert_eq!(run_until(&mut app, 0.5), GameState::GameOver);
+        assert_eq!(
+            run_until(&mut app, GAME_OVER_GRACE_SECS + 0.5),
+            GameState::Playing
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Machine-readable metrics of every finished game.
+//!
+//! With `Settings.metrics_log`, one line per game is appended to the given
+//! file: a JSON object, or a CSV row if the path ends in `.csv`. Every line
+//! holds the seed, a hash of the settings that determine the simulation, the
+//! controller or policy of each player, the duration, the score of each
+//! player and all `Stats`, so that e.g. win rates of humans against policies
+//! can be computed across sessions and headless runs. Games are not appended
+//! to a CSV file whose columns differ, e.g. because it was written with a
+//! different number of players.
+
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, Write};
+
+use bevy::log;
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::replay::ReplaySettings;
+use crate::{GameSummary, Players, Settings, Stats};
+
+/// Column names of the stats that aren't specific to a player.
+const STATS_COLUMNS: [&str; 6] = [
+    "bullets_fired",
+    "timesteps",
+    "bullet_hits",
+    "destroyed_asteroids",
+    "destroyed_opponents",
+    "destroyed_allies",
+];
+/// Column names of the stats of each player, prefixed with `p<i>_`.
+const PLAYER_COLUMNS: [&str; 6] = [
+    "score",
+    "kills",
+    "deaths",
+    "assists",
+    "destroyed_asteroids",
+    "rammed_asteroids",
+];
+
+#[derive(Serialize)]
+struct EpisodeMetrics {
+    /// Games finished before this one since the app was started.
+    episode: usize,
+    seed: u64,
+    /// FNV-1a hash of the `ReplaySettings` as JSON, in hexadecimal.
+    settings_hash: String,
+    /// Policy name of each player, or the kind of its controller.
+    policies: Vec<String>,
+    duration_secs: f32,
+    scores: Vec<f32>,
+    stats: Stats,
+}
+
+impl EpisodeMetrics {
+    fn csv_header(&self) -> String {
+        let mut columns = [
+            "episode",
+            "seed",
+            "settings_hash",
+            "policies",
+            "duration_secs",
+        ]
+        .iter()
+        .chain(&STATS_COLUMNS)
+        .map(|column| column.to_string())
+        .collect::<Vec<_>>();
+        for i in 0..self.scores.len() {
+            columns.extend(
+                PLAYER_COLUMNS
+                    .iter()
+                    .map(|column| format!("p{}_{}", i, column)),
+            );
+        }
+        columns.join(",")
+    }
+
+    fn csv_row(&self) -> String {
+        let stats = &self.stats;
+        let mut values = vec![
+            self.episode.to_string(),
+            self.seed.to_string(),
+            self.settings_hash.clone(),
+            self.policies.join(" vs "),
+            self.duration_secs.to_string(),
+            stats.bullets_fired.to_string(),
+            stats.timesteps.to_string(),
+            stats.bullet_hits.to_string(),
+            stats.destroyed_asteroids.to_string(),
+            stats.destroyed_opponents.to_string(),
+            stats.destroyed_allies.to_string(),
+        ];
+        for (i, score) in self.scores.iter().enumerate() {
+            let player = stats.player(i);
+            values.extend([
+                score.to_string(),
+                player.kills.to_string(),
+                player.deaths.to_string(),
+                player.assists.to_string(),
+                player.destroyed_asteroids.to_string(),
+                player.rammed_asteroids.to_string(),
+            ]);
+        }
+        values
+            .iter()
+            .map(|value| csv_field(value))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Quotes `value` if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn settings_hash(settings: &Settings) -> String {
+    let json = serde_json::to_string(&ReplaySettings::new(settings))
+        .unwrap_or_default();
+    let hash = json.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn append(path: &str, metrics: &EpisodeMetrics) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)?;
+    if path.ends_with(".csv") {
+        let header = metrics.csv_header();
+        let mut existing = String::new();
+        BufReader::new(&file).read_line(&mut existing)?;
+        if existing.is_empty() {
+            writeln!(file, "{}", header)?;
+        } else if existing.trim_end() != header {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "existing columns differ from those of this game",
+            ));
+        }
+        writeln!(file, "{}", metrics.csv_row())
+    } else {
+        writeln!(file, "{}", serde_json::to_string(metrics)?)
+    }
+}
+
+/// Appends the metrics of every game to `Settings.metrics_log`.
+pub(crate) struct MetricsPlugin;
+
+impl Plugin for MetricsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(log_metrics.after(crate::reset));
+    }
+}
+
+fn log_metrics(
+    mut summaries: EventReader<GameSummary>,
+    players: NonSend<Players>,
+    settings: Res<Settings>,
+    mut episode: Local<usize>,
+) {
+    let path = match &settings.metrics_log {
+        Some(path) => path,
+        None => return,
+    };
+    for GameSummary(stats) in summaries.iter() {
+        let metrics = EpisodeMetrics {
+            episode: *episode,
+            seed: settings.seed,
+            settings_hash: settings_hash(&settings),
+            policies: players
+                .0
+                .iter()
+                .map(|player| {
+                    player
+                        .policy
+                        .clone()
+                        .unwrap_or_else(|| player.kind().to_string())
+                })
+                .collect(),
+            duration_secs: stats.timesteps as f32 / settings.frame_rate,
+            scores: (0..players.0.len()).map(|i| stats.score(i)).collect(),
+            stats: stats.clone(),
+        };
+        if let Err(e) = append(path, &metrics) {
+            log::warn!("Failed to write metrics to {}: {}", path, e);
+        }
+        *episode += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PlayerStats;
+
+    fn metrics(players: usize) -> EpisodeMetrics {
+        EpisodeMetrics {
+            episode: 0,
+            seed: 1,
+            settings_hash: "0123456789abcdef".to_string(),
+            policies: vec!["human".to_string(); players],
+            duration_secs: 60.0,
+            scores: vec![0.0; players],
+            stats: Stats::default(),
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("human"), "human");
+        assert_eq!(csv_field("a,b vs c"), "\"a,b vs c\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        let mut metrics = metrics(2);
+        metrics.policies = vec!["out/a,b".to_string(), "human".to_string()];
+        let row = metrics.csv_row();
+        assert!(row.starts_with("0,1,0123456789abcdef,\"out/a,b vs human\","));
+    }
+
+    #[test]
+    fn csv_has_a_column_for_every_stat() {
+        let json = serde_json::to_value(metrics(1).stats).unwrap();
+        let mut stats = json.as_object().unwrap().keys().collect::<Vec<_>>();
+        stats.retain(|key| *key != "players");
+        let mut columns = STATS_COLUMNS.to_vec();
+        stats.sort();
+        columns.sort();
+        assert_eq!(stats, columns);
+        let json = serde_json::to_value(PlayerStats::default()).unwrap();
+        for key in json.as_object().unwrap().keys() {
+            assert!(PLAYER_COLUMNS.contains(&key.as_str()), "{}", key);
+        }
+        let metrics = metrics(2);
+        let header = metrics.csv_header();
+        assert_eq!(
+            header.split(',').count(),
+            metrics.csv_row().split(',').count()
+        );
+        assert!(header.ends_with(",p1_rammed_asteroids"));
+    }
+
+    #[test]
+    fn csv_rows_with_other_columns_are_not_appended() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy-starfighter-metrics-{}.csv",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        append(path, &metrics(2)).unwrap();
+        append(path, &metrics(2)).unwrap();
+        assert!(append(path, &metrics(3)).is_err());
+        let contents = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], metrics(2).csv_header());
+    }
+}
@@ -0,0 +1,232 @@
+//! Minimap in the bottom left corner of the screen.
+//!
+//! Fighters show up as blips in their player's color, along with asteroids,
+//! projectiles and power-ups, all read through the same `ObservationQueries`
+//! that observations of agents are built from. With
+//! `Settings.minimap_sensor_range`, only entities sensed by player 0's
+//! fighters are shown, which is exactly what an agent in control of player 0
+//! would observe. A translucent rectangle marks the part of the arena the
+//! camera shows. M toggles the minimap.
+
+use bevy::prelude::*;
+
+use crate::{
+    ObservationQueries, Players, Settings, BULLET_COLORS, FIGHTER_COLORS,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::M;
+const WIDTH: f32 = 240.0;
+const MARGIN: f32 = 10.0;
+const FIGHTER_BLIP: f32 = 7.0;
+const PROJECTILE_BLIP: f32 = 2.0;
+const POWERUP_BLIP: f32 = 5.0;
+const MIN_ASTEROID_BLIP: f32 = 3.0;
+const BACKGROUND_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+const VIEW_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.08);
+const ASTEROID_COLOR: Color = Color::rgb(0.6, 0.3, 0.2);
+const POWERUP_COLOR: Color = Color::rgb(1.0, 0.9, 0.3);
+
+pub(crate) struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_minimap)
+            .add_system(toggle_minimap)
+            .add_system(update_blips)
+            .add_system(update_view_rect);
+    }
+}
+
+#[derive(Component)]
+struct Minimap;
+
+#[derive(Component)]
+struct Blip;
+
+/// Part of the arena shown by the camera.
+#[derive(Component)]
+struct ViewRect;
+
+/// Size of the minimap in pixels, which has the aspect ratio of the arena.
+fn map_size(settings: &Settings) -> Vec2 {
+    let arena = settings.arena_size;
+    Vec2::new(WIDTH, WIDTH * arena.y / arena.x)
+}
+
+/// Converts a position in the arena to minimap pixels from the top left.
+fn to_map(settings: &Settings, position: Vec2) -> Vec2 {
+    let half = settings.arena_half_size();
+    let scale = map_size(settings).x / settings.arena_size.x;
+    Vec2::new(position.x + half.x, half.y - position.y) * scale
+}
+
+fn spawn_minimap(mut cmd: Commands, settings: Res<Settings>) {
+    let size = map_size(&settings);
+    cmd.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(MARGIN),
+                left: Val::Px(MARGIN),
+                ..default()
+            },
+            size: Size::new(Val::Px(size.x), Val::Px(size.y)),
+            ..default()
+        },
+        background_color: BACKGROUND_COLOR.into(),
+        ..default()
+    })
+    .insert(Minimap)
+    .with_children(|parent| {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                background_color: VIEW_COLOR.into(),
+                ..default()
+            })
+            .insert(ViewRect);
+    });
+}
+
+fn toggle_minimap(
+    keys: Res<Input<KeyCode>>,
+    mut minimap: Query<&mut Visibility, With<Minimap>>,
+) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        for mut visibility in minimap.iter_mut() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+/// Positions a blip for every entity shown on the minimap, reusing the blips
+/// of previous frames and hiding those left over.
+fn update_blips(
+    mut cmd: Commands,
+    players: NonSend<Players>,
+    observations: ObservationQueries,
+    minimap: Query<Entity, With<Minimap>>,
+    mut blips: Query<
+        (&mut Style, &mut BackgroundColor, &mut Visibility),
+        With<Blip>,
+    >,
+    mut pool: Local<Vec<Entity>>,
+) {
+    let minimap = match minimap.get_single() {
+        Ok(minimap) => minimap,
+        Err(_) => return,
+    };
+    let settings = &*observations.settings;
+    let ids = &players.0[0].ids;
+    let visible = |position: Vec3| {
+        position
+            .truncate()
+            .abs()
+            .cmple(settings.arena_half_size())
+            .all()
+            && (!settings.minimap_sensor_range
+                || observations.senses(ids, position))
+    };
+    let scale = map_size(settings).x / settings.arena_size.x;
+    let mut shown = vec![];
+    for (fighter, transform, _, _) in observations.fighters.iter() {
+        if fighter.player_id == 0 || visible(transform.translation) {
+            let color =
+                FIGHTER_COLORS[fighter.player_id % FIGHTER_COLORS.len()];
+            shown.push((transform.translation, FIGHTER_BLIP, color));
+        }
+    }
+    for (asteroid, transform, _, _) in observations.asteroids.iter() {
+        if visible(transform.translation) {
+            let size = (2.0 * asteroid.radius * scale).max(MIN_ASTEROID_BLIP);
+            shown.push((transform.translation, size, ASTEROID_COLOR));
+        }
+    }
+    for (bullet, transform, _, _) in observations.bullets.iter() {
+        if visible(transform.translation) {
+            let color = BULLET_COLORS[bullet.player_id % BULLET_COLORS.len()];
+            shown.push((transform.translation, PROJECTILE_BLIP, color));
+        }
+    }
+    for (_, transform, _) in observations.powerups.iter() {
+        if visible(transform.translation) {
+            shown.push((transform.translation, POWERUP_BLIP, POWERUP_COLOR));
+        }
+    }
+
+    let count = shown.len();
+    for (k, (position, size, color)) in shown.into_iter().enumerate() {
+        let corner = to_map(settings, position.truncate()) - size / 2.0;
+        let style = Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(corner.x),
+                top: Val::Px(corner.y),
+                ..default()
+            },
+            size: Size::new(Val::Px(size), Val::Px(size)),
+            ..default()
+        };
+        if let Some((mut blip_style, mut blip_color, mut visibility)) =
+            pool.get(k).and_then(|blip| blips.get_mut(*blip).ok())
+        {
+            *blip_style = style;
+            *blip_color = color.into();
+            visibility.is_visible = true;
+            continue;
+        }
+        let blip = cmd
+            .spawn(NodeBundle {
+                style,
+                background_color: color.into(),
+                ..default()
+            })
+            .insert(Blip)
+            .id();
+        cmd.entity(minimap).add_child(blip);
+        pool.push(blip);
+    }
+    for blip in pool.iter().skip(count) {
+        if let Ok((_, _, mut visibility)) = blips.get_mut(*blip) {
+            visibility.is_visible = false;
+        }
+    }
+}
+
+fn update_view_rect(
+    settings: Res<Settings>,
+    cameras: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    mut view_rect: Query<&mut Style, With<ViewRect>>,
+) {
+    let (camera, transform, projection) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let (viewport, mut style) =
+        match (camera.logical_viewport_size(), view_rect.get_single_mut()) {
+            (Some(viewport), Ok(style)) => (viewport, style),
+            _ => return,
+        };
+    let half = settings.arena_half_size();
+    let center = transform.translation().truncate();
+    let extent = viewport * projection.scale / 2.0;
+    // Corners of the visible part of the arena, top left and bottom right
+    let top_left = to_map(
+        &settings,
+        Vec2::new(center.x - extent.x, center.y + extent.y).clamp(-half, half),
+    );
+    let bottom_right = to_map(
+        &settings,
+        Vec2::new(center.x + extent.x, center.y - extent.y).clamp(-half, half),
+    );
+    let size = bottom_right - top_left;
+    style.position = UiRect {
+        left: Val::Px(top_left.x),
+        top: Val::Px(top_left.y),
+        ..default()
+    };
+    style.size = Size::new(Val::Px(size.x), Val::Px(size.y));
+}
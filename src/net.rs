@@ -0,0 +1,600 @@
+//! Networked multiplayer with deterministic lockstep.
+//!
+//! All peers run the same simulation from the same seed and only exchange the
+//! actions of human players, along with presses of their `target` binding. Actions are sampled `input_delay` action
+//! intervals before they are applied, and the simulation stalls whenever the
+//! actions of some player are still missing for the next action interval.
+//! Packets are exchanged through a relay server (`relay` binary), over UDP on
+//! native builds and WebSockets on the web build. Rapier is built with
+//! `enhanced-determinism`, so native and web peers step the physics
+//! identically.
+//!
+//! Only the inputs of `Controller::Human` and `Controller::Remote` players are
+//! synchronized, so every other player should be controlled by something
+//! deterministic such as `Controller::Scripted`.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use bevy::log;
+use bevy::prelude::*;
+
+use crate::ids::EntityIds;
+use crate::{
+    act, targeting, Fighter, Players, RemainingTime, Settings, SimulationGate,
+};
+
+const MSG_INPUT: u8 = 1;
+/// Size of an encoded `act::FighterAction`.
+const ACTION_BYTES: usize = 5;
+/// Size of an encoded `PlayerInput`.
+const INPUT_BYTES: usize = ACTION_BYTES + 1;
+/// Number of past actions repeated in every packet to cover for packet loss.
+const REDUNDANCY: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct NetworkSettings {
+    /// Address of the relay, `host:port` for UDP or a `ws://` URL on the web.
+    pub relay: String,
+    /// Games sharing a relay are separated by room number.
+    pub room: u32,
+    /// Number of action intervals between sampling and applying an input.
+    pub input_delay: u32,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            relay: "127.0.0.1:4000".to_string(),
+            room: 0,
+            input_delay: 3,
+        }
+    }
+}
+
+/// Requires the `Transport` to the relay, which `try_app` connects before
+/// adding the plugin so that launchers can report unreachable relays.
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        let input_delay = app
+            .world
+            .resource::<Settings>()
+            .network
+            .as_ref()
+            .map_or(0, |network| network.input_delay);
+        app.insert_resource(Lockstep::new(input_delay))
+            .add_startup_system(init_lockstep)
+            .add_system_to_stage(CoreStage::PreUpdate, sync_inputs);
+    }
+}
+
+/// Input of a networked player for one action interval.
+#[derive(Clone, Copy)]
+struct PlayerInput {
+    action: act::FighterAction,
+    /// Whether the `target` binding was pressed, see
+    /// `targeting::toggled_target`.
+    toggle_target: bool,
+}
+
+/// Inputs of all networked players, indexed by action interval.
+#[derive(Resource)]
+struct Lockstep {
+    /// Index of the next action interval to be simulated.
+    tick: u32,
+    input_delay: u32,
+    /// Index of the last tick for which local inputs were sampled.
+    sampled: Option<u32>,
+    /// Players controlled by local humans, with their recent inputs.
+    local: Vec<(usize, VecDeque<PlayerInput>)>,
+    /// Local players that pressed their `target` binding since their input
+    /// was last sampled.
+    target_pressed: HashSet<usize>,
+    /// All players whose actions are synchronized.
+    players: Vec<usize>,
+    // Ordered by player so that all peers apply inputs in the same order
+    inputs: HashMap<u32, BTreeMap<usize, PlayerInput>>,
+}
+
+impl Lockstep {
+    fn new(input_delay: u32) -> Lockstep {
+        Lockstep {
+            tick: 0,
+            input_delay,
+            sampled: None,
+            local: vec![],
+            target_pressed: HashSet::new(),
+            players: vec![],
+            inputs: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, tick: u32, player: usize, input: PlayerInput) {
+        if tick >= self.tick {
+            self.inputs.entry(tick).or_default().insert(player, input);
+        }
+    }
+
+    /// Removes and returns the inputs for the current tick once all players
+    /// have sent theirs.
+    fn take_ready(&mut self) -> Option<BTreeMap<usize, PlayerInput>> {
+        let ready = self.inputs.get(&self.tick).map_or(false, |inputs| {
+            self.players.iter().all(|p| inputs.contains_key(p))
+        });
+        if !ready {
+            return None;
+        }
+        let inputs = self.inputs.remove(&self.tick);
+        self.tick += 1;
+        inputs
+    }
+
+    /// Encodes the most recent inputs of a local player.
+    fn packet(&self, room: u32, player: usize) -> Option<Vec<u8>> {
+        let (_, history) = self.local.iter().find(|(p, _)| *p == player)?;
+        let last = self.sampled? + self.input_delay;
+        let first = last + 1 - history.len() as u32;
+        let mut packet = vec![MSG_INPUT];
+        packet.extend_from_slice(&room.to_le_bytes());
+        packet.push(player as u8);
+        packet.extend_from_slice(&first.to_le_bytes());
+        packet.push(history.len() as u8);
+        for input in history {
+            packet.extend_from_slice(&encode_input(input));
+        }
+        Some(packet)
+    }
+}
+
+fn init_lockstep(mut lockstep: ResMut<Lockstep>, players: NonSend<Players>) {
+    for (i, player) in players.0.iter().enumerate() {
+        if player.remote {
+            lockstep.players.push(i);
+        } else if !player.humans.is_empty() {
+            if player.humans.len() > 1 {
+                log::warn!(
+                    "Only the first human of player {} plays over the network",
+                    i
+                );
+            }
+            lockstep.players.push(i);
+            lockstep.local.push((i, VecDeque::new()));
+        }
+    }
+    // The first actions are sampled `input_delay` ticks in advance, so all
+    // players start out idle
+    for tick in 0..lockstep.input_delay {
+        for player in lockstep.players.clone() {
+            lockstep.insert(tick, player, idle_input());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sync_inputs(
+    mut lockstep: ResMut<Lockstep>,
+    mut transport: NonSendMut<Transport>,
+    mut gate: ResMut<SimulationGate>,
+    mut action_events: EventWriter<(act::FighterAction, Entity)>,
+    mut target_events: EventWriter<(act::Target, Entity)>,
+    players: NonSend<Players>,
+    settings: Res<Settings>,
+    remaining_time: Res<RemainingTime>,
+    keys: Option<Res<Input<KeyCode>>>,
+    buttons: Option<Res<Input<GamepadButton>>>,
+    axes: Option<Res<Axis<GamepadAxis>>>,
+    ids: Res<EntityIds>,
+    fighters: Query<(Entity, &Fighter, &Transform)>,
+) {
+    // The simulation doesn't step, so sampling inputs would desync the peers
+    if gate.paused {
+        return;
+    }
+    let room = settings.network.as_ref().map_or(0, |n| n.room);
+    while let Some(packet) = transport.recv() {
+        match decode_packet(&packet) {
+            Some((packet_room, player, first, inputs))
+                if packet_room == room
+                    && !lockstep.local.iter().any(|(p, _)| *p == player) =>
+            {
+                for (i, input) in inputs.into_iter().enumerate() {
+                    lockstep.insert(first + i as u32, player, input);
+                }
+            }
+            Some(_) => {}
+            None => log::warn!("Dropping malformed packet"),
+        }
+    }
+    // Presses only count as just pressed for a single frame, so they are
+    // remembered until the next input is sampled
+    if let Some(keys) = &keys {
+        let pressed = lockstep
+            .local
+            .iter()
+            .map(|(player, _)| *player)
+            .filter(|player| {
+                players.0[*player]
+                    .humans
+                    .first()
+                    .and_then(|pilot| {
+                        settings.input.players.get(pilot.bindings)
+                    })
+                    .map_or(false, |bindings| {
+                        keys.any_just_pressed(bindings.target.iter().copied())
+                    })
+            })
+            .collect::<Vec<_>>();
+        lockstep.target_pressed.extend(pressed);
+    }
+    if remaining_time.0 as u32 % settings.action_interval != 0 {
+        gate.network_stall = false;
+        return;
+    }
+
+    let tick = lockstep.tick;
+    if lockstep.sampled != Some(tick) {
+        lockstep.sampled = Some(tick);
+        let apply_at = tick + lockstep.input_delay;
+        for k in 0..lockstep.local.len() {
+            let player = lockstep.local[k].0;
+            let action = match (&keys, &buttons, &axes) {
+                (Some(keys), Some(buttons), Some(axes)) => players.0[player]
+                    .humans
+                    .first()
+                    .and_then(|pilot| {
+                        let bindings =
+                            settings.input.players.get(pilot.bindings)?;
+                        Some(bindings.action(keys, buttons, axes, pilot.weapon))
+                    })
+                    .unwrap_or_else(idle_action),
+                _ => idle_action(),
+            };
+            let input = PlayerInput {
+                action,
+                toggle_target: lockstep.target_pressed.remove(&player),
+            };
+            lockstep.insert(apply_at, player, input);
+            let history = &mut lockstep.local[k].1;
+            history.push_back(input);
+            if history.len() > REDUNDANCY {
+                history.pop_front();
+            }
+        }
+    }
+
+    match lockstep.take_ready() {
+        Some(inputs) => {
+            for (player, input) in inputs {
+                let fighter = match players.0[player].ids.first() {
+                    Some(fighter) => *fighter,
+                    None => continue,
+                };
+                action_events.send((input.action, fighter));
+                // All peers toggle the target in the same simulation state
+                let target = input.toggle_target.then(|| {
+                    targeting::toggled_target(fighter, &ids, &fighters)
+                });
+                if let Some(target) = target.flatten() {
+                    target_events.send((target, fighter));
+                }
+            }
+            gate.network_stall = false;
+        }
+        None => gate.network_stall = true,
+    }
+    // Packets are resent every frame until all peers have caught up
+    for (player, _) in &lockstep.local {
+        if let Some(packet) = lockstep.packet(room, *player) {
+            transport.send(&packet);
+        }
+    }
+}
+
+fn idle_action() -> act::FighterAction {
+    act::FighterAction {
+        thrust: act::Thrust::Off,
+        shoot: act::Shoot::Off,
+        turn: act::Turn::None,
+        weapon: act::Weapon::Gun,
+        ability: act::Ability::None,
+    }
+}
+
+fn idle_input() -> PlayerInput {
+    PlayerInput {
+        action: idle_action(),
+        toggle_target: false,
+    }
+}
+
+// Actions are encoded as the index of each variant in declaration order
+const THRUST: [act::Thrust; 3] =
+    [act::Thrust::On, act::Thrust::Off, act::Thrust::Stop];
+const SHOOT: [act::Shoot; 2] = [act::Shoot::On, act::Shoot::Off];
+const TURN: [act::Turn; 5] = [
+    act::Turn::Left,
+    act::Turn::QuarterLeft,
+    act::Turn::Right,
+    act::Turn::QuarterRight,
+    act::Turn::None,
+];
+
+fn encode_action(action: &act::FighterAction) -> [u8; ACTION_BYTES] {
+    [
+        action.thrust as u8,
+        action.shoot as u8,
+        action.turn as u8,
+        action.weapon as u8,
+        action.ability as u8,
+    ]
+}
+
+fn decode_action(bytes: &[u8]) -> Option<act::FighterAction> {
+    Some(act::FighterAction {
+        thrust: *THRUST.get(bytes[0] as usize)?,
+        shoot: *SHOOT.get(bytes[1] as usize)?,
+        turn: *TURN.get(bytes[2] as usize)?,
+        weapon: *act::Weapon::ALL.get(bytes[3] as usize)?,
+        ability: *act::Ability::ALL.get(bytes[4] as usize)?,
+    })
+}
+
+fn encode_input(input: &PlayerInput) -> [u8; INPUT_BYTES] {
+    let mut bytes = [0; INPUT_BYTES];
+    bytes[..ACTION_BYTES].copy_from_slice(&encode_action(&input.action));
+    bytes[ACTION_BYTES] = input.toggle_target as u8;
+    bytes
+}
+
+fn decode_input(bytes: &[u8]) -> Option<PlayerInput> {
+    Some(PlayerInput {
+        action: decode_action(bytes)?,
+        toggle_target: match bytes[ACTION_BYTES] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        },
+    })
+}
+
+/// Returns the room a packet is addressed to.
+pub fn packet_room(packet: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(packet.get(1..5)?.try_into().ok()?))
+}
+
+/// Decodes a packet into its room, player, first tick and inputs.
+fn decode_packet(packet: &[u8]) -> Option<(u32, usize, u32, Vec<PlayerInput>)> {
+    if *packet.first()? != MSG_INPUT {
+        return None;
+    }
+    let room = packet_room(packet)?;
+    let player = *packet.get(5)? as usize;
+    let first = u32::from_le_bytes(packet.get(6..10)?.try_into().ok()?);
+    let count = *packet.get(10)? as usize;
+    let inputs = packet.get(11..11 + INPUT_BYTES * count)?;
+    let inputs = inputs
+        .chunks(INPUT_BYTES)
+        .map(decode_input)
+        .collect::<Option<Vec<_>>>()?;
+    Some((room, player, first, inputs))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Transport {
+    socket: std::net::UdpSocket,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport {
+    pub(crate) fn connect(relay: &str) -> std::io::Result<Transport> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(relay)?;
+        socket.set_nonblocking(true)?;
+        Ok(Transport { socket })
+    }
+
+    fn send(&mut self, packet: &[u8]) {
+        if let Err(e) = self.socket.send(packet) {
+            log::warn!("Failed to send packet: {}", e);
+        }
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        let mut buf = [0; 1024];
+        match self.socket.recv(&mut buf) {
+            Ok(len) => Some(buf[..len].to_vec()),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => None,
+            Err(e) => {
+                log::warn!("Failed to receive packet: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct Transport {
+    socket: web_sys::WebSocket,
+    inbox: std::rc::Rc<std::cell::RefCell<VecDeque<Vec<u8>>>>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Transport {
+    pub(crate) fn connect(relay: &str) -> Result<Transport, String> {
+        use wasm_bindgen::{closure::Closure, JsCast};
+
+        let socket =
+            web_sys::WebSocket::new(relay).map_err(|e| format!("{:?}", e))?;
+        socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+        let inbox = std::rc::Rc::new(std::cell::RefCell::new(VecDeque::new()));
+        let receiver = inbox.clone();
+        let onmessage =
+            Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
+                if let Ok(buf) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
+                    receiver
+                        .borrow_mut()
+                        .push_back(js_sys::Uint8Array::new(&buf).to_vec());
+                }
+            })
+                as Box<dyn FnMut(web_sys::MessageEvent)>);
+        socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+        Ok(Transport { socket, inbox })
+    }
+
+    fn send(&mut self, packet: &[u8]) {
+        // Packets are resent every frame, so anything sent before the
+        // connection is open can be dropped
+        if self.socket.ready_state() == web_sys::WebSocket::OPEN {
+            if let Err(e) = self.socket.send_with_u8_array(packet) {
+                log::warn!("Failed to send packet: {:?}", e);
+            }
+        }
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        self.inbox.borrow_mut().pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(i: usize) -> PlayerInput {
+        PlayerInput {
+            action: act::FighterAction {
+                thrust: THRUST[i % THRUST.len()],
+                shoot: SHOOT[i % SHOOT.len()],
+                turn: TURN[i % TURN.len()],
+                weapon: act::Weapon::ALL[i % act::Weapon::ALL.len()],
+                ability: act::Ability::ALL[i % act::Ability::ALL.len()],
+            },
+            toggle_target: i % 4 == 0,
+        }
+    }
+
+    /// Peer of a two player game in which it controls `local`.
+    fn peer(local: usize, input_delay: u32) -> Lockstep {
+        let mut lockstep = Lockstep::new(input_delay);
+        lockstep.players = vec![0, 1];
+        lockstep.local.push((local, VecDeque::new()));
+        for tick in 0..input_delay {
+            for player in 0..2 {
+                lockstep.insert(tick, player, idle_input());
+            }
+        }
+        lockstep
+    }
+
+    /// Samples the local input for the current tick like `sync_inputs`.
+    fn sample(lockstep: &mut Lockstep, input: PlayerInput) {
+        let tick = lockstep.tick;
+        lockstep.sampled = Some(tick);
+        let (player, history) = &mut lockstep.local[0];
+        let player = *player;
+        history.push_back(input);
+        if history.len() > REDUNDANCY {
+            history.pop_front();
+        }
+        lockstep.insert(tick + lockstep.input_delay, player, input);
+    }
+
+    fn deliver(packet: &[u8], to: &mut Lockstep) {
+        let (_, player, first, inputs) = decode_packet(packet).unwrap();
+        for (i, input) in inputs.into_iter().enumerate() {
+            to.insert(first + i as u32, player, input);
+        }
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let mut lockstep = peer(1, 3);
+        for i in 0..30 {
+            sample(&mut lockstep, input(i));
+            lockstep.tick += 1;
+        }
+        let packet = lockstep.packet(7, 1).unwrap();
+        let (room, player, first, inputs) = decode_packet(&packet).unwrap();
+        assert_eq!((room, player), (7, 1));
+        assert_eq!(packet_room(&packet), Some(7));
+        assert_eq!(first, 30 + 3 - REDUNDANCY as u32);
+        assert_eq!(inputs.len(), REDUNDANCY);
+        for (i, decoded) in inputs.iter().enumerate() {
+            let sent = input(30 - REDUNDANCY + i);
+            assert_eq!(encode_input(decoded), encode_input(&sent));
+        }
+    }
+
+    #[test]
+    fn peers_apply_same_inputs() {
+        let mut peers = [peer(0, 2), peer(1, 2)];
+        let mut applied = [vec![], vec![]];
+        for i in 0..40 {
+            for (p, lockstep) in peers.iter_mut().enumerate() {
+                if lockstep.sampled != Some(lockstep.tick) {
+                    sample(lockstep, input(i * 2 + p));
+                }
+            }
+            // Every third packet of the second peer is lost, which the
+            // redundant actions in later packets make up for
+            let packets = [
+                peers[0].packet(0, 0),
+                peers[1].packet(0, 1).filter(|_| i % 3 != 0),
+            ];
+            if let Some(packet) = &packets[0] {
+                deliver(packet, &mut peers[1]);
+            }
+            if let Some(packet) = &packets[1] {
+                deliver(packet, &mut peers[0]);
+            }
+            for (p, lockstep) in peers.iter_mut().enumerate() {
+                if let Some(inputs) = lockstep.take_ready() {
+                    applied[p].push(
+                        inputs
+                            .iter()
+                            .map(|(player, input)| {
+                                (*player, encode_input(input))
+                            })
+                            .collect::<Vec<_>>(),
+                    );
+                }
+            }
+        }
+        let ticks = applied[0].len().min(applied[1].len());
+        assert!(ticks > 20);
+        assert_eq!(applied[0][..ticks], applied[1][..ticks]);
+    }
+
+    #[test]
+    fn target_presses_reach_peers() {
+        let mut peers = [peer(0, 2), peer(1, 2)];
+        let pressed = PlayerInput {
+            toggle_target: true,
+            ..idle_input()
+        };
+        sample(&mut peers[0], pressed);
+        sample(&mut peers[1], idle_input());
+        deliver(&peers[0].packet(0, 0).unwrap(), &mut peers[1]);
+        deliver(&peers[1].packet(0, 1).unwrap(), &mut peers[0]);
+        for lockstep in &mut peers {
+            let toggles = (0..3)
+                .map(|_| {
+                    let inputs = lockstep.take_ready().unwrap();
+                    inputs
+                        .values()
+                        .map(|input| input.toggle_target)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            // Applied by both peers `input_delay` ticks after the press
+            let expected = [[false, false], [false, false], [true, false]];
+            assert_eq!(toggles, expected);
+        }
+        // Anything but 0 or 1 is a malformed target press
+        let mut packet = peers[0].packet(0, 0).unwrap();
+        *packet.last_mut().unwrap() = 2;
+        assert!(decode_packet(&packet).is_none());
+    }
+}
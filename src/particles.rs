@@ -0,0 +1,311 @@
+//! Cosmetic particle effects simulated on the CPU.
+//!
+//! The `ParticlePlugin` turns gameplay events into short-lived particles:
+//! explosions when fighters and asteroids are destroyed, a flash when a
+//! passive shield breaks, and, from the purely cosmetic `Effect` events,
+//! sparks where projectiles hit and exhaust trails behind thrusting fighters.
+//! The plugin is not added in headless mode, so the events are simply dropped
+//! during training.
+
+use std::collections::HashMap;
+
+use bevy::prelude::shape::Quad;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::event::{AsteroidDestroyed, FighterDestroyed, ShieldBroken};
+use crate::{Asteroid, Fighter, FIGHTER_COLORS};
+
+const EXPLOSION_PARTICLES: usize = 40;
+const SPARK_PARTICLES: usize = 8;
+const SHIELD_PARTICLES: usize = 24;
+/// Exhaust particles emitted per frame at full thrust.
+const EXHAUST_RATE: f32 = 0.6;
+/// Distance from the center of a fighter to its engine.
+const ENGINE_OFFSET: f32 = 25.0;
+
+/// Cosmetic moments without a gameplay event of their own.
+pub(crate) enum Effect {
+    /// A projectile hit something at the position of the entity.
+    Impact(Entity),
+    /// A fighter set its engine to the given multiple of its base thrust.
+    Thrust(Entity, f32),
+}
+
+enum Burst {
+    Explosion,
+    Impact,
+    ShieldBreak,
+}
+
+pub(crate) struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ParticleRng(SmallRng::seed_from_u64(0)))
+            .init_resource::<Throttles>()
+            .add_startup_system(setup_particles)
+            .add_system(
+                spawn_effects
+                    .after(crate::detect_collisions)
+                    .after(crate::fighter_actions),
+            )
+            .add_system(emit_exhaust.after(spawn_effects))
+            .add_system(update_particles.after(emit_exhaust));
+    }
+}
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    remaining_lifetime: f32,
+    lifetime: f32,
+    size: f32,
+}
+
+/// Randomness of particles, kept apart from `RngState` so that effects don't
+/// change the simulation.
+#[derive(Resource)]
+struct ParticleRng(SmallRng);
+
+/// Mesh and materials shared by all particles.
+#[derive(Resource)]
+struct ParticleAssets {
+    mesh: Handle<Mesh>,
+    fighters: Vec<Handle<ColorMaterial>>,
+    asteroid: Handle<ColorMaterial>,
+    flash: Handle<ColorMaterial>,
+    shield: Handle<ColorMaterial>,
+}
+
+impl ParticleAssets {
+    /// Material of the fighters of `player_id`, with colors repeating for
+    /// more players than there are colors.
+    fn fighter(&self, player_id: usize) -> &Handle<ColorMaterial> {
+        &self.fighters[player_id % self.fighters.len()]
+    }
+}
+
+/// Current thrust of each fighter, as last sent with `Effect::Thrust`.
+#[derive(Resource, Default)]
+struct Throttles(HashMap<Entity, f32>);
+
+fn setup_particles(
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut material = |color: Color| materials.add(ColorMaterial::from(color));
+    cmd.insert_resource(ParticleAssets {
+        mesh: meshes.add(Quad::new(Vec2::ONE).into()),
+        fighters: FIGHTER_COLORS.iter().map(|c| material(*c)).collect(),
+        asteroid: material(Color::rgb(1.0, 0.5, 0.2)),
+        flash: material(Color::rgb(1.0, 0.9, 0.6)),
+        shield: material(Color::hsla(250.0, 1.0, 0.7, 1.0)),
+    });
+}
+
+/// Spawns `count` particles flying away from `position` at up to
+/// `max_speed`, in addition to `velocity`.
+#[allow(clippy::too_many_arguments)]
+fn burst(
+    cmd: &mut Commands,
+    rng: &mut SmallRng,
+    mesh: &Handle<Mesh>,
+    material: &Handle<ColorMaterial>,
+    position: Vec2,
+    velocity: Vec2,
+    count: usize,
+    max_speed: f32,
+    size: f32,
+    lifetime: f32,
+) {
+    for _ in 0..count {
+        let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+        let speed = rng.gen_range(0.2..1.0) * max_speed;
+        let lifetime = rng.gen_range(0.5..1.0) * lifetime;
+        cmd.spawn(Particle {
+            velocity: velocity + speed * Vec2::new(angle.cos(), angle.sin()),
+            remaining_lifetime: lifetime,
+            lifetime,
+            size,
+        })
+        .insert(ColorMesh2dBundle {
+            mesh: mesh.clone().into(),
+            material: material.clone(),
+            transform: Transform::from_translation(position.extend(2.0))
+                .with_scale(Vec3::splat(size)),
+            ..default()
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_effects(
+    mut cmd: Commands,
+    mut effects: EventReader<Effect>,
+    mut fighters_destroyed: EventReader<FighterDestroyed>,
+    mut asteroids_destroyed: EventReader<AsteroidDestroyed>,
+    mut shields_broken: EventReader<ShieldBroken>,
+    mut rng: ResMut<ParticleRng>,
+    mut throttles: ResMut<Throttles>,
+    assets: Res<ParticleAssets>,
+    bodies: Query<(&Transform, Option<&Velocity>)>,
+    fighters: Query<&Fighter>,
+    asteroids: Query<&Asteroid>,
+) {
+    let mut bursts = vec![];
+    for effect in effects.iter() {
+        match *effect {
+            Effect::Impact(entity) => bursts.push((Burst::Impact, entity)),
+            Effect::Thrust(fighter, thrust) => {
+                throttles.0.insert(fighter, thrust);
+            }
+        }
+    }
+    bursts.extend(
+        fighters_destroyed
+            .iter()
+            .map(|event| (Burst::Explosion, event.victim)),
+    );
+    bursts.extend(
+        asteroids_destroyed
+            .iter()
+            .map(|event| (Burst::Explosion, event.asteroid)),
+    );
+    bursts.extend(
+        shields_broken
+            .iter()
+            .map(|event| (Burst::ShieldBreak, event.fighter)),
+    );
+    for (burst_kind, entity) in bursts {
+        let (position, velocity) = match bodies.get(entity) {
+            Ok((transform, velocity)) => (
+                transform.translation.truncate(),
+                velocity.map_or(Vec2::ZERO, |v| v.linvel),
+            ),
+            Err(_) => continue,
+        };
+        match burst_kind {
+            Burst::Explosion => {
+                let (material, radius) =
+                    if let Ok(fighter) = fighters.get(entity) {
+                        (assets.fighter(fighter.player_id), 30.0)
+                    } else if let Ok(asteroid) = asteroids.get(entity) {
+                        (&assets.asteroid, asteroid.radius)
+                    } else {
+                        continue;
+                    };
+                burst(
+                    &mut cmd,
+                    &mut rng.0,
+                    &assets.mesh,
+                    material,
+                    position,
+                    velocity * 0.5,
+                    EXPLOSION_PARTICLES,
+                    radius * 8.0,
+                    radius * 0.2,
+                    0.8,
+                );
+                burst(
+                    &mut cmd,
+                    &mut rng.0,
+                    &assets.mesh,
+                    &assets.flash,
+                    position,
+                    velocity * 0.5,
+                    EXPLOSION_PARTICLES / 2,
+                    radius * 4.0,
+                    radius * 0.3,
+                    0.4,
+                );
+            }
+            Burst::Impact => burst(
+                &mut cmd,
+                &mut rng.0,
+                &assets.mesh,
+                &assets.flash,
+                position,
+                Vec2::ZERO,
+                SPARK_PARTICLES,
+                400.0,
+                3.0,
+                0.25,
+            ),
+            Burst::ShieldBreak => burst(
+                &mut cmd,
+                &mut rng.0,
+                &assets.mesh,
+                &assets.shield,
+                position,
+                velocity,
+                SHIELD_PARTICLES,
+                300.0,
+                5.0,
+                0.3,
+            ),
+        }
+    }
+}
+
+fn emit_exhaust(
+    mut cmd: Commands,
+    mut rng: ResMut<ParticleRng>,
+    mut throttles: ResMut<Throttles>,
+    assets: Res<ParticleAssets>,
+    fighters: Query<(&Fighter, &Transform, &Velocity)>,
+) {
+    throttles.0.retain(|entity, _| fighters.contains(*entity));
+    for (&entity, &thrust) in throttles.0.iter() {
+        let (fighter, transform, velocity) = fighters.get(entity).unwrap();
+        if thrust <= 0.0 || rng.0.gen::<f32>() >= thrust * EXHAUST_RATE {
+            continue;
+        }
+        let (direction_x, direction_y) =
+            crate::transform_to_direction(transform);
+        let backwards = -Vec2::new(direction_x, direction_y);
+        let spread = rng.0.gen_range(-0.3..0.3);
+        let lifetime = 0.3 * thrust;
+        cmd.spawn(Particle {
+            velocity: velocity.linvel
+                + Vec2::from_angle(spread).rotate(backwards) * 300.0 * thrust,
+            remaining_lifetime: lifetime,
+            lifetime,
+            size: 4.0 * thrust,
+        })
+        .insert(ColorMesh2dBundle {
+            mesh: assets.mesh.clone().into(),
+            material: assets.fighter(fighter.player_id).clone(),
+            transform: Transform::from_translation(
+                (transform.translation.truncate() + backwards * ENGINE_OFFSET)
+                    .extend(0.9),
+            )
+            .with_scale(Vec3::splat(4.0 * thrust)),
+            ..default()
+        });
+    }
+}
+
+/// Moves particles and shrinks them until they disappear.
+fn update_particles(
+    mut cmd: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        particle.remaining_lifetime -= dt;
+        if particle.remaining_lifetime <= 0.0 {
+            cmd.entity(entity).despawn();
+            continue;
+        }
+        transform.translation += (particle.velocity * dt).extend(0.0);
+        particle.velocity *= 1.0 - 2.0 * dt.min(0.5);
+        transform.scale = Vec3::splat(
+            particle.size * particle.remaining_lifetime / particle.lifetime,
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Power-ups that appear at random positions and are collected by flying into
+//! them.
+//!
+//! A power-up spawns every `Settings.powerup_interval` frames while fewer than
+//! `Settings.max_powerups` are present, and disappears again after
+//! `Settings.powerup_lifetime` frames. Power-ups are off unless
+//! `Settings.powerup_interval` is set, which keeps the observations and random
+//! numbers of existing training setups unchanged.
+
+use bevy::prelude::shape::Quad;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use crate::ids::RecordSpawnOrder;
+use crate::{CollisionQueue, CollisionType, RngState, Settings, Stats};
+
+const RADIUS: f32 = 20.0;
+/// Distance from the walls within which no power-ups spawn.
+pub(crate) const WALL_CLEARANCE: f32 = 100.0;
+pub(crate) const RAPID_FIRE_DURATION: i32 = 600;
+pub(crate) const SPEED_BOOST_DURATION: i32 = 600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PowerUpKind {
+    /// Repairs the hull to `Fighter.max_health` and restores the passive
+    /// shield of fighters that have one.
+    Restore,
+    /// Halves weapon cooldowns for `RAPID_FIRE_DURATION` frames.
+    RapidFire,
+    /// Raises thrust and top speed for `SPEED_BOOST_DURATION` frames.
+    SpeedBoost,
+    /// Respawns the player's last fighter instead of ending the game.
+    ExtraLife,
+}
+
+impl PowerUpKind {
+    pub(crate) const ALL: [PowerUpKind; 4] = [
+        PowerUpKind::Restore,
+        PowerUpKind::RapidFire,
+        PowerUpKind::SpeedBoost,
+        PowerUpKind::ExtraLife,
+    ];
+
+    pub(crate) fn color(self) -> Color {
+        match self {
+            PowerUpKind::Restore => Color::rgb(0.3, 0.5, 1.0),
+            PowerUpKind::RapidFire => Color::rgb(1.0, 0.8, 0.2),
+            PowerUpKind::SpeedBoost => Color::rgb(0.3, 1.0, 0.4),
+            PowerUpKind::ExtraLife => Color::rgb(1.0, 0.4, 0.8),
+        }
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct PowerUp {
+    pub(crate) kind: PowerUpKind,
+    pub(crate) remaining_lifetime: i32,
+}
+
+pub(crate) fn spawn_powerups(
+    settings: Res<Settings>,
+    stats: Res<Stats>,
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    powerups: Query<&PowerUp>,
+    mut rng: ResMut<RngState>,
+) {
+    if !settings.powerups_enabled()
+        || powerups.iter().count() >= settings.max_powerups as usize
+    {
+        return;
+    }
+    // Spawn whenever the timestep counter passes a multiple of the interval
+    let interval = settings.powerup_interval as usize;
+    let previous = stats.timesteps.saturating_sub(settings.frameskip as usize);
+    if stats.timesteps / interval == previous / interval {
+        return;
+    }
+    let kind = PowerUpKind::ALL[rng.gen_range(0..PowerUpKind::ALL.len())];
+    let bounds = settings.arena_half_size() - WALL_CLEARANCE;
+    let position = Vec3::new(
+        rng.gen_range(-bounds.x..bounds.x),
+        rng.gen_range(-bounds.y..bounds.y),
+        0.5,
+    );
+    cmd.spawn(PowerUp {
+        kind,
+        remaining_lifetime: settings.powerup_lifetime as i32,
+    })
+    .record_spawn_order()
+    .insert(Collider::ball(RADIUS))
+    .insert(Sensor)
+    .insert(ActiveEvents::COLLISION_EVENTS)
+    .insert(CollisionType::PowerUp)
+    .insert(ColorMesh2dBundle {
+        mesh: meshes
+            .add(Quad::new(Vec2::splat(RADIUS * 1.2)).into())
+            .into(),
+        transform: Transform::default()
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4))
+            .with_translation(position),
+        material: materials.add(ColorMaterial::from(kind.color())),
+        ..default()
+    });
+}
+
+pub(crate) fn expire_powerups(
+    settings: Res<Settings>,
+    mut cmd: Commands,
+    mut queue: ResMut<CollisionQueue>,
+    mut powerups: Query<(Entity, &mut PowerUp)>,
+) {
+    for (entity, mut powerup) in powerups.iter_mut() {
+        powerup.remaining_lifetime -= settings.frameskip as i32;
+        if powerup.remaining_lifetime <= 0 {
+            queue.despawn(&mut cmd, entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn powerups_expire_after_their_lifetime() {
+        let mut app = App::new();
+        app.insert_resource(Settings {
+            frameskip: 2,
+            ..Settings::default()
+        })
+        .init_resource::<CollisionQueue>()
+        .add_system(expire_powerups);
+        let powerup = app
+            .world
+            .spawn(PowerUp {
+                kind: PowerUpKind::RapidFire,
+                remaining_lifetime: 4,
+            })
+            .id();
+
+        app.update();
+        let remaining = app.world.get::<PowerUp>(powerup).unwrap();
+        assert_eq!(remaining.remaining_lifetime, 2);
+        app.update();
+        assert!(app.world.get_entity(powerup).is_none());
+    }
+}
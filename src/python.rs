@@ -1,8 +1,13 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
 use crate::*;
 
-use entity_gym_rs::agent::TrainEnvBuilder;
+use bevy::prelude::*;
+use entity_gym_rs::agent::{Action, Featurizable, TrainEnvBuilder};
 use entity_gym_rs::low_level::py_vec_env::PyVecEnv;
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
 
 #[derive(Clone)]
 #[pyclass]
@@ -11,35 +16,117 @@ pub struct Config {
     pub act_interval: u32,
     pub versus: bool,
     pub ccd: bool,
+    /// See `Settings.sensor_range`.
+    pub sensor_range: Option<f32>,
+    /// Width of the frames returned by `render`, which is only available if
+    /// set.
+    pub render_width: Option<u32>,
+    /// See `Settings.observation_history`.
+    pub history: usize,
+    /// See `Settings.powerup_interval`.
+    pub powerup_interval: u32,
+    /// See `Settings.agent_loadout`.
+    pub loadout: bool,
+    /// See `Settings.kill_rewards`.
+    pub kill_rewards: bool,
+    /// See `Settings.observe_ids`.
+    pub ids: bool,
+    /// Where environments publish their frames, set by `create_env`.
+    pub frames: Option<Frames>,
 }
 
 #[pymethods]
 impl Config {
     #[new]
-    #[args(frameskip = "1", act_interval = "1", versus = "true", ccd = "true")]
-    fn new(frameskip: u32, act_interval: u32, versus: bool, ccd: bool) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    #[args(
+        frameskip = "1",
+        act_interval = "1",
+        versus = "true",
+        ccd = "true",
+        sensor_range = "None",
+        render_width = "None",
+        history = "0",
+        powerup_interval = "0",
+        loadout = "false",
+        kill_rewards = "false",
+        ids = "false"
+    )]
+    fn new(
+        frameskip: u32,
+        act_interval: u32,
+        versus: bool,
+        ccd: bool,
+        sensor_range: Option<f32>,
+        render_width: Option<u32>,
+        history: usize,
+        powerup_interval: u32,
+        loadout: bool,
+        kill_rewards: bool,
+        ids: bool,
+    ) -> Self {
         Config {
             frameskip,
             act_interval,
             versus,
             ccd,
+            sensor_range,
+            render_width,
+            history,
+            powerup_interval,
+            loadout,
+            kill_rewards,
+            ids,
+            frames: None,
         }
     }
 }
 
-#[pyfunction]
+/// Creates `num_envs` environments with the indices from `first_env_index`,
+/// which are also their seeds. With `Config.render_width`, the environments
+/// publish their frames to `frames`. Agents act with the categorical
+/// `FighterAction`, since entity-gym has no continuous action spaces;
+/// continuous control is only available to bots, see `remote`.
+#[pyfunction(frames = "None")]
 fn create_env(
-    config: Config,
+    mut config: Config,
     num_envs: usize,
     threads: usize,
     first_env_index: u64,
+    frames: Option<Frames>,
 ) -> PyVecEnv {
-    let builder = TrainEnvBuilder::default()
-        .entity::<entity::Fighter>()
-        .entity::<entity::EnemyFighter>()
-        .entity::<entity::Asteroid>()
-        .entity::<entity::Bullet>()
-        .action::<act::FighterAction>();
+    if let (Some(frames), Some(_)) = (&frames, config.render_width) {
+        // Blank frames until the first ones are rendered
+        let (width, height) =
+            crate::raster::frame_size(&crate::training_settings(&config, 0));
+        let mut images = frames.0.lock().unwrap();
+        for index in first_env_index..first_env_index + num_envs as u64 {
+            images.insert(index, image::RgbImage::new(width, height));
+        }
+    }
+    config.frames = frames;
+    let ids = config.ids;
+    let mut builder = TrainEnvBuilder::default();
+    if config.loadout {
+        builder = register_entity::<entity::Fighter>(builder, ids);
+        builder = register_entity::<entity::EnemyFighter>(builder, ids);
+        builder = register_entity::<entity::Asteroid>(builder, ids);
+        builder = register_entity::<entity::Bullet>(builder, ids);
+        builder = builder.action::<act::FighterAction>();
+    } else {
+        builder = register_entity::<entity::classic::Fighter>(builder, ids);
+        builder =
+            register_entity::<entity::classic::EnemyFighter>(builder, ids);
+        builder = register_entity::<entity::classic::Asteroid>(builder, ids);
+        builder = register_entity::<entity::classic::Bullet>(builder, ids);
+        builder = builder.action::<act::classic::FighterAction>();
+    }
+    if config.powerup_interval > 0 {
+        builder = register_entity::<entity::PowerUp>(builder, ids);
+    }
+    if config.history > 0 {
+        builder = register_entity::<entity::PastFighter>(builder, ids);
+    }
     if config.versus {
         builder.build_multiagent::<_, _, 2>(
             config,
@@ -53,9 +140,92 @@ fn create_env(
     }
 }
 
+/// Registers the entity type `E`, with the ID feature if `ids` is set.
+fn register_entity<E: Featurizable>(
+    builder: TrainEnvBuilder,
+    ids: bool,
+) -> TrainEnvBuilder {
+    if ids {
+        builder.entity::<entity::Identified<E>>()
+    } else {
+        builder.entity::<E>()
+    }
+}
+
+/// Latest software rendered frame of each environment created with it, by
+/// environment index.
+#[derive(Clone, Default)]
+#[pyclass]
+pub struct Frames(Arc<Mutex<HashMap<u64, image::RgbImage>>>);
+
+#[pymethods]
+impl Frames {
+    #[new]
+    fn new() -> Self {
+        Frames::default()
+    }
+
+    /// Returns the latest frame of environment `env_index` as a tuple of
+    /// height, width and RGB bytes, or `None` if the environment wasn't
+    /// created with these frames and `Config.render_width`.
+    fn render(
+        &self,
+        py: Python,
+        env_index: u64,
+    ) -> Option<(u32, u32, PyObject)> {
+        let frames = self.0.lock().unwrap();
+        let frame = frames.get(&env_index)?;
+        Some((
+            frame.height(),
+            frame.width(),
+            PyBytes::new(py, frame.as_raw()).into(),
+        ))
+    }
+}
+
+/// Frames of the environment simulated by an app, with its index.
+#[derive(Resource)]
+pub(crate) struct FrameSink {
+    pub(crate) frames: Frames,
+    pub(crate) env_index: u64,
+}
+
+pub(crate) fn publish_frame(
+    sink: Res<FrameSink>,
+    frame: Res<crate::raster::Frame>,
+) {
+    sink.frames
+        .0
+        .lock()
+        .unwrap()
+        .insert(sink.env_index, frame.0.clone());
+}
+
+/// Returns the index in the labels of the action space of `config` of an
+/// action given as JSON, e.g. `{"thrust": "On", "shoot": "Off", "turn":
+/// "None"}`.
+#[pyfunction]
+fn action_index(config: Config, action: String) -> PyResult<u64> {
+    let index = if config.loadout {
+        serde_json::from_str::<act::FighterAction>(&action)
+            .map(|action| action.to_u64())
+    } else {
+        serde_json::from_str::<act::classic::FighterAction>(&action)
+            .map(|action| action.to_u64())
+    };
+    index.map_err(|e| {
+        pyo3::exceptions::PyValueError::new_err(format!(
+            "invalid action {}: {}",
+            action, e
+        ))
+    })
+}
+
 #[pymodule]
 fn bevy_starfighter(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(create_env, m)?)?;
+    m.add_function(wrap_pyfunction!(action_index, m)?)?;
     m.add_class::<Config>()?;
+    m.add_class::<Frames>()?;
     Ok(())
 }
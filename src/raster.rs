@@ -0,0 +1,318 @@
+//! Software renderer drawing the arena into an RGB image on the CPU.
+//!
+//! Fighters, asteroids, projectiles and power-ups are drawn from their
+//! colliders and transforms, so frames can be rendered in headless mode on
+//! machines without a GPU. Every update, the `RasterPlugin` draws a new
+//! `Frame`, which is written to `Settings.record_frames` as numbered PNGs or
+//! handed to Python by `python::publish_frame`.
+
+use bevy::log;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use image::{Rgb, RgbImage};
+
+use crate::{
+    act, powerup, weapon, Asteroid, Bullet, Fighter, Settings, BULLET_COLORS,
+    FIGHTER_COLORS,
+};
+
+const BACKGROUND_COLOR: Color = Color::rgb(0.07, 0.07, 0.07);
+const ASTEROID_COLOR: Color = Color::rgb(0.4, 0.1, 0.1);
+const LASER_WIDTH: f32 = 4.0;
+
+/// Most recently rendered frame.
+#[derive(Resource)]
+pub(crate) struct Frame(pub(crate) RgbImage);
+
+pub(crate) struct RasterPlugin;
+
+impl Plugin for RasterPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Frame(RgbImage::new(1, 1)))
+            .add_system_to_stage(CoreStage::Last, rasterize);
+        #[cfg(not(target_arch = "wasm32"))]
+        if app.world.resource::<Settings>().record_frames.is_some() {
+            app.add_system_to_stage(
+                CoreStage::Last,
+                save_frames.after(rasterize),
+            );
+        }
+    }
+}
+
+enum Shape {
+    Circle(Vec2, f32),
+    Polygon(Vec<Vec2>),
+}
+
+impl Shape {
+    /// World space shape of `collider` attached to an entity at `transform`.
+    fn from_collider(
+        collider: &Collider,
+        transform: &Transform,
+    ) -> Option<Shape> {
+        // Undo any scale Rapier already applied to the collider
+        let scale = transform.scale.truncate() / collider.scale();
+        let center = transform.translation.truncate();
+        let to_world = |point: Vec2| {
+            center
+                + (transform.rotation * (point * scale).extend(0.0)).truncate()
+        };
+        if let Some(ball) = collider.as_ball() {
+            Some(Shape::Circle(center, ball.radius() * scale.x))
+        } else if let Some(polygon) = collider.as_convex_polygon() {
+            Some(Shape::Polygon(polygon.points().map(to_world).collect()))
+        } else {
+            collider.as_cuboid().map(|cuboid| {
+                let Vec2 { x, y } = cuboid.half_extents();
+                Shape::Polygon(
+                    [(-x, -y), (x, -y), (x, y), (-x, y)]
+                        .into_iter()
+                        .map(|(x, y)| to_world(Vec2::new(x, y)))
+                        .collect(),
+                )
+            })
+        }
+    }
+
+    /// Rectangle of the given `width` from `from` to `to`.
+    fn line(from: Vec2, to: Vec2, width: f32) -> Shape {
+        let normal = (to - from).normalize_or_zero().perp() * width / 2.0;
+        Shape::Polygon(vec![
+            from - normal,
+            to - normal,
+            to + normal,
+            from + normal,
+        ])
+    }
+}
+
+/// Image of the arena, with `scale` pixels per world unit.
+struct Canvas<'a> {
+    image: &'a mut RgbImage,
+    half_size: Vec2,
+    scale: f32,
+}
+
+fn to_rgb(color: Color) -> Rgb<u8> {
+    let [r, g, b, _] = color.as_rgba_f32();
+    Rgb([r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+impl<'a> Canvas<'a> {
+    fn to_pixels(&self, position: Vec2) -> Vec2 {
+        Vec2::new(position.x + self.half_size.x, self.half_size.y - position.y)
+            * self.scale
+    }
+
+    /// Sets every pixel with its center within the bounding box of `points`
+    /// and for which `inside` holds.
+    fn fill(
+        &mut self,
+        points: &[Vec2],
+        color: Color,
+        inside: impl Fn(Vec2) -> bool,
+    ) {
+        let min = points.iter().fold(Vec2::splat(f32::MAX), |a, b| a.min(*b));
+        let max = points.iter().fold(Vec2::splat(f32::MIN), |a, b| a.max(*b));
+        let (width, height) = self.image.dimensions();
+        let x_range =
+            min.x.max(0.0) as u32..(max.x.ceil().max(0.0) as u32).min(width);
+        let y_range =
+            min.y.max(0.0) as u32..(max.y.ceil().max(0.0) as u32).min(height);
+        let color = to_rgb(color);
+        for y in y_range {
+            for x in x_range.clone() {
+                if inside(Vec2::new(x as f32 + 0.5, y as f32 + 0.5)) {
+                    self.image.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, shape: &Shape, color: Color) {
+        match shape {
+            Shape::Circle(center, radius) => {
+                let center = self.to_pixels(*center);
+                // Keep small projectiles visible at low resolutions
+                let radius = (radius * self.scale).max(0.5);
+                self.fill(
+                    &[center - radius, center + radius],
+                    color,
+                    |pixel| pixel.distance_squared(center) <= radius * radius,
+                );
+            }
+            Shape::Polygon(points) => {
+                let points = points
+                    .iter()
+                    .map(|point| self.to_pixels(*point))
+                    .collect::<Vec<_>>();
+                let edges = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| (*a, *b - *a))
+                    .collect::<Vec<_>>();
+                // Inside a convex polygon, the pixel is on the same side of
+                // all edges, whichever the winding order
+                self.fill(&points, color, |pixel| {
+                    let sides = edges
+                        .iter()
+                        .map(|(start, edge)| edge.perp_dot(pixel - *start));
+                    let (mut left, mut right) = (true, true);
+                    for side in sides {
+                        left &= side >= 0.0;
+                        right &= side <= 0.0;
+                    }
+                    left || right
+                });
+            }
+        }
+    }
+}
+
+/// Width and height of the frames rendered with `settings`.
+pub(crate) fn frame_size(settings: &Settings) -> (u32, u32) {
+    let width = settings.software_render_width.unwrap_or(1).max(1);
+    let scale = width as f32 / settings.arena_size.x;
+    let height = ((settings.arena_size.y * scale).round() as u32).max(1);
+    (width, height)
+}
+
+pub(crate) fn rasterize(
+    settings: Res<Settings>,
+    mut frame: ResMut<Frame>,
+    fighters: Query<(&Fighter, &Collider, &Transform)>,
+    asteroids: Query<(&Collider, &Transform), With<Asteroid>>,
+    bullets: Query<(
+        &Bullet,
+        &Transform,
+        Option<&Collider>,
+        Option<&weapon::Laser>,
+    )>,
+    powerups: Query<(&powerup::PowerUp, &Collider, &Transform)>,
+) {
+    let (width, height) = frame_size(&settings);
+    let scale = width as f32 / settings.arena_size.x;
+    if frame.0.dimensions() != (width, height) {
+        frame.0 = RgbImage::new(width, height);
+    }
+    let mut canvas = Canvas {
+        image: &mut frame.0,
+        half_size: settings.arena_half_size(),
+        scale,
+    };
+    let background = to_rgb(BACKGROUND_COLOR);
+    canvas
+        .image
+        .pixels_mut()
+        .for_each(|pixel| *pixel = background);
+
+    for (collider, transform) in asteroids.iter() {
+        if let Some(shape) = Shape::from_collider(collider, transform) {
+            canvas.draw(&shape, ASTEROID_COLOR);
+        }
+    }
+    for (powerup, collider, transform) in powerups.iter() {
+        if let Some(shape) = Shape::from_collider(collider, transform) {
+            canvas.draw(&shape, powerup.kind.color());
+        }
+    }
+    for (bullet, transform, collider, laser) in bullets.iter() {
+        let position = transform.translation.truncate();
+        let shape = if let Some(laser) = laser {
+            // The beam is centered between the fighter and where it ends
+            let direction = (transform.rotation * Vec3::X).truncate();
+            let half = direction * laser.length / 2.0;
+            Shape::line(position - half, position + half, LASER_WIDTH)
+        } else if bullet.weapon == act::Weapon::Mine {
+            // Armed mines have a much larger trigger collider
+            Shape::Circle(position, weapon::MINE_RADIUS)
+        } else if let Some(shape) =
+            collider.and_then(|c| Shape::from_collider(c, transform))
+        {
+            shape
+        } else {
+            continue;
+        };
+        canvas.draw(
+            &shape,
+            BULLET_COLORS[bullet.player_id % BULLET_COLORS.len()],
+        );
+    }
+    for (fighter, collider, transform) in fighters.iter() {
+        if let Some(shape) = Shape::from_collider(collider, transform) {
+            canvas.draw(
+                &shape,
+                FIGHTER_COLORS[fighter.player_id % FIGHTER_COLORS.len()],
+            );
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_frames(
+    settings: Res<Settings>,
+    frame: Res<Frame>,
+    mut index: Local<u32>,
+) {
+    let dir = match &settings.record_frames {
+        Some(dir) => std::path::Path::new(dir),
+        None => return,
+    };
+    if *index == 0 {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            log::error!("Failed to create {}: {}", dir.display(), e);
+        }
+    }
+    let path = dir.join(format!("{:06}.png", *index));
+    if let Err(e) = frame.0.save(&path) {
+        log::error!("Failed to save {}: {}", path.display(), e);
+    }
+    *index += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws `collider` at `transform` into an image of a 20 by 10 arena with
+    /// one pixel per world unit, returns the covered pixels row by row.
+    fn covered(collider: Collider, transform: Transform) -> Vec<(u32, u32)> {
+        let mut image = RgbImage::new(20, 10);
+        let mut canvas = Canvas {
+            image: &mut image,
+            half_size: Vec2::new(10.0, 5.0),
+            scale: 1.0,
+        };
+        let shape = Shape::from_collider(&collider, &transform).unwrap();
+        canvas.draw(&shape, Color::WHITE);
+        let white = to_rgb(Color::WHITE);
+        image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == white)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn ball() {
+        let pixels =
+            covered(Collider::ball(2.0), Transform::from_xyz(-5.0, 2.5, 0.0));
+        assert_eq!(pixels.len(), 12);
+        // Above the center of the arena, so in the top half of the image
+        assert!(pixels
+            .iter()
+            .all(|(x, y)| (3..=6).contains(x) && (1..=3).contains(y)));
+    }
+
+    #[test]
+    fn rotated_cuboid() {
+        let transform = Transform::from_xyz(4.0, -2.0, 0.0)
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        let pixels = covered(Collider::cuboid(3.0, 1.0), transform);
+        // Turned upright, covering x from 3 to 5 and y from -5 to 1
+        let expected: Vec<(u32, u32)> =
+            (4..10).flat_map(|y| [(13, y), (14, y)]).collect();
+        assert_eq!(pixels, expected);
+    }
+}
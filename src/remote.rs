@@ -0,0 +1,670 @@
+//! Server for agents running in other processes, written in any language.
+//!
+//! Agents connect over TCP, sending and receiving one JSON message per line,
+//! or over WebSockets with one JSON message per text frame. Connections that
+//! don't start with an HTTP request within `DETECT_TIMEOUT` are treated as
+//! plain TCP. Each connection becomes the `PlayerAgent` of the next player
+//! with `Controller::Bot` that has no agent yet, and is driven by the `ai`
+//! system like the agents of every other player.
+//!
+//! The server sends
+//! - `{"type": "hello", "player": 1, "action_interval": 12, "continuous":
+//!   false}` on connect,
+//! - `{"type": "observation", "tick": 7, "observation": {"score": 0.0,
+//!   "Fighter": [...], ...}}` every action interval, where the observation
+//!   maps entity-gym's name of every entity type seen by policies to the
+//!   features of those entities, which always include their `id`,
+//! - `{"type": "game_over", "score": 3.0, "stats": {...}}` at the end of every
+//!   game.
+//!
+//! Agents reply to each observation with
+//! `{"tick": 7, "actions": [{"thrust": "On", "shoot": "Off", "turn": "Left"}]}`
+//! containing one action for each entry of `observation.Fighter`. Weapons and
+//! abilities of actions are ignored unless `Settings.agent_loadout` is set,
+//! like the features that describe them are left out. Replies
+//! may also contain `"targets": [3, null]`, the `id` of an observed entity for
+//! each fighter to aim at or `null` to stop aiming, see `act::Target`. Bots of
+//! players in `Settings.continuous_control`, announced by `"continuous": true`,
+//! reply with `"continuous": [{"thrust": 0.5, "turn": -0.2, "fire": 0.9}]`
+//! instead of `actions`, see `act::ContinuousAction`. Replies must arrive
+//! before the next observation is sent, otherwise they are dropped and the
+//! fighters continue with their previous action.
+
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use bevy::log;
+use bevy::prelude::*;
+use entity_gym_rs::agent::Featurizable;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tungstenite::handshake::server::{NoCallback, ServerHandshake};
+use tungstenite::handshake::{HandshakeError, MidHandshake};
+use tungstenite::{Message, WebSocket};
+
+use crate::{
+    act, Decisions, Observation, Observer, PendingDecisions, PlayerAgent,
+    Players, Settings, Stats,
+};
+
+/// Time after which a connection that hasn't sent an HTTP request is treated
+/// as plain TCP, since those bots wait for the hello message.
+const DETECT_TIMEOUT: Duration = Duration::from_millis(200);
+/// Bytes of unsent messages after which a bot that doesn't read them is
+/// disconnected.
+const MAX_OUTBOX: usize = 1 << 20;
+
+/// Requires the `RemoteAgentServer`, which `try_app` binds before adding the
+/// plugin so that launchers can report addresses that are in use.
+pub struct RemoteAgentPlugin;
+
+impl Plugin for RemoteAgentPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(address) = &app.world.resource::<Settings>().bot_server {
+            log::info!("Accepting bot connections on {}", address);
+        }
+        app.add_system(accept_agents);
+    }
+}
+
+/// Listener for bot connections, with the connections that are still being
+/// set up.
+pub(crate) struct RemoteAgentServer {
+    listener: TcpListener,
+    pending: Vec<PendingConnection>,
+}
+
+impl RemoteAgentServer {
+    pub(crate) fn bind(address: &str) -> Result<RemoteAgentServer, String> {
+        let listener = TcpListener::bind(address)
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .map_err(|e| format!("failed to bind {}: {}", address, e))?;
+        Ok(RemoteAgentServer {
+            listener,
+            pending: vec![],
+        })
+    }
+}
+
+enum PendingConnection {
+    /// Waiting for the first bytes, which tell WebSockets from plain TCP.
+    Detecting {
+        stream: TcpStream,
+        since: Instant,
+    },
+    Handshake(MidHandshake<ServerHandshake<TcpStream, NoCallback>>),
+}
+
+impl PendingConnection {
+    /// Advances the setup without blocking, returns the connection once it
+    /// is ready and `Err` if it failed.
+    fn poll(self) -> io::Result<Result<Connection, PendingConnection>> {
+        match self {
+            PendingConnection::Detecting { stream, since } => {
+                let mut start = [0; 4];
+                match stream.peek(&mut start) {
+                    Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+                    Ok(4) if &start == b"GET " => {
+                        Connection::handshake(tungstenite::accept(stream))
+                    }
+                    // Wait for the rest of what may be an HTTP request
+                    Ok(len)
+                        if start[..len] == b"GET "[..len]
+                            && since.elapsed() < DETECT_TIMEOUT =>
+                    {
+                        Ok(Err(PendingConnection::Detecting { stream, since }))
+                    }
+                    Err(e)
+                        if e.kind() == ErrorKind::WouldBlock
+                            && since.elapsed() < DETECT_TIMEOUT =>
+                    {
+                        Ok(Err(PendingConnection::Detecting { stream, since }))
+                    }
+                    Ok(_) => Ok(Ok(Connection::tcp(stream))),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        Ok(Ok(Connection::tcp(stream)))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            PendingConnection::Handshake(handshake) => {
+                Connection::handshake(handshake.handshake())
+            }
+        }
+    }
+}
+
+/// Agent in another process, driving the fighters of one player.
+pub(crate) struct RemoteAgent {
+    connection: Connection,
+    player: usize,
+    /// Whether the player acts with `act::ContinuousAction`.
+    continuous: bool,
+    /// Whether the player chooses weapons and abilities, see
+    /// `Settings.agent_loadout`.
+    loadout: bool,
+    /// Number of observations sent so far.
+    tick: u32,
+    /// Tick and fighters of the observation awaiting a reply.
+    pending: Option<(u32, Vec<Entity>)>,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    tick: u32,
+    #[serde(default)]
+    actions: Vec<act::FighterAction>,
+    #[serde(default)]
+    continuous: Vec<act::ContinuousAction>,
+    #[serde(default)]
+    targets: Vec<act::Target>,
+}
+
+/// Features of an observation as sent to bots, by entity type.
+#[derive(Serialize)]
+struct Features {
+    score: f32,
+    #[serde(flatten)]
+    entities: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Observer for Features {
+    fn actors<E: Featurizable + Serialize>(self, actors: Vec<E>) -> Self {
+        self.entities(actors)
+    }
+
+    fn entities<E: Featurizable + Serialize>(
+        mut self,
+        entities: Vec<E>,
+    ) -> Self {
+        self.entities.insert(E::name().to_string(), json!(entities));
+        self
+    }
+}
+
+impl RemoteAgent {
+    /// Sends the observation and returns the decisions for it, waiting for
+    /// them for up to one action interval when running headless, since the
+    /// game would race ahead of the bot otherwise.
+    pub(crate) fn act(
+        &mut self,
+        observation: Observation,
+        ids: Vec<Entity>,
+        settings: &Settings,
+    ) -> PendingDecisions<'_> {
+        let features = Features {
+            score: observation.score,
+            entities: default(),
+        };
+        let features = observation.observe_into(features, settings, true);
+        if let Err(e) = self.observe(&features, ids) {
+            log::warn!("Bot of player {} disconnected: {}", self.player, e);
+            return Box::new(|| None);
+        }
+        let action_interval = settings
+            .ai_action_interval
+            .unwrap_or(settings.action_interval);
+        let deadline = settings.headless.then(|| {
+            Instant::now()
+                + Duration::from_secs_f32(
+                    action_interval as f32 / settings.frame_rate,
+                )
+        });
+        Box::new(move || self.decide(deadline))
+    }
+
+    /// Decisions that arrived since the last step, if any.
+    pub(crate) fn poll(&mut self) -> Option<Decisions> {
+        self.decide(None)
+    }
+
+    /// Decisions for the pending observation once they arrived, or no
+    /// decisions if they didn't arrive by the `deadline`, `None` if the bot
+    /// disconnected.
+    fn decide(&mut self, deadline: Option<Instant>) -> Option<Decisions> {
+        loop {
+            match self.poll_actions() {
+                Ok(Some(decisions)) => return Some(decisions),
+                Ok(None)
+                    if self.pending.is_some()
+                        && deadline.map_or(false, |d| Instant::now() < d) =>
+                {
+                    std::thread::sleep(Duration::from_micros(100));
+                }
+                Ok(None) => return Some(Decisions::default()),
+                Err(e) => {
+                    log::warn!(
+                        "Bot of player {} disconnected: {}",
+                        self.player,
+                        e
+                    );
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Sends the observation of the next tick.
+    fn observe(
+        &mut self,
+        features: &Features,
+        ids: Vec<Entity>,
+    ) -> io::Result<()> {
+        if let Some((late, _)) = self.pending {
+            log::debug!(
+                "Bot of player {} did not reply to tick {}",
+                self.player,
+                late
+            );
+        }
+        self.tick += 1;
+        self.pending = Some((self.tick, ids));
+        self.connection.send(&json!({
+            "type": "observation",
+            "tick": self.tick,
+            "observation": features,
+        }))
+    }
+
+    /// Returns the actions for the pending observation once they arrived.
+    fn poll_actions(&mut self) -> io::Result<Option<Decisions>> {
+        self.connection.flush()?;
+        while let Some(message) = self.connection.recv()? {
+            let reply: Reply = match serde_json::from_str(&message) {
+                Ok(reply) => reply,
+                Err(e) => {
+                    log::warn!("Invalid reply from bot: {}", e);
+                    continue;
+                }
+            };
+            match self.pending.take() {
+                Some((tick, ids)) if tick == reply.tick => {
+                    let mut decisions = Decisions {
+                        targets: reply
+                            .targets
+                            .into_iter()
+                            .zip(ids.clone())
+                            .collect(),
+                        ..default()
+                    };
+                    // Without loadouts, weapons and abilities are left to
+                    // the defaults like for `act::classic::FighterAction`
+                    let loadout = self.loadout;
+                    if self.continuous {
+                        decisions.continuous = reply
+                            .continuous
+                            .into_iter()
+                            .map(|action| {
+                                if loadout {
+                                    action
+                                } else {
+                                    act::ContinuousAction {
+                                        weapon: act::Weapon::Gun,
+                                        ability: act::Ability::None,
+                                        ..action
+                                    }
+                                }
+                            })
+                            .zip(ids)
+                            .collect();
+                    } else {
+                        decisions.actions = reply
+                            .actions
+                            .into_iter()
+                            .map(|action| {
+                                if loadout {
+                                    action
+                                } else {
+                                    act::classic::FighterAction::from(action)
+                                        .into()
+                                }
+                            })
+                            .zip(ids)
+                            .collect();
+                    }
+                    return Ok(Some(decisions));
+                }
+                pending => self.pending = pending,
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn game_over(&mut self, score: f32, stats: &Stats) {
+        self.pending = None;
+        let result = self.connection.send(&json!({
+            "type": "game_over",
+            "score": score,
+            "stats": stats,
+        }));
+        // A broken connection is dropped once the next reply is polled
+        if let Err(e) = result {
+            log::warn!("Failed to send game over to bot: {}", e);
+        }
+    }
+}
+
+enum Connection {
+    Tcp {
+        reader: BufReader<TcpStream>,
+        line: Vec<u8>,
+        /// Messages that couldn't be written without blocking yet.
+        outbox: Vec<u8>,
+    },
+    WebSocket(Box<WebSocket<TcpStream>>),
+}
+
+impl Connection {
+    fn tcp(stream: TcpStream) -> Connection {
+        Connection::Tcp {
+            reader: BufReader::new(stream),
+            line: vec![],
+            outbox: vec![],
+        }
+    }
+
+    fn handshake(
+        result: Result<
+            WebSocket<TcpStream>,
+            HandshakeError<ServerHandshake<TcpStream, NoCallback>>,
+        >,
+    ) -> io::Result<Result<Connection, PendingConnection>> {
+        match result {
+            Ok(ws) => Ok(Ok(Connection::WebSocket(Box::new(ws)))),
+            Err(HandshakeError::Interrupted(handshake)) => {
+                Ok(Err(PendingConnection::Handshake(handshake)))
+            }
+            Err(HandshakeError::Failure(e)) => {
+                Err(io::Error::new(ErrorKind::Other, e))
+            }
+        }
+    }
+
+    /// Queues a message and writes as much as possible without blocking.
+    fn send(&mut self, message: &serde_json::Value) -> io::Result<()> {
+        match self {
+            Connection::Tcp { outbox, .. } => {
+                outbox.extend_from_slice(format!("{}\n", message).as_bytes());
+                if outbox.len() > MAX_OUTBOX {
+                    return Err(io::Error::new(
+                        ErrorKind::Other,
+                        "bot stopped reading",
+                    ));
+                }
+            }
+            Connection::WebSocket(ws) => {
+                match ws.write_message(Message::Text(message.to_string())) {
+                    Ok(()) => {}
+                    // The message stays queued until `flush`
+                    Err(tungstenite::Error::Io(e))
+                        if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+                }
+            }
+        }
+        self.flush()
+    }
+
+    /// Writes queued messages until the socket would block.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Tcp { reader, outbox, .. } => {
+                while !outbox.is_empty() {
+                    match reader.get_mut().write(outbox) {
+                        Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                        Ok(written) => {
+                            outbox.drain(..written);
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(())
+            }
+            Connection::WebSocket(ws) => match ws.write_pending() {
+                Ok(()) => Ok(()),
+                Err(tungstenite::Error::Io(e))
+                    if e.kind() == ErrorKind::WouldBlock =>
+                {
+                    Ok(())
+                }
+                Err(e) => Err(io::Error::new(ErrorKind::Other, e)),
+            },
+        }
+    }
+
+    /// Returns the next complete message without blocking.
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        match self {
+            Connection::Tcp { reader, line, .. } => {
+                match reader.read_until(b'\n', line) {
+                    Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+                    Ok(_) if line.ends_with(b"\n") => {
+                        let message =
+                            String::from_utf8_lossy(line).into_owned();
+                        line.clear();
+                        Ok(Some(message))
+                    }
+                    Ok(_) => Ok(None),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+                    Err(e) => Err(e),
+                }
+            }
+            Connection::WebSocket(ws) => match ws.read_message() {
+                Ok(Message::Text(message)) => Ok(Some(message)),
+                Ok(Message::Close(_)) => Err(ErrorKind::UnexpectedEof.into()),
+                Ok(_) => Ok(None),
+                Err(tungstenite::Error::Io(e))
+                    if e.kind() == ErrorKind::WouldBlock =>
+                {
+                    Ok(None)
+                }
+                Err(e) => Err(io::Error::new(ErrorKind::Other, e)),
+            },
+        }
+    }
+}
+
+/// Accepts new connections and assigns those that are set up to free bot
+/// players, without ever blocking.
+fn accept_agents(
+    mut server: NonSendMut<RemoteAgentServer>,
+    mut players: NonSendMut<Players>,
+    settings: Res<Settings>,
+) {
+    loop {
+        match server.listener.accept() {
+            Ok((stream, _)) => match stream.set_nonblocking(true) {
+                Ok(()) => server.pending.push(PendingConnection::Detecting {
+                    stream,
+                    since: Instant::now(),
+                }),
+                Err(e) => log::warn!("Failed to accept bot connection: {}", e),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => {
+                log::warn!("Failed to accept bot connection: {}", e);
+                break;
+            }
+        }
+    }
+    for pending in std::mem::take(&mut server.pending) {
+        let connection = match pending.poll() {
+            Ok(Ok(connection)) => connection,
+            Ok(Err(pending)) => {
+                server.pending.push(pending);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Failed to accept bot connection: {}", e);
+                continue;
+            }
+        };
+        let player = players.0.iter().position(|p| p.bot && p.agent.is_none());
+        let player = match player {
+            Some(player) => player,
+            None => {
+                log::warn!(
+                    "Rejecting bot connection, all bot players are taken"
+                );
+                continue;
+            }
+        };
+        let continuous = settings.continuous_control.contains(&player);
+        let mut agent = RemoteAgent {
+            connection,
+            player,
+            continuous,
+            loadout: settings.agent_loadout,
+            tick: 0,
+            pending: None,
+        };
+        let hello = json!({
+            "type": "hello",
+            "player": player,
+            "action_interval": settings
+                .ai_action_interval
+                .unwrap_or(settings.action_interval),
+            "continuous": continuous,
+        });
+        match agent.connection.send(&hello) {
+            Ok(()) => {
+                log::info!("Bot connected as player {}", player);
+                players.0[player].agent = Some(PlayerAgent::Remote(agent));
+            }
+            Err(e) => log::warn!("Failed to greet bot: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::thread;
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::entity;
+
+    const REPLY: &str = concat!(
+        r#"{"tick": 1, "actions": [{"thrust": "On", "shoot": "Off", "#,
+        r#""turn": "Left"}], "targets": [3]}"#,
+    );
+
+    fn observation() -> Observation {
+        Observation {
+            score: 2.0,
+            fighters: vec![],
+            enemy_fighters: vec![],
+            asteroids: vec![entity::Identified {
+                id: 3,
+                entity: entity::Asteroid {
+                    health: 1.0,
+                    radius: 50.0,
+                    x: 10.0,
+                    y: -20.0,
+                    dx: 0.0,
+                    dy: 0.0,
+                },
+            }],
+            bullets: vec![],
+            powerups: vec![],
+            past_fighters: vec![],
+        }
+    }
+
+    /// Sends one observation to a bot that connects with `client`, which
+    /// replies with `REPLY` and returns the message it received, and checks
+    /// both.
+    fn round_trip(client: fn(SocketAddr) -> Value) {
+        let mut server = RemoteAgentServer::bind("127.0.0.1:0").unwrap();
+        let address = server.listener.local_addr().unwrap();
+        let client = thread::spawn(move || client(address));
+        let connection = loop {
+            if let Ok((stream, _)) = server.listener.accept() {
+                stream.set_nonblocking(true).unwrap();
+                server.pending.push(PendingConnection::Detecting {
+                    stream,
+                    since: Instant::now(),
+                });
+            }
+            if let Some(pending) = server.pending.pop() {
+                match pending.poll().unwrap() {
+                    Ok(connection) => break connection,
+                    Err(pending) => server.pending.push(pending),
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        let mut agent = RemoteAgent {
+            connection,
+            player: 1,
+            continuous: false,
+            loadout: false,
+            tick: 0,
+            pending: None,
+        };
+        // Waits for the reply for up to 10 seconds
+        let settings = Settings {
+            headless: true,
+            ai_action_interval: Some(900),
+            ..default()
+        };
+        let fighter = Entity::from_raw(5);
+        let decisions =
+            agent.act(observation(), vec![fighter], &settings)().unwrap();
+
+        let message = client.join().unwrap();
+        assert_eq!(message["type"], "observation");
+        assert_eq!(message["tick"], 1);
+        let observation = &message["observation"];
+        assert_eq!(observation["score"], 2.0);
+        assert_eq!(observation["Fighter"], json!([]));
+        assert_eq!(observation["Asteroid"][0]["id"], 3);
+        assert_eq!(observation["Asteroid"][0]["x"], 10.0);
+        // Power-ups are disabled
+        assert!(observation.get("PowerUp").is_none());
+
+        assert_eq!(decisions.actions.len(), 1);
+        let (action, id) = decisions.actions[0];
+        assert_eq!(id, fighter);
+        assert_eq!(action.thrust, act::Thrust::On);
+        assert_eq!(action.shoot, act::Shoot::Off);
+        assert_eq!(action.turn, act::Turn::Left);
+        assert_eq!(decisions.targets, vec![(act::Target(Some(3)), fighter)]);
+        assert!(decisions.continuous.is_empty());
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        round_trip(|address| {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            writeln!(&stream, "{}", REPLY).unwrap();
+            serde_json::from_str(&line).unwrap()
+        });
+    }
+
+    #[test]
+    fn websocket_round_trip() {
+        round_trip(|address| {
+            let stream = TcpStream::connect(address).unwrap();
+            let (mut ws, _) =
+                tungstenite::client(format!("ws://{}/", address), stream)
+                    .unwrap();
+            let message = match ws.read_message().unwrap() {
+                Message::Text(message) => message,
+                message => panic!("unexpected message {:?}", message),
+            };
+            ws.write_message(Message::Text(REPLY.to_string())).unwrap();
+            serde_json::from_str(&message).unwrap()
+        });
+    }
+}
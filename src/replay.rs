@@ -0,0 +1,566 @@
+//! Recording, playback and video export of matches.
+//!
+//! With `Settings.record_replay`, every action applied during the first game
+//! is written to a JSON `Replay`, along with the seed and the settings that
+//! affect the simulation. Recording forces a fixed timestep, see
+//! `Settings::lockstep`, so that playing the actions back re-simulates the
+//! same game. `export_video` does so headless and renders the game with the
+//! software renderer into an animated GIF or a sequence of PNGs, with a HUD
+//! showing the remaining time and the score of every player.
+
+use std::collections::VecDeque;
+use std::fs::File;
+
+use bevy::log;
+use bevy::prelude::*;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    act, raster, Controller, Fighter, GameSummary, Player, Players,
+    RemainingTime, Settings, Stats, FIGHTER_COLORS,
+};
+
+/// Glyphs of the HUD font, 3 pixels wide and 5 high, as rows of bits with the
+/// most significant bit on the left.
+const GLYPHS: [(char, [u8; 5]); 26] = [
+    ('0', [7, 5, 5, 5, 7]),
+    ('1', [2, 6, 2, 2, 7]),
+    ('2', [7, 1, 7, 4, 7]),
+    ('3', [7, 1, 7, 1, 7]),
+    ('4', [5, 5, 7, 1, 1]),
+    ('5', [7, 4, 7, 1, 7]),
+    ('6', [7, 4, 7, 5, 7]),
+    ('7', [7, 1, 1, 1, 1]),
+    ('8', [7, 5, 7, 5, 7]),
+    ('9', [7, 5, 7, 1, 7]),
+    (':', [0, 2, 0, 2, 0]),
+    ('.', [0, 0, 0, 0, 2]),
+    ('-', [0, 0, 7, 0, 0]),
+    ('/', [1, 1, 2, 4, 4]),
+    ('A', [2, 5, 7, 5, 5]),
+    ('C', [7, 4, 4, 4, 7]),
+    ('D', [6, 5, 5, 5, 6]),
+    ('E', [7, 4, 7, 4, 7]),
+    ('I', [7, 2, 2, 2, 7]),
+    ('K', [5, 5, 6, 5, 5]),
+    ('M', [5, 7, 7, 5, 5]),
+    ('O', [7, 5, 5, 5, 7]),
+    ('P', [7, 5, 7, 4, 4]),
+    ('R', [6, 5, 6, 5, 5]),
+    ('S', [7, 4, 7, 1, 7]),
+    ('T', [7, 2, 2, 2, 2]),
+];
+
+/// Recorded match that can be played back with `export_video`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub settings: ReplaySettings,
+    pub players: Vec<ReplayPlayer>,
+    /// Actions applied in each frame, in order of `timestep`.
+    pub frames: Vec<ReplayFrame>,
+}
+
+/// Subset of `Settings` that determines the course of a game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaySettings {
+    pub frameskip: u32,
+    pub frame_rate: f32,
+    pub action_interval: u32,
+    pub asteroid_count: u32,
+    pub continuous_collision_detection: bool,
+    pub respawn_time: u32,
+    pub opponent_stats_multiplier: f32,
+    pub max_game_length: u32,
+    pub difficulty_ramp: u32,
+    pub powerup_interval: u32,
+    pub max_powerups: u32,
+    pub powerup_lifetime: u32,
+    pub hull: f32,
+    pub opponent_hull: f32,
+    pub ram_damage_per_impulse: f32,
+    pub arena_size: Vec2,
+    /// Changes the scores, but not the course of the game.
+    #[serde(default)]
+    pub kill_rewards: bool,
+}
+
+impl ReplaySettings {
+    pub(crate) fn new(settings: &Settings) -> ReplaySettings {
+        ReplaySettings {
+            frameskip: settings.frameskip,
+            frame_rate: settings.frame_rate,
+            action_interval: settings.action_interval,
+            asteroid_count: settings.asteroid_count,
+            continuous_collision_detection: settings
+                .continuous_collision_detection,
+            respawn_time: settings.respawn_time,
+            opponent_stats_multiplier: settings.opponent_stats_multiplier,
+            max_game_length: settings.max_game_length,
+            difficulty_ramp: settings.difficulty_ramp,
+            powerup_interval: settings.powerup_interval,
+            max_powerups: settings.max_powerups,
+            powerup_lifetime: settings.powerup_lifetime,
+            hull: settings.hull,
+            opponent_hull: settings.opponent_hull,
+            ram_damage_per_impulse: settings.ram_damage_per_impulse,
+            arena_size: settings.arena_size,
+            kill_rewards: settings.kill_rewards,
+        }
+    }
+
+    fn apply(&self, settings: &mut Settings) {
+        settings.frameskip = self.frameskip;
+        settings.frame_rate = self.frame_rate;
+        settings.action_interval = self.action_interval;
+        settings.asteroid_count = self.asteroid_count;
+        settings.continuous_collision_detection =
+            self.continuous_collision_detection;
+        settings.respawn_time = self.respawn_time;
+        settings.opponent_stats_multiplier = self.opponent_stats_multiplier;
+        settings.max_game_length = self.max_game_length;
+        settings.difficulty_ramp = self.difficulty_ramp;
+        settings.powerup_interval = self.powerup_interval;
+        settings.max_powerups = self.max_powerups;
+        settings.powerup_lifetime = self.powerup_lifetime;
+        settings.hull = self.hull;
+        settings.opponent_hull = self.opponent_hull;
+        settings.ram_damage_per_impulse = self.ram_damage_per_impulse;
+        settings.arena_size = self.arena_size;
+        settings.kill_rewards = self.kill_rewards;
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayPlayer {
+    /// Controller spawning the same fighters as the recorded player's,
+    /// without choosing any actions.
+    pub controller: Controller,
+    pub act_interval: u32,
+}
+
+impl ReplayPlayer {
+    fn new(player: &Player, settings: &Settings) -> ReplayPlayer {
+        // Only humans and remote players have a fixed number of fighters
+        let controller = if !player.humans.is_empty() {
+            Controller::Human((0..player.humans.len()).collect())
+        } else if player.remote {
+            Controller::Human(vec![0])
+        } else {
+            Controller::Idle
+        };
+        ReplayPlayer {
+            controller,
+            act_interval: player.fighter_act_interval(settings),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// Value of `Stats.timesteps` when the actions were applied.
+    pub timestep: usize,
+    pub actions: Vec<ReplayAction<act::FighterAction>>,
+    #[serde(default)]
+    pub targets: Vec<ReplayAction<act::Target>>,
+    #[serde(default)]
+    pub continuous: Vec<ReplayAction<act::ContinuousAction>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayAction<A> {
+    pub player: usize,
+    /// Number of the fighter among those spawned by the player.
+    pub fighter: usize,
+    pub action: A,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Replay, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("failed to parse {}: {}", path, e))
+    }
+
+    fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json)
+            .map_err(|e| format!("failed to write {}: {}", path, e))
+    }
+
+    /// Headless app that plays back the replay and renders every frame
+    /// `width` pixels wide.
+    fn app(&self, width: u32) -> App {
+        let mut settings = Settings {
+            seed: self.seed,
+            headless: true,
+            players: self.players.len() as u32,
+            controllers: self
+                .players
+                .iter()
+                .map(|player| player.controller.clone())
+                .collect(),
+            software_render_width: Some(width),
+            ..default()
+        };
+        self.settings.apply(&mut settings);
+        let mut app = crate::app(settings, vec![]);
+        let mut players = app.world.non_send_resource_mut::<Players>();
+        for (player, recorded) in players.0.iter_mut().zip(&self.players) {
+            player.act_interval = Some(recorded.act_interval);
+        }
+        app.insert_resource(Playback {
+            frames: self.frames.iter().cloned().collect(),
+            finished: false,
+        })
+        .add_system(
+            play_actions
+                .before(crate::fighter_actions)
+                .before(crate::targeting::select_targets),
+        )
+        .add_system(finish_playback.after(crate::reset));
+        app
+    }
+}
+
+/// Actions recorded so far, until the first game ends.
+#[derive(Resource, Default)]
+pub(crate) struct Recorder {
+    frames: Vec<ReplayFrame>,
+    done: bool,
+}
+
+fn recorded<A: Copy + Send + Sync + 'static>(
+    events: &mut EventReader<(A, Entity)>,
+    fighters: &Query<&Fighter>,
+) -> Vec<ReplayAction<A>> {
+    events
+        .iter()
+        .filter_map(|(action, entity)| {
+            fighters.get(*entity).ok().map(|fighter| ReplayAction {
+                player: fighter.player_id,
+                fighter: fighter.number,
+                action: *action,
+            })
+        })
+        .collect()
+}
+
+/// Records the actions `fighter_actions` and the targets `select_targets`
+/// applied in this frame.
+pub(crate) fn record_actions(
+    stats: Res<Stats>,
+    mut recorder: ResMut<Recorder>,
+    mut action_events: EventReader<(act::FighterAction, Entity)>,
+    mut continuous_events: EventReader<(act::ContinuousAction, Entity)>,
+    mut target_events: EventReader<(act::Target, Entity)>,
+    fighters: Query<&Fighter>,
+) {
+    let actions = recorded(&mut action_events, &fighters);
+    let continuous = recorded(&mut continuous_events, &fighters);
+    let targets = recorded(&mut target_events, &fighters);
+    if recorder.done
+        || actions.is_empty() && continuous.is_empty() && targets.is_empty()
+    {
+        return;
+    }
+    recorder.frames.push(ReplayFrame {
+        timestep: stats.timesteps,
+        actions,
+        targets,
+        continuous,
+    });
+}
+
+/// Writes the replay once the first game is over.
+pub(crate) fn save_replay(
+    mut summaries: EventReader<GameSummary>,
+    mut recorder: ResMut<Recorder>,
+    settings: Res<Settings>,
+    players: NonSend<Players>,
+) {
+    if summaries.iter().next().is_none() || recorder.done {
+        return;
+    }
+    recorder.done = true;
+    let path = match &settings.record_replay {
+        Some(path) => path,
+        None => return,
+    };
+    let replay = Replay {
+        seed: settings.seed,
+        settings: ReplaySettings::new(&settings),
+        players: players
+            .0
+            .iter()
+            .map(|player| ReplayPlayer::new(player, &settings))
+            .collect(),
+        frames: std::mem::take(&mut recorder.frames),
+    };
+    match replay.save(path) {
+        Ok(()) => log::info!("Saved replay to {}", path),
+        Err(e) => log::error!("Failed to save replay: {}", e),
+    }
+}
+
+#[derive(Resource)]
+struct Playback {
+    frames: VecDeque<ReplayFrame>,
+    /// Set once the game is over.
+    finished: bool,
+}
+
+fn play_actions(
+    stats: Res<Stats>,
+    mut playback: ResMut<Playback>,
+    fighters: Query<(Entity, &Fighter)>,
+    mut action_events: EventWriter<(act::FighterAction, Entity)>,
+    mut continuous_events: EventWriter<(act::ContinuousAction, Entity)>,
+    mut target_events: EventWriter<(act::Target, Entity)>,
+) {
+    let find = |player: usize, number: usize| {
+        fighters
+            .iter()
+            .find(|(_, f)| f.player_id == player && f.number == number)
+            .map(|(entity, _)| entity)
+    };
+    while playback
+        .frames
+        .front()
+        .map_or(false, |frame| frame.timestep <= stats.timesteps)
+    {
+        let frame = playback.frames.pop_front().unwrap();
+        for recorded in frame.actions {
+            if let Some(entity) = find(recorded.player, recorded.fighter) {
+                action_events.send((recorded.action, entity));
+            }
+        }
+        for recorded in frame.continuous {
+            if let Some(entity) = find(recorded.player, recorded.fighter) {
+                continuous_events.send((recorded.action, entity));
+            }
+        }
+        for recorded in frame.targets {
+            if let Some(entity) = find(recorded.player, recorded.fighter) {
+                target_events.send((recorded.action, entity));
+            }
+        }
+    }
+}
+
+fn finish_playback(
+    mut summaries: EventReader<GameSummary>,
+    mut playback: ResMut<Playback>,
+) {
+    if summaries.iter().next().is_some() {
+        playback.finished = true;
+    }
+}
+
+/// Draws `text` with its top left corner at `(x, y)`, with every pixel of the
+/// font `scale` pixels wide.
+fn draw_text(
+    image: &mut RgbImage,
+    (x, y): (u32, u32),
+    text: &str,
+    scale: u32,
+    color: Rgb<u8>,
+) {
+    for (i, c) in text.chars().enumerate() {
+        let rows = match GLYPHS.iter().find(|(glyph, _)| *glyph == c) {
+            Some((_, rows)) => rows,
+            None => continue,
+        };
+        let left = x + i as u32 * 4 * scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (4 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (
+                            left + column * scale + dx,
+                            y + row as u32 * scale + dy,
+                        );
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn draw_hud(image: &mut RgbImage, world: &World) {
+    let settings = world.resource::<Settings>();
+    let stats = world.resource::<Stats>();
+    let remaining = world.resource::<RemainingTime>().0.max(0);
+    let seconds = (remaining as f32 / settings.frame_rate) as u32;
+    let scale = (image.width() / 400).max(1);
+    let line_height = 7 * scale;
+    draw_text(
+        image,
+        (2 * scale, 2 * scale),
+        &format!("TIME {}:{:02}", seconds / 60, seconds % 60),
+        scale,
+        Rgb([255, 255, 255]),
+    );
+    let player_count = world.non_send_resource::<Players>().0.len();
+    for i in 0..player_count {
+        let player = stats.player(i);
+        let [r, g, b, _] =
+            FIGHTER_COLORS[i % FIGHTER_COLORS.len()].as_rgba_f32();
+        draw_text(
+            image,
+            (2 * scale, 2 * scale + (i as u32 + 1) * line_height),
+            &format!(
+                "P{} SCORE {:.0} K/D/A {}/{}/{}",
+                i,
+                stats.score(i),
+                player.kills,
+                player.deaths,
+                player.assists,
+            ),
+            scale,
+            Rgb([r, g, b].map(|c| (c * 255.0) as u8)),
+        );
+    }
+}
+
+/// Options of `export_video`.
+pub struct VideoOptions {
+    /// GIF file if the path ends in `.gif`, otherwise a directory for PNGs.
+    pub output: String,
+    /// Width of the video in pixels.
+    pub width: u32,
+    /// Number of simulated frames per frame of video.
+    pub frame_interval: u32,
+}
+
+/// Re-simulates `replay` and writes it as a video, returning the number of
+/// frames written.
+pub fn export_video(
+    replay: &Replay,
+    options: &VideoOptions,
+) -> Result<usize, String> {
+    let interval = options.frame_interval.max(1);
+    let mut gif = if options.output.ends_with(".gif") {
+        let file = File::create(&options.output).map_err(|e| {
+            format!("failed to create {}: {}", options.output, e)
+        })?;
+        let mut encoder = GifEncoder::new(file);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        Some(encoder)
+    } else {
+        std::fs::create_dir_all(&options.output).map_err(|e| {
+            format!("failed to create {}: {}", options.output, e)
+        })?;
+        None
+    };
+    let delay = Delay::from_numer_denom_ms(
+        interval * replay.settings.frameskip * 1000,
+        replay.settings.frame_rate as u32,
+    );
+    let mut app = replay.app(options.width);
+    let mut written = 0;
+    for update in 0.. {
+        app.update();
+        // The last update already started the next game
+        if app.world.resource::<Playback>().finished {
+            break;
+        }
+        if update % interval != 0 {
+            continue;
+        }
+        let mut image = app.world.resource::<raster::Frame>().0.clone();
+        draw_hud(&mut image, &app.world);
+        match &mut gif {
+            Some(encoder) => encoder
+                .encode_frame(image::Frame::from_parts(
+                    DynamicImage::ImageRgb8(image).into_rgba8(),
+                    0,
+                    0,
+                    delay,
+                ))
+                .map_err(|e| e.to_string())?,
+            None => {
+                let path = std::path::Path::new(&options.output)
+                    .join(format!("{:06}.png", written));
+                image.save(&path).map_err(|e| {
+                    format!("failed to save {}: {}", path.display(), e)
+                })?;
+            }
+        }
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stats and scores of the last game that ended in `app`.
+    fn summary(app: &App, players: usize) -> (String, Vec<f32>) {
+        let events = app.world.resource::<Events<GameSummary>>();
+        let GameSummary(stats) = events
+            .get_reader()
+            .iter(events)
+            .last()
+            .expect("game didn't end");
+        assert!(stats.bullets_fired > 0);
+        (
+            serde_json::to_string(stats).unwrap(),
+            (0..players).map(|player| stats.score(player)).collect(),
+        )
+    }
+
+    #[test]
+    fn playback_reproduces_recorded_game() {
+        let path = std::env::temp_dir().join(format!(
+            "bevy-starfighter-replay-{}.json",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let mut app = crate::app(
+            Settings {
+                seed: 7,
+                headless: true,
+                players: 2,
+                controllers: vec![Controller::Scripted, Controller::Scripted],
+                max_game_length: 900,
+                kill_rewards: true,
+                record_replay: Some(path.to_string()),
+                ..default()
+            },
+            vec![],
+        );
+        for _ in 0..1000 {
+            app.update();
+            if app.world.resource::<Recorder>().done {
+                break;
+            }
+        }
+        let recorded = summary(&app, 2);
+
+        let replay = Replay::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(replay.settings.kill_rewards);
+        assert!(!replay.frames.is_empty());
+        let mut app = replay.app(32);
+        for _ in 0..1000 {
+            app.update();
+            if app.world.resource::<Playback>().finished {
+                break;
+            }
+        }
+        assert_eq!(summary(&app, 2), recorded);
+    }
+}
@@ -0,0 +1,360 @@
+//! Persistent leaderboard and match history.
+//!
+//! Records are stored as JSON through the `Storage` trait: in a file in the
+//! platform's data directory on native builds, in the browser's
+//! `localStorage` on the web, and in memory when neither is available.
+
+use std::collections::HashMap;
+use std::io;
+
+use bevy::log;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{GameSummary, HighscoreText, Players, Settings, Stats};
+
+const LEADERBOARD_KEY: &str = "leaderboard";
+const HISTORY_KEY: &str = "history";
+const LEADERBOARD_SIZE: usize = 10;
+const HISTORY_SIZE: usize = 50;
+
+/// Key-value store for persisted records.
+pub trait Storage {
+    fn load(&self, key: &str) -> io::Result<Option<String>>;
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()>;
+}
+
+/// Keeps records for the lifetime of the process only.
+#[derive(Default)]
+pub struct MemoryStorage(HashMap<String, String>);
+
+impl Storage for MemoryStorage {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        Ok(self.0.get(key).cloned())
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.0.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// Stores each key in a JSON file in `dir`.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileStorage {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStorage {
+    pub fn new(dir: std::path::PathBuf) -> io::Result<FileStorage> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(FileStorage { dir })
+    }
+
+    /// Storage in the platform's data directory.
+    pub fn data_dir() -> io::Result<FileStorage> {
+        let dir = dirs::data_dir().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no data directory")
+        })?;
+        FileStorage::new(dir.join("bevy-starfighter"))
+    }
+
+    fn path(&self, key: &str) -> std::path::PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Storage for FileStorage {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        match std::fs::read_to_string(self.path(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()> {
+        // Write to a temporary file first so a crash can't corrupt records
+        let path = self.path(key);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, value)?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+/// Stores each key in the browser's `localStorage`.
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorage(web_sys::Storage);
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(e: wasm_bindgen::JsValue) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{:?}", e))
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorage {
+    pub fn new() -> io::Result<LocalStorage> {
+        web_sys::window()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no window")
+            })?
+            .local_storage()
+            .map_err(js_error)?
+            .map(LocalStorage)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no localStorage")
+            })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Storage for LocalStorage {
+    fn load(&self, key: &str) -> io::Result<Option<String>> {
+        self.0
+            .get_item(&format!("bevy-starfighter.{}", key))
+            .map_err(js_error)
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> io::Result<()> {
+        self.0
+            .set_item(&format!("bevy-starfighter.{}", key), value)
+            .map_err(js_error)
+    }
+}
+
+/// Summary of a finished game.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct MatchRecord {
+    /// Controllers of all players, e.g. "human vs policy".
+    pub(crate) mode: String,
+    pub(crate) opponent_policy: Option<String>,
+    pub(crate) score: f32,
+    pub(crate) duration_secs: f32,
+    /// Seconds since the Unix epoch at the end of the game.
+    pub(crate) timestamp: u64,
+    pub(crate) stats: Stats,
+}
+
+/// Records loaded from and saved to a `Storage` backend.
+pub(crate) struct Records {
+    storage: Box<dyn Storage>,
+    /// Best games, highest score first.
+    pub(crate) leaderboard: Vec<MatchRecord>,
+    /// Most recent games, oldest first.
+    pub(crate) history: Vec<MatchRecord>,
+}
+
+fn load_json<T: for<'de> Deserialize<'de>>(
+    storage: &dyn Storage,
+    key: &str,
+) -> Vec<T> {
+    match storage.load(key) {
+        Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid {} records: {}", key, e);
+            vec![]
+        }),
+        Ok(None) => vec![],
+        Err(e) => {
+            log::warn!("Failed to load {} records: {}", key, e);
+            vec![]
+        }
+    }
+}
+
+impl Records {
+    pub(crate) fn load(storage: Box<dyn Storage>) -> Records {
+        Records {
+            leaderboard: load_json(&*storage, LEADERBOARD_KEY),
+            history: load_json(&*storage, HISTORY_KEY),
+            storage,
+        }
+    }
+
+    pub(crate) fn best_score(&self) -> Option<f32> {
+        self.leaderboard.first().map(|record| record.score)
+    }
+
+    /// Adds a finished game to the history and, if it scored high enough, to
+    /// the leaderboard, then saves both.
+    pub(crate) fn add(&mut self, record: MatchRecord) -> io::Result<()> {
+        let position = self
+            .leaderboard
+            .iter()
+            .position(|r| r.score < record.score)
+            .unwrap_or(self.leaderboard.len());
+        if position < LEADERBOARD_SIZE {
+            self.leaderboard.insert(position, record.clone());
+            self.leaderboard.truncate(LEADERBOARD_SIZE);
+        }
+        self.history.push(record);
+        if self.history.len() > HISTORY_SIZE {
+            self.history.remove(0);
+        }
+        self.storage.save(
+            LEADERBOARD_KEY,
+            &serde_json::to_string(&self.leaderboard)?,
+        )?;
+        self.storage
+            .save(HISTORY_KEY, &serde_json::to_string(&self.history)?)
+    }
+}
+
+fn default_storage() -> Box<dyn Storage> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let storage =
+        FileStorage::data_dir().map(|s| Box::new(s) as Box<dyn Storage>);
+    #[cfg(target_arch = "wasm32")]
+    let storage = LocalStorage::new().map(|s| Box::new(s) as Box<dyn Storage>);
+    storage.unwrap_or_else(|e| {
+        log::warn!("Records will not be persisted: {}", e);
+        Box::new(MemoryStorage::default())
+    })
+}
+
+fn timestamp() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
+
+/// Persists the result of every game and restores the best score.
+pub(crate) struct StoragePlugin;
+
+impl Plugin for StoragePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_non_send_resource(Records::load(default_storage()))
+            .add_startup_system_to_stage(
+                StartupStage::PostStartup,
+                restore_best_score,
+            )
+            .add_system(record_games.after(crate::reset));
+    }
+}
+
+fn restore_best_score(
+    records: NonSend<Records>,
+    mut highscore: Query<&mut HighscoreText>,
+) {
+    let best = records.best_score().unwrap_or(0.0).max(0.0) as u32;
+    for mut highscore in highscore.iter_mut() {
+        highscore.best = highscore.best.max(best);
+    }
+}
+
+fn record_games(
+    mut summaries: EventReader<GameSummary>,
+    mut records: NonSendMut<Records>,
+    players: NonSend<Players>,
+    settings: Res<Settings>,
+) {
+    for GameSummary(stats) in summaries.iter() {
+        let record = MatchRecord {
+            mode: players
+                .0
+                .iter()
+                .map(|player| player.kind())
+                .collect::<Vec<_>>()
+                .join(" vs "),
+            opponent_policy: players
+                .0
+                .iter()
+                .skip(1)
+                .find_map(|player| player.policy.clone()),
+            score: stats.player0_score(),
+            duration_secs: stats.timesteps as f32 / settings.frame_rate,
+            timestamp: timestamp(),
+            stats: stats.clone(),
+        };
+        if let Err(e) = records.add(record) {
+            log::warn!("Failed to save records: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(score: f32, timestamp: u64) -> MatchRecord {
+        MatchRecord {
+            mode: "human vs policy".to_string(),
+            opponent_policy: None,
+            score,
+            duration_secs: 60.0,
+            timestamp,
+            stats: Stats::default(),
+        }
+    }
+
+    fn scores(records: &[MatchRecord]) -> Vec<f32> {
+        records.iter().map(|record| record.score).collect()
+    }
+
+    #[test]
+    fn leaderboard_keeps_best_scores_in_order() {
+        let mut records = Records::load(Box::new(MemoryStorage::default()));
+        assert_eq!(records.best_score(), None);
+        let added =
+            [3.0, 7.0, 1.0, 7.0, 5.0, 9.0, 0.0, 2.0, 8.0, 4.0, 6.0, 10.0];
+        for (i, &score) in added.iter().enumerate() {
+            records.add(record(score, i as u64)).unwrap();
+        }
+        assert_eq!(
+            scores(&records.leaderboard),
+            [10.0, 9.0, 8.0, 7.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0]
+        );
+        // Earlier games rank first among equal scores
+        assert_eq!(records.leaderboard[3].timestamp, 1);
+        assert_eq!(records.leaderboard[4].timestamp, 3);
+        assert_eq!(records.best_score(), Some(10.0));
+        // Too low for the full leaderboard
+        records.add(record(1.0, 12)).unwrap();
+        assert_eq!(records.leaderboard.len(), LEADERBOARD_SIZE);
+        assert_eq!(records.leaderboard.last().unwrap().score, 2.0);
+    }
+
+    #[test]
+    fn history_drops_oldest_games() {
+        let mut records = Records::load(Box::new(MemoryStorage::default()));
+        for i in 0..HISTORY_SIZE + 5 {
+            records.add(record(i as f32, i as u64)).unwrap();
+        }
+        assert_eq!(records.history.len(), HISTORY_SIZE);
+        assert_eq!(records.history.first().unwrap().timestamp, 5);
+        assert_eq!(
+            records.history.last().unwrap().timestamp,
+            (HISTORY_SIZE + 4) as u64
+        );
+    }
+
+    #[test]
+    fn records_round_trip_through_storage() {
+        let mut records = Records::load(Box::new(MemoryStorage::default()));
+        for (i, score) in [4.0, 2.0, 6.0].into_iter().enumerate() {
+            records.add(record(score, i as u64)).unwrap();
+        }
+        let loaded = Records::load(records.storage);
+        assert_eq!(scores(&loaded.leaderboard), [6.0, 4.0, 2.0]);
+        assert_eq!(scores(&loaded.history), [4.0, 2.0, 6.0]);
+        assert_eq!(loaded.history[2].mode, "human vs policy");
+        assert_eq!(loaded.history[2].timestamp, 2);
+    }
+
+    #[test]
+    fn invalid_records_are_ignored() {
+        let mut storage = MemoryStorage::default();
+        storage.save(LEADERBOARD_KEY, "not json").unwrap();
+        let records = Records::load(Box::new(storage));
+        assert!(records.leaderboard.is_empty());
+        assert!(records.history.is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+//! Auto-aim assist driven by the `act::Target` entity-reference action.
+//!
+//! A `Target` action refers to an observed entity by the `id` that bots receive
+//! with it (see `entity::Identified`), the way entity-gym's select-entity
+//! actions do. Fighters with a target turn towards it whenever their pilot
+//! isn't turning, until the target is cleared or despawns. Bots select targets
+//! in their replies (see `remote`), and humans lock onto the enemy closest to
+//! their heading with the `target` binding, pressing it again to release the
+//! lock. In networked games, these presses are exchanged with the actions of
+//! each player and applied by `net`. Policies don't select targets: even with
+//! `Settings.observe_ids`, entity-gym-rs agents return one categorical choice
+//! per actor and `TrainEnvBuilder` only registers categorical action spaces,
+//! so there is no select-entity action head to train.
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::ids::EntityIds;
+use crate::{act, transform_to_direction, Fighter, Players, Settings};
+
+/// Angular velocity of auto-aim per radian off target.
+const AIM_GAIN: f32 = 10.0;
+
+/// Applies the targets selected this frame and drops those that despawned.
+pub(crate) fn select_targets(
+    mut target_events: EventReader<(act::Target, Entity)>,
+    ids: Res<EntityIds>,
+    mut fighters: Query<&mut Fighter>,
+) {
+    for (act::Target(target), id) in target_events.iter() {
+        if let Ok(mut fighter) = fighters.get_mut(*id) {
+            fighter.target = target.and_then(|target| ids.entity(target));
+        }
+    }
+    for mut fighter in fighters.iter_mut() {
+        if fighter.target.map_or(false, |target| ids.id(target) == 0) {
+            fighter.target = None;
+        }
+    }
+}
+
+pub(crate) fn auto_aim(
+    mut fighters: Query<(&Fighter, &Transform, &mut Velocity)>,
+    transforms: Query<&Transform>,
+) {
+    for (fighter, transform, mut velocity) in fighters.iter_mut() {
+        if fighter.is_turning {
+            continue;
+        }
+        let target = match fighter.target.and_then(|t| transforms.get(t).ok()) {
+            Some(target) => target,
+            None => continue,
+        };
+        let (direction_x, direction_y) = transform_to_direction(transform);
+        let offset = (target.translation - transform.translation).truncate();
+        let angle = Vec2::new(direction_x, direction_y).angle_between(offset);
+        if angle.is_finite() {
+            velocity.angvel = (angle * AIM_GAIN)
+                .clamp(-fighter.max_turn_speed, fighter.max_turn_speed);
+        }
+    }
+}
+
+/// Locks human fighters onto the enemy closest to their heading, or releases
+/// the lock, when the `target` binding is pressed.
+pub(crate) fn human_targets(
+    mut target_events: EventWriter<(act::Target, Entity)>,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    ids: Res<EntityIds>,
+    players: NonSend<Players>,
+    fighters: Query<(Entity, &Fighter, &Transform)>,
+) {
+    // Networked games exchange presses with the actions in `net::sync_inputs`
+    if settings.network.is_some() {
+        return;
+    }
+    for pilot in players.0.iter().flat_map(|p| &p.humans) {
+        let (fighter, bindings) =
+            match (pilot.fighter, settings.input.players.get(pilot.bindings)) {
+                (Some(fighter), Some(bindings)) => (fighter, bindings),
+                _ => continue,
+            };
+        if !keys.any_just_pressed(bindings.target.iter().copied()) {
+            continue;
+        }
+        if let Some(target) = toggled_target(fighter, &ids, &fighters) {
+            target_events.send((target, fighter));
+        }
+    }
+}
+
+/// Target selected by pressing the `target` binding of `fighter`, which
+/// releases its lock or locks onto the enemy closest to its heading.
+pub(crate) fn toggled_target(
+    fighter: Entity,
+    ids: &EntityIds,
+    fighters: &Query<(Entity, &Fighter, &Transform)>,
+) -> Option<act::Target> {
+    let (_, own, transform) = fighters.get(fighter).ok()?;
+    if own.target.is_some() {
+        return Some(act::Target(None));
+    }
+    let (direction_x, direction_y) = transform_to_direction(transform);
+    let direction = Vec2::new(direction_x, direction_y);
+    let off_heading = |target: &Transform| {
+        let offset = (target.translation - transform.translation).truncate();
+        direction.angle_between(offset).abs()
+    };
+    fighters
+        .iter()
+        .filter(|(_, f, _)| f.player_id != own.player_id)
+        .min_by(|(_, _, a), (_, _, b)| {
+            off_heading(a).total_cmp(&off_heading(b))
+        })
+        .map(|(entity, _, _)| ids.id(entity))
+        .filter(|id| *id != 0)
+        .map(|id| act::Target(Some(id)))
+}
@@ -0,0 +1,380 @@
+//! Weapons selected with `act::FighterAction.weapon`.
+//!
+//! Every projectile carries a `Bullet` with its lifetime, owner and damage,
+//! plus a component specific to the weapon that fired it. Projectiles are
+//! resolved like plain bullets in `detect_collisions`, except that
+//! - spread shot pellets deal half damage,
+//! - laser beams hit the first fighter or asteroid along a ray when fired and
+//!   pass through everything else,
+//! - homing missiles steer towards the nearest enemy fighter and are destroyed
+//!   by any enemy projectile,
+//! - proximity mines are stationary, arm after a delay and only detonate when
+//!   an enemy fighter comes within their trigger radius.
+
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::prelude::shape::{Circle, Quad};
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
+use bevy::sprite::MaterialMesh2dBundle;
+use bevy_rapier2d::prelude::*;
+
+use crate::ids::RecordSpawnOrder;
+use crate::{
+    act, transform_to_direction, Bullet, CollisionType, Fighter, Settings,
+    BULLET_COLORS,
+};
+
+/// Number of frames the trigger must be held to fire the laser.
+pub(crate) const LASER_CHARGE_TIME: u32 = 60;
+const LASER_RANGE: f32 = 1500.0;
+/// Number of frames the laser beam stays visible.
+const LASER_BEAM_LIFETIME: u32 = 6;
+const SPREAD_PELLETS: i32 = 5;
+/// Angle between adjacent spread shot pellets.
+const SPREAD_ANGLE: f32 = 0.12;
+/// Maximum change in direction of a missile per frame.
+pub(crate) const MISSILE_TURN_RATE: f32 = 0.05;
+const MINE_LIFETIME: u32 = 1800;
+pub(crate) const MINE_ARM_TIME: i32 = 60;
+pub(crate) const MINE_TRIGGER_RADIUS: f32 = 60.0;
+/// Radius of the mine itself, as opposed to its trigger.
+pub(crate) const MINE_RADIUS: f32 = 8.0;
+
+#[derive(Component)]
+pub(crate) struct Laser {
+    /// Entity hit by the beam, taken once the hit has been resolved.
+    pub(crate) target: Option<Entity>,
+    /// Length of the beam, which extends from the firing fighter.
+    pub(crate) length: f32,
+}
+
+#[derive(Component)]
+pub(crate) struct Missile {
+    speed: f32,
+}
+
+#[derive(Component)]
+pub(crate) struct Mine {
+    remaining_arm_time: i32,
+}
+
+/// Resources needed to fire weapons.
+#[derive(SystemParam)]
+pub(crate) struct Armory<'w, 's> {
+    cmd: Commands<'w, 's>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    rapier: Res<'w, RapierContext>,
+    collision_type: Query<'w, 's, &'static CollisionType>,
+    settings: Res<'w, Settings>,
+}
+
+impl<'w, 's> Armory<'w, 's> {
+    /// Fires `weapon` from `fighter` if it is ready, returns whether a shot
+    /// was fired.
+    pub(crate) fn fire(
+        &mut self,
+        weapon: act::Weapon,
+        entity: Entity,
+        fighter: &mut Fighter,
+        transform: &Transform,
+        velocity: Vec2,
+    ) -> bool {
+        if fighter.remaining_bullet_cooldown > 0 {
+            return false;
+        }
+        let (direction_x, direction_y) = transform_to_direction(transform);
+        let direction = Vec2::new(direction_x, direction_y);
+        let muzzle = transform.translation
+            + 24.0
+                * direction.extend(0.0)
+                * if fighter.player_id == 0 { -0.0 } else { 1.0 };
+        let player_id = fighter.player_id;
+        let mut cooldown_multiplier = match weapon {
+            act::Weapon::Gun => {
+                self.spawn_projectile(
+                    muzzle,
+                    velocity + direction * fighter.bullet_speed,
+                    fighter.bullet_lifetime,
+                    player_id,
+                    weapon,
+                    1.0,
+                );
+                1.0
+            }
+            act::Weapon::Spread => {
+                for i in -SPREAD_PELLETS / 2..=SPREAD_PELLETS / 2 {
+                    let direction = Vec2::from_angle(i as f32 * SPREAD_ANGLE)
+                        .rotate(direction);
+                    self.spawn_projectile(
+                        muzzle,
+                        velocity + direction * fighter.bullet_speed * 0.8,
+                        fighter.bullet_lifetime / 2,
+                        player_id,
+                        weapon,
+                        0.5,
+                    );
+                }
+                2.0
+            }
+            act::Weapon::Laser => {
+                fighter.laser_charge += fighter.act_interval;
+                if fighter.laser_charge < LASER_CHARGE_TIME {
+                    return false;
+                }
+                fighter.laser_charge = 0;
+                self.fire_laser(entity, player_id, transform, direction);
+                3.0
+            }
+            act::Weapon::Missile => {
+                let speed = fighter.bullet_speed * 0.4;
+                self.spawn_projectile(
+                    muzzle,
+                    velocity + direction * speed,
+                    fighter.bullet_lifetime * 3,
+                    player_id,
+                    weapon,
+                    3.0,
+                )
+                .insert(Missile { speed });
+                4.0
+            }
+            act::Weapon::Mine => {
+                self.lay_mine(
+                    transform.translation - 40.0 * direction.extend(0.0),
+                    player_id,
+                );
+                4.0
+            }
+        };
+        if fighter.rapid_fire_time > 0 {
+            cooldown_multiplier *= 0.5;
+        }
+        fighter.remaining_bullet_cooldown =
+            (fighter.bullet_cooldown as f32 * cooldown_multiplier) as i32;
+        true
+    }
+
+    fn spawn_projectile(
+        &mut self,
+        position: Vec3,
+        velocity: Vec2,
+        lifetime: u32,
+        player_id: usize,
+        weapon: act::Weapon,
+        damage: f32,
+    ) -> EntityCommands<'w, 's, '_> {
+        let (mesh, collider) = match weapon {
+            act::Weapon::Gun if player_id == 0 => (
+                self.meshes.add(create_projectile_mesh()).into(),
+                Collider::convex_hull(&[
+                    Vect::new(-8.0, 30.0),
+                    Vect::new(-8.0, -30.0),
+                    Vect::new(8.0, 0.0),
+                ])
+                .unwrap(),
+            ),
+            act::Weapon::Spread => (
+                self.meshes.add(Circle::new(2.0).into()).into(),
+                Collider::ball(2.0),
+            ),
+            act::Weapon::Missile => (
+                self.meshes
+                    .add(Quad::new(Vec2::new(24.0, 8.0)).into())
+                    .into(),
+                Collider::cuboid(12.0, 4.0),
+            ),
+            _ => (
+                self.meshes.add(Circle::new(3.0).into()).into(),
+                Collider::ball(3.0),
+            ),
+        };
+        let material = self.materials.add(ColorMaterial::from(
+            BULLET_COLORS[player_id % BULLET_COLORS.len()],
+        ));
+        let ccd = self.settings.ccd();
+        let mut projectile = self.cmd.spawn(Bullet {
+            remaining_lifetime: lifetime as i32,
+            player_id,
+            weapon,
+            damage,
+        });
+        projectile
+            .record_spawn_order()
+            .insert(RigidBody::Dynamic)
+            .insert(collider)
+            .insert(Velocity {
+                linvel: velocity,
+                angvel: 0.0,
+            })
+            .insert(LockedAxes::ROTATION_LOCKED)
+            .insert(CollisionType::Bullet)
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(ccd)
+            .insert(MaterialMesh2dBundle {
+                mesh,
+                transform: Transform::default()
+                    .with_scale(Vec3::splat(1.0))
+                    .with_rotation(Quat::from_rotation_z(
+                        Vec2::new(1.0, 0.0).angle_between(velocity),
+                    ))
+                    .with_translation(position),
+                material,
+                ..default()
+            });
+        projectile
+    }
+
+    /// Casts a ray from the fighter and spawns a beam up to the first fighter
+    /// or asteroid it hits, which is then damaged by `detect_collisions`.
+    fn fire_laser(
+        &mut self,
+        fighter: Entity,
+        player_id: usize,
+        transform: &Transform,
+        direction: Vec2,
+    ) {
+        let origin = transform.translation.truncate();
+        let collision_type = &self.collision_type;
+        let is_target = |entity| {
+            matches!(
+                collision_type.get(entity),
+                Ok(CollisionType::Fighter | CollisionType::Asteroid)
+            )
+        };
+        let filter = QueryFilter::new()
+            .exclude_collider(fighter)
+            .exclude_sensors()
+            .predicate(&is_target);
+        let hit =
+            self.rapier
+                .cast_ray(origin, direction, LASER_RANGE, true, filter);
+        let length = hit.map_or(LASER_RANGE, |(_, toi)| toi);
+        let mesh = self
+            .meshes
+            .add(Quad::new(Vec2::new(length, 4.0)).into())
+            .into();
+        let material = self.materials.add(ColorMaterial::from(
+            BULLET_COLORS[player_id % BULLET_COLORS.len()],
+        ));
+        self.cmd
+            .spawn(Bullet {
+                remaining_lifetime: LASER_BEAM_LIFETIME as i32,
+                player_id,
+                weapon: act::Weapon::Laser,
+                damage: 2.0,
+            })
+            .record_spawn_order()
+            .insert(Laser {
+                target: hit.map(|(entity, _)| entity),
+                length,
+            })
+            .insert(Velocity::zero())
+            .insert(CollisionType::Bullet)
+            .insert(MaterialMesh2dBundle {
+                mesh,
+                transform: Transform::default()
+                    .with_rotation(Quat::from_rotation_z(
+                        Vec2::new(1.0, 0.0).angle_between(direction),
+                    ))
+                    .with_translation(
+                        (origin + direction * length / 2.0).extend(0.5),
+                    ),
+                material,
+                ..default()
+            });
+    }
+
+    /// Spawns a mine, which gets its trigger collider once `arm_mines` arms it.
+    fn lay_mine(&mut self, position: Vec3, player_id: usize) {
+        let mesh = self.meshes.add(Circle::new(MINE_RADIUS).into()).into();
+        let material = self.materials.add(ColorMaterial::from(
+            BULLET_COLORS[player_id % BULLET_COLORS.len()],
+        ));
+        self.cmd
+            .spawn(Bullet {
+                remaining_lifetime: MINE_LIFETIME as i32,
+                player_id,
+                weapon: act::Weapon::Mine,
+                damage: 3.0,
+            })
+            .record_spawn_order()
+            .insert(Mine {
+                remaining_arm_time: MINE_ARM_TIME,
+            })
+            .insert(Velocity::zero())
+            .insert(CollisionType::Bullet)
+            .insert(MaterialMesh2dBundle {
+                mesh,
+                transform: Transform::default().with_translation(position),
+                material,
+                ..default()
+            });
+    }
+}
+
+fn create_projectile_mesh() -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        vec![[-8.0, 30.0, 0.0], [-8.0, -30.0, 0.0], [8.0, 0.0, 0.0]],
+    );
+    mesh.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+    mesh
+}
+
+pub(crate) fn steer_missiles(
+    settings: Res<Settings>,
+    mut missiles: Query<
+        (&Missile, &Bullet, &mut Transform, &mut Velocity),
+        Without<Fighter>,
+    >,
+    fighters: Query<(&Fighter, &Transform)>,
+) {
+    for (missile, bullet, mut transform, mut velocity) in missiles.iter_mut() {
+        let position = transform.translation.truncate();
+        let target = fighters
+            .iter()
+            .filter(|(f, _)| f.player_id != bullet.player_id)
+            .map(|(_, t)| t.translation.truncate() - position)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+        let heading = if velocity.linvel == Vec2::ZERO {
+            Vec2::X
+        } else {
+            velocity.linvel.normalize()
+        };
+        let heading = match target {
+            Some(offset) if offset != Vec2::ZERO => {
+                let max_turn = MISSILE_TURN_RATE * settings.frameskip as f32;
+                let turn =
+                    heading.angle_between(offset).clamp(-max_turn, max_turn);
+                Vec2::from_angle(turn).rotate(heading)
+            }
+            _ => heading,
+        };
+        velocity.linvel = heading * missile.speed;
+        transform.rotation =
+            Quat::from_rotation_z(Vec2::X.angle_between(heading));
+    }
+}
+
+pub(crate) fn arm_mines(
+    settings: Res<Settings>,
+    mut cmd: Commands,
+    mut mines: Query<(Entity, &mut Mine)>,
+) {
+    for (entity, mut mine) in mines.iter_mut() {
+        if mine.remaining_arm_time <= 0 {
+            continue;
+        }
+        mine.remaining_arm_time -= settings.frameskip as i32;
+        if mine.remaining_arm_time <= 0 {
+            cmd.entity(entity)
+                .insert(Collider::ball(MINE_TRIGGER_RADIUS))
+                .insert(Sensor)
+                .insert(ActiveEvents::COLLISION_EVENTS);
+        }
+    }
+}
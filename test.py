@@ -1,3 +1,4 @@
+import json
 import hyperstate
 from enn_trainer import TrainConfig, State, init_train_state, train, EnvConfig
 from entity_gym.env import VecEnv
@@ -5,25 +6,52 @@ from entity_gym_rs import RustVecEnv
 from ragged_buffer import RaggedBufferI64
 import numpy as np
 
-from bevy_starfighter import create_env, Config
+from bevy_starfighter import action_index, create_env, Config, Frames
+
+
+config = Config(render_width=400)
+
+
+class StarfighterVecEnv(RustVecEnv):
+    def __init__(self, env, frames: Frames, num_envs: int, first_env_index: int) -> None:
+        super().__init__(env)
+        self.frames = frames
+        self.env_indices = range(first_env_index, first_env_index + num_envs)
+
+    def render(self, **kwargs) -> np.ndarray:
+        images = []
+        for index in self.env_indices:
+            height, width, rgb = self.frames.render(index)
+            images.append(np.frombuffer(rgb, dtype=np.uint8).reshape(height, width, 3))
+        return np.stack(images)
 
 
 def create_starfighter_vec_env(
     cfg: EnvConfig, num_envs: int, num_processes: int, first_env_index: int
 ) -> VecEnv:
+    frames = Frames()
     env = create_env(
-        Config(),
+        config,
         num_envs,
         num_processes,
         first_env_index=first_env_index,
+        frames=frames,
     )
-    return RustVecEnv(env)  # type: ignore
+    return StarfighterVecEnv(env, frames, num_envs, first_env_index)  # type: ignore
+
+
+def fighter_action(**kwargs) -> RaggedBufferI64:
+    fields = {"thrust": "Off", "shoot": "Off", "turn": "None", **kwargs}
+    index = action_index(config, json.dumps(fields))
+    return RaggedBufferI64.from_flattened(np.array([[index]], dtype=np.int64), lengths=np.array([1], dtype=np.int64))
+
 
-turn_left = RaggedBufferI64.from_flattened(np.array([[1]], dtype=np.int64), lengths=np.array([1], dtype=np.int64))
-thrust = RaggedBufferI64.from_flattened(np.array([[2]], dtype=np.int64), lengths=np.array([1], dtype=np.int64))
-shoot = RaggedBufferI64.from_flattened(np.array([[3]], dtype=np.int64), lengths=np.array([1], dtype=np.int64))
+turn_left = fighter_action(turn="Left")
+thrust = fighter_action(thrust="On")
+shoot = fighter_action(shoot="On")
 
 env = create_starfighter_vec_env(EnvConfig(), 1, 1, 0)
 print(env.reset(None))
 for action in [turn_left, thrust, shoot, turn_left, thrust, thrust]:
-    print(env.act({"FighterAction": action}, None))
\ No newline at end of file
+    print(env.act({"FighterAction": action}, None))
+print(env.render().shape)
@@ -0,0 +1,37 @@
+#!/usr/bin/env bash
+# Plays a networked game between two headless native clients through a relay
+# on localhost, and checks that both clients simulated the same game.
+set -euo pipefail
+
+cargo build --release --bin relay --bin native-launcher
+dir=$(mktemp -d)
+trap 'kill $(jobs -p) 2>/dev/null; rm -rf "$dir"' EXIT
+
+target/release/relay --udp=127.0.0.1:4100 --websocket=127.0.0.1:4101 &
+sleep 1
+client=(
+    target/release/native-launcher --headless --ccd --seed=7
+    --relay=127.0.0.1:4100 --room=1
+)
+"${client[@]}" --controller=human:0 --controller=remote \
+    --metrics-log="$dir/0.jsonl" &
+"${client[@]}" --controller=remote --controller=human:0 \
+    --metrics-log="$dir/1.jsonl" &
+
+# Headless humans stay idle, so the first game ends once player 0 is hit
+for _ in $(seq 120); do
+    if [[ -s $dir/0.jsonl && -s $dir/1.jsonl ]]; then
+        break
+    fi
+    sleep 1
+done
+
+python3 - "$dir/0.jsonl" "$dir/1.jsonl" <<'PY'
+import json
+import sys
+
+games = [json.loads(open(path).readline()) for path in sys.argv[1:]]
+for key in ["seed", "settings_hash", "duration_secs", "scores", "stats"]:
+    assert games[0][key] == games[1][key], (key, games[0][key], games[1][key])
+print(f"Both clients played the same {games[0]['duration_secs']}s game")
+PY